@@ -0,0 +1,384 @@
+// Reference browser client for `vdom::remote::Session`.
+//
+// Renders what a session sends over a WebSocket into the page and sends back
+// the events that fire on its listeners. Messages are the JSON serde makes of
+// `ServerMessage` and `ClientMessage`, one per text frame. This mirrors
+// `remote_client::Client`; keep the two in step.
+//
+//     connect("ws://localhost:8080/session", document.getElementById("app"));
+
+(function (exports) {
+    "use strict";
+
+    var NAMESPACES = {
+        xlink: "http://www.w3.org/1999/xlink",
+        xml: "http://www.w3.org/XML/1998/namespace",
+        xmlns: "http://www.w3.org/2000/xmlns/"
+    };
+
+    // The fields of a DOM event the session gets to see, as `Value`s.
+    function defaultFields(event) {
+        var fields = {};
+        var target = event.target;
+        if (target && typeof target.value === "string") {
+            fields.value = { String: target.value };
+        }
+        if (target && typeof target.checked === "boolean") {
+            fields.checked = { Bool: target.checked };
+        }
+        if (typeof event.key === "string") {
+            fields.key = { String: event.key };
+        }
+        return fields;
+    }
+
+    function fromValue(value) {
+        if (value === "Null") {
+            return null;
+        }
+        if ("Bool" in value) {
+            return value.Bool;
+        }
+        if ("Number" in value) {
+            return value.Number;
+        }
+        return value.String;
+    }
+
+    function prefixNamespace(key) {
+        var end = key.indexOf(":");
+        return end < 0 ? undefined : NAMESPACES[key.slice(0, end)];
+    }
+
+    // `Attr::set` and `Attr::remove`, for an `AttrSnapshot`.
+    function setAttr(node, attr) {
+        var value = fromValue(attr.value);
+        if (attr.kind === "Property") {
+            node[attr.key] = value;
+            return;
+        }
+        var ns = prefixNamespace(attr.key);
+        if (ns) {
+            node.setAttributeNS(ns, attr.key, value);
+        } else {
+            node.setAttribute(attr.key, value);
+        }
+    }
+
+    function removeAttr(node, attr) {
+        if (attr.kind === "Property") {
+            delete node[attr.key];
+            return;
+        }
+        var ns = prefixNamespace(attr.key);
+        if (ns) {
+            node.removeAttributeNS(ns, attr.key.split(":").pop());
+        } else {
+            node.removeAttribute(attr.key);
+        }
+    }
+
+    function listenerKey(id) {
+        return id.event + ":" + id.id;
+    }
+
+    // The DOM nodes a tree puts into its parent, see `DomTree::nodes`: a
+    // fragment's node is the empty text node after its children.
+    function nodesOf(tree) {
+        if (!tree.fragment) {
+            return [tree.node];
+        }
+        var nodes = [];
+        tree.children.forEach(function (child) {
+            nodes.push.apply(nodes, nodesOf(child));
+        });
+        nodes.push(tree.node);
+        return nodes;
+    }
+
+    // Put the nodes of `tree` into `parent`, before `sibling` or at the end.
+    function place(parent, tree, sibling) {
+        nodesOf(tree).forEach(function (node) {
+            parent.insertBefore(node, sibling || null);
+        });
+    }
+
+    function Client(target, send, fields) {
+        // the DOM node of every rendered node, with its children and
+        // listeners, in the same shape as on the server
+        this.tree = { node: target, children: [], listeners: {} };
+        this.send = send;
+        this.fields = fields || defaultFields;
+        // set while waiting for the render asked for after a patch failed
+        this.resyncing = false;
+    }
+
+    // A patch that doesn't fit what was rendered means the client is out of
+    // step: the session is asked to render everything again, the error is
+    // rethrown and patches are ignored until the new render.
+    Client.prototype.handle = function (message) {
+        if ("Render" in message) {
+            this.replace(this.tree, null, this.createTree(message.Render));
+            this.resyncing = false;
+        } else if (!this.resyncing) {
+            try {
+                this.apply(message.Patch);
+            } catch (err) {
+                this.resyncing = true;
+                this.send("Resync");
+                throw err;
+            }
+        }
+    };
+
+    // Like `apply::apply`, every path is looked up before anything changes.
+    Client.prototype.apply = function (patches) {
+        var self = this;
+        var targets = patches.map(function (patch) {
+            // the node of the closest element on the way, see
+            // `DomTree::find_with_parent`
+            var tree = self.tree;
+            var parent = null;
+            patch.node.forEach(function (pos) {
+                if (tree && !tree.fragment) {
+                    parent = tree.node;
+                }
+                tree = tree && tree.children[pos];
+            });
+            if (!tree) {
+                throw new Error("No DOM node at " + JSON.stringify(patch.node) + " to patch");
+            }
+            return {
+                tree: tree,
+                node: tree.node,
+                parent: parent,
+                path: patch.node,
+                operation: patch.operation
+            };
+        });
+        targets.forEach(function (target) {
+            self.applyPatch(target.tree, target.node, target.parent, target.path, target.operation);
+        });
+    };
+
+    Client.prototype.applyPatch = function (tree, node, parent, path, operation) {
+        var self = this;
+        var kind = typeof operation === "string" ? operation : Object.keys(operation)[0];
+        var arg = operation[kind];
+        // where the children of the tree go, and what they go before if it is
+        // a fragment
+        var container = tree.fragment ? parent : node;
+        var anchor = tree.fragment ? node : null;
+        if (!container) {
+            throw new Error("Fragment without a parent at " + JSON.stringify(path));
+        }
+        switch (kind) {
+        case "ReplaceNode":
+            this.replace(tree, parent, this.createTree(arg));
+            break;
+        case "ReplaceText":
+            node.textContent = arg;
+            break;
+        case "ReplaceWithText":
+            var text = { node: document.createTextNode(arg), children: [], listeners: {} };
+            this.replace(tree, parent, text);
+            break;
+        case "ReplaceWithFragment":
+            this.replace(tree, parent, this.createFragment(arg));
+            break;
+        case "RemoveAttribute":
+            removeAttr(node, arg);
+            break;
+        case "SetAttribute":
+            setAttr(node, arg);
+            break;
+        case "SetStyle":
+            node.style.setProperty(arg[0], arg[1]);
+            break;
+        case "RemoveStyle":
+            node.style.removeProperty(arg);
+            break;
+        case "RemoveLast":
+            for (var i = 0; i < arg; i++) {
+                if (!tree.children.length) {
+                    throw new Error("RemoveLast without children at " + JSON.stringify(path));
+                }
+                nodesOf(tree.children.pop()).forEach(function (removed) {
+                    container.removeChild(removed);
+                });
+            }
+            break;
+        case "Append":
+            arg.forEach(function (child) {
+                var childTree = self.createChild(child);
+                place(container, childTree, anchor);
+                tree.children.push(childTree);
+            });
+            break;
+        case "Insert":
+            var inserted = this.createTree(arg[1]);
+            var sibling = tree.children[arg[0]];
+            place(container, inserted, sibling ? nodesOf(sibling)[0] : anchor);
+            tree.children.splice(Math.min(arg[0], tree.children.length), 0, inserted);
+            break;
+        case "Reorder":
+            this.reorder(tree, container, anchor, path, arg);
+            break;
+        case "AddListener":
+            this.listen(tree, arg);
+            break;
+        case "RemoveListener":
+            unlisten(tree, arg);
+            break;
+        default:
+            throw new Error("Unknown operation " + kind);
+        }
+    };
+
+    // Make `tree` describe `replacement`, like `DomTree::replace`, so that
+    // patches already resolved to it land on the new node.
+    Client.prototype.replace = function (tree, parent, replacement) {
+        if (!tree.fragment && !replacement.fragment) {
+            if (tree.node.parentNode) {
+                tree.node.parentNode.replaceChild(replacement.node, tree.node);
+            }
+        } else {
+            place(parent, replacement, nodesOf(tree)[0]);
+            nodesOf(tree).forEach(function (old) {
+                parent.removeChild(old);
+            });
+        }
+        tree.node = replacement.node;
+        tree.children = replacement.children;
+        tree.listeners = replacement.listeners;
+        tree.fragment = replacement.fragment;
+    };
+
+    // The children of a fragment are all put before its anchor again, the
+    // rest of a parent's children are only moved if they aren't in place.
+    Client.prototype.reorder = function (tree, parent, anchor, path, ops) {
+        var self = this;
+        var oldChildren = tree.children;
+        var newChildren = [];
+        var badReorder = function () {
+            return new Error("Reorder doesn't fit the children at " + JSON.stringify(path));
+        };
+        var oldChild = function (from) {
+            if (!oldChildren[from]) {
+                throw badReorder();
+            }
+            return oldChildren[from];
+        };
+        // every op puts at most one child in place, see `Client::reorder`
+        var checkTo = function (to) {
+            if (!(to >= 0 && to < ops.length)) {
+                throw badReorder();
+            }
+            return to;
+        };
+        ops.forEach(function (op) {
+            if ("Remove" in op) {
+                parent.removeChild(oldChild(op.Remove).node);
+            } else if ("Move" in op) {
+                newChildren[checkTo(op.Move[1])] = oldChild(op.Move[0]);
+            } else {
+                newChildren[checkTo(op.Insert[0])] = self.createTree(op.Insert[1]);
+            }
+        });
+        for (var pos = 0; pos < newChildren.length; pos++) {
+            if (!newChildren[pos]) {
+                throw badReorder();
+            }
+        }
+        // only move nodes that aren't already in place, see `apply::arrange`
+        newChildren.forEach(function (child, pos) {
+            var current = anchor || parent.childNodes[pos];
+            if (current !== child.node) {
+                parent.insertBefore(child.node, current || null);
+            }
+        });
+        tree.children = newChildren;
+    };
+
+    Client.prototype.createTree = function (snapshot) {
+        var self = this;
+        var node = snapshot.namespace
+            ? document.createElementNS(snapshot.namespace, snapshot.tag)
+            : document.createElement(snapshot.tag);
+        snapshot.attributes.forEach(function (attr) {
+            setAttr(node, attr);
+        });
+        snapshot.styles.forEach(function (style) {
+            node.style.setProperty(style[0], style[1]);
+        });
+        var tree = { node: node, children: [], listeners: {} };
+        snapshot.children.forEach(function (child) {
+            var childTree = self.createChild(child);
+            place(node, childTree, null);
+            tree.children.push(childTree);
+        });
+        snapshot.listeners.forEach(function (id) {
+            self.listen(tree, id);
+        });
+        return tree;
+    };
+
+    Client.prototype.createChild = function (child) {
+        if ("Text" in child) {
+            return { node: document.createTextNode(child.Text), children: [], listeners: {} };
+        }
+        if ("Fragment" in child) {
+            return this.createFragment(child.Fragment);
+        }
+        return this.createTree(child.Node);
+    };
+
+    Client.prototype.createFragment = function (children) {
+        var self = this;
+        return {
+            node: document.createTextNode(""),
+            children: children.map(function (child) {
+                return self.createChild(child);
+            }),
+            listeners: {},
+            fragment: true
+        };
+    };
+
+    Client.prototype.listen = function (tree, id) {
+        var self = this;
+        var handler = function (event) {
+            self.send({
+                Event: {
+                    listener: id,
+                    event: { event_type: id.event, fields: self.fields(event) }
+                }
+            });
+        };
+        tree.node.addEventListener(id.event, handler);
+        tree.listeners[listenerKey(id)] = handler;
+    };
+
+    function unlisten(tree, id) {
+        var key = listenerKey(id);
+        if (tree.listeners[key]) {
+            tree.node.removeEventListener(id.event, tree.listeners[key]);
+            delete tree.listeners[key];
+        }
+    }
+
+    // Render the session at `url` into `target`, which the first message
+    // replaces. `fields` picks what the session sees of an event.
+    exports.connect = function (url, target, fields) {
+        var socket = new WebSocket(url);
+        var client = new Client(target, function (message) {
+            socket.send(JSON.stringify(message));
+        }, fields);
+        socket.onmessage = function (frame) {
+            client.handle(JSON.parse(frame.data));
+        };
+        return socket;
+    };
+
+    exports.Client = Client;
+})(typeof module === "object" ? module.exports : (this.vdomRemote = {}));
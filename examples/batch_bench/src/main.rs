@@ -0,0 +1,216 @@
+//! Times the DOM updates `apply` makes for a table of rows, made one call
+//! into JavaScript per operation the way `apply_patch` used to make them,
+//! against encoding them into a `Batch` and making a single call
+//! (`StdwebBackend::apply_batch`).
+//!
+//! Run with `cargo web start --release` and open the page, the results are
+//! logged to the console. The workloads are creating the rows, changing the
+//! text of every tenth row, swapping two rows and removing them all. The
+//! batched times include encoding the batch.
+
+#[macro_use]
+extern crate stdweb;
+extern crate vdom;
+
+use stdweb::web::{document, INode, Node};
+use stdweb::unstable::TryInto;
+
+use vdom::attribute::Attr;
+use vdom::backend::Backend;
+use vdom::batch::{Batch, Slot};
+use vdom::StdwebBackend;
+
+const ROWS: usize = 1000;
+const ROUNDS: usize = 10;
+
+/// A way of making the DOM changes of each workload.
+trait Updates {
+    fn create_rows(&self, tbody: &Node, round: usize);
+    fn set_labels(&self, labels: &[Node]);
+    fn arrange(&self, tbody: &Node, rows: &[Node]);
+    fn remove_rows(&self, tbody: &Node, rows: &[Node]);
+}
+
+fn class(i: usize) -> Attr {
+    Attr::property("className", if i % 2 == 0 { "even" } else { "odd" })
+}
+
+fn label(i: usize, round: usize) -> String {
+    format!("row {} of round {}", i, round)
+}
+
+/// Every operation is its own call on the backend, as in `apply_patch`
+/// before patches were batched.
+struct PerOperation;
+
+impl Updates for PerOperation {
+    fn create_rows(&self, tbody: &Node, round: usize) {
+        let backend = &StdwebBackend;
+        for i in 0..ROWS {
+            let row = backend.create_element("tr");
+            class(i).set(backend, &row);
+            let id = backend.create_element("td");
+            let text = backend.create_text_node(&i.to_string());
+            backend.append_child(&id, &text);
+            backend.append_child(&row, &id);
+            let label_td = backend.create_element("td");
+            let text = backend.create_text_node(&label(i, round));
+            backend.append_child(&label_td, &text);
+            backend.append_child(&row, &label_td);
+            backend.append_child(tbody, &row);
+        }
+    }
+
+    fn set_labels(&self, labels: &[Node]) {
+        for label in labels {
+            StdwebBackend.set_text_content(label, "updated");
+        }
+    }
+
+    /// `apply::arrange`: the children are read once, only the rows that
+    /// aren't in place are moved.
+    fn arrange(&self, tbody: &Node, rows: &[Node]) {
+        let backend = &StdwebBackend;
+        let mut current = backend.child_nodes(tbody);
+        for (pos, row) in rows.iter().enumerate() {
+            if current.get(pos) == Some(row) {
+                continue;
+            }
+            match current.get(pos) {
+                Some(next) => backend.insert_before(tbody, row, next),
+                None => backend.append_child(tbody, row),
+            }
+            if let Some(from) = current.iter().skip(pos + 1).position(|node| node == row) {
+                current.remove(pos + 1 + from);
+            }
+            current.insert(pos, row.clone());
+        }
+    }
+
+    fn remove_rows(&self, tbody: &Node, rows: &[Node]) {
+        for row in rows {
+            StdwebBackend.remove_child(tbody, row);
+        }
+    }
+}
+
+/// The operations are encoded into a batch, which is applied with one call.
+struct Batched;
+
+impl Batched {
+    fn apply<F: FnOnce(&mut Batch<Node>)>(&self, encode: F) {
+        let mut batch = Batch::new();
+        encode(&mut batch);
+        StdwebBackend.apply_batch(&batch);
+    }
+}
+
+impl Updates for Batched {
+    fn create_rows(&self, tbody: &Node, round: usize) {
+        self.apply(|batch| {
+            let tbody = batch.input(tbody.clone());
+            for i in 0..ROWS {
+                let row = batch.create_element("tr");
+                batch.set_attr(row, &class(i));
+                let id = batch.create_element("td");
+                let text = batch.create_text_node(&i.to_string());
+                batch.append_child(id, text);
+                batch.append_child(row, id);
+                let label_td = batch.create_element("td");
+                let text = batch.create_text_node(&label(i, round));
+                batch.append_child(label_td, text);
+                batch.append_child(row, label_td);
+                batch.append_child(tbody, row);
+            }
+        })
+    }
+
+    fn set_labels(&self, labels: &[Node]) {
+        self.apply(|batch| for label in labels {
+            let label = batch.input(label.clone());
+            batch.set_text_content(label, "updated");
+        })
+    }
+
+    fn arrange(&self, tbody: &Node, rows: &[Node]) {
+        self.apply(|batch| {
+            let tbody = batch.input(tbody.clone());
+            let rows: Vec<Slot> = rows.iter().map(|row| batch.input(row.clone())).collect();
+            batch.arrange(tbody, &rows);
+        })
+    }
+
+    fn remove_rows(&self, tbody: &Node, rows: &[Node]) {
+        self.apply(|batch| {
+            let tbody = batch.input(tbody.clone());
+            for row in rows {
+                let row = batch.input(row.clone());
+                batch.remove_child(tbody, row);
+            }
+        })
+    }
+}
+
+fn now() -> f64 {
+    js!( return performance.now(); ).try_into().unwrap()
+}
+
+/// Add the time `run` takes to `total`.
+fn time<F: FnOnce()>(total: &mut f64, run: F) {
+    let start = now();
+    run();
+    *total += now() - start;
+}
+
+/// Run every workload `ROUNDS` times, returning the total time spent on each.
+fn bench<U: Updates>(updates: &U, tbody: &Node) -> [f64; 4] {
+    let mut totals = [0.0; 4];
+    for round in 0..ROUNDS {
+        time(&mut totals[0], || updates.create_rows(tbody, round));
+
+        let rows: Vec<Node> = tbody.child_nodes().iter().collect();
+        let labels: Vec<Node> = rows.iter()
+            .step_by(10)
+            .map(|row| row.last_child().unwrap().first_child().unwrap())
+            .collect();
+        time(&mut totals[1], || updates.set_labels(&labels));
+
+        let mut order = rows.clone();
+        order.swap(1, ROWS - 2);
+        time(&mut totals[2], || updates.arrange(tbody, &order));
+
+        time(&mut totals[3], || updates.remove_rows(tbody, &rows));
+    }
+    totals
+}
+
+fn main() {
+    stdweb::initialize();
+
+    let table = document().create_element("table");
+    let tbody = document().create_element("tbody");
+    table.append_child(&tbody);
+    document().body().unwrap().append_child(&table);
+    let tbody = tbody.as_node().clone();
+
+    // warm up both paths before measuring
+    bench(&PerOperation, &tbody);
+    bench(&Batched, &tbody);
+
+    let workloads = ["create", "update every 10th", "swap two", "remove all"];
+    let per_operation = bench(&PerOperation, &tbody);
+    let batched = bench(&Batched, &tbody);
+    for (i, name) in workloads.iter().enumerate() {
+        let (a, b) = (per_operation[i] / ROUNDS as f64, batched[i] / ROUNDS as f64);
+        let line = format!(
+            "{:<18} per operation {:>8.2}ms   batched {:>8.2}ms   {:>5.1}x",
+            name,
+            a,
+            b,
+            a / b
+        );
+        console!(log, line);
+    }
+
+    stdweb::event_loop();
+}
@@ -1,5 +1,5 @@
 use stdweb::web::event::ClickEvent;
-use vdom::{Component, Node, Callback};
+use vdom::{Cmd, Component, Node};
 
 #[derive(Debug, Default)]
 pub struct Counter(i32);
@@ -19,10 +19,11 @@ impl Component<CounterMsg> for Counter {
         ])
     }
 
-    fn update(&mut self, msg: CounterMsg, _recur: Callback<CounterMsg>) {
+    fn update(&mut self, msg: CounterMsg) -> Cmd<CounterMsg> {
         match msg {
             CounterMsg::Inc => self.0 += 1,
             CounterMsg::Dec => self.0 -= 1,
         }
+        Cmd::none()
     }
 }
\ No newline at end of file
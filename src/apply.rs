@@ -1,157 +1,552 @@
 use std::fmt::Debug;
-use stdweb::web::{Node as DNode, INode};
-use stdweb::unstable::TryInto;
 
-use super::diff::Patch;
-use super::root::create_element;
+use super::backend::{Backend, NodeTarget};
+use super::batch::{Batch, Slot};
+use super::diff::{Patch, Path, KeyedOp, Operation};
+use super::dom_tree::DomTree;
 use super::component::Callback;
-use super::dom_iterator::DomIterator;
+use super::node::{Child, Node as VNode};
 
-pub fn apply<'node, M>(dnode: &mut DNode, patches: Vec<Patch<'node, i32, M>>, send: Callback<M>)
-where
+/// The node a patch applies to: its entry in the retained tree, the DOM node
+/// that entry held when the patches were computed, and the DOM node that one
+/// is a child of, see `DomTree::find_with_parent`.
+struct Target<N> {
+    tree: DomTree<N>,
+    node: N,
+    parent: Option<N>,
+}
+
+/// Apply `patches` to `tree`, the DOM rendered for the root of the diff,
+/// keeping `tree` in step. The entry each patch is for is looked up by its
+/// path before any of them are applied, and the DOM is changed with a single
+/// `Backend::apply_batch`.
+pub fn apply<'node, B, M>(
+    backend: &B,
+    tree: &DomTree<B::Node>,
+    patches: Vec<Patch<'node, Path, M>>,
+    send: Callback<M>,
+) where
+    B: Backend,
     M: 'static + Debug,
 {
-    if patches.len() == 0 {
-        return;
+    let targets: Vec<Patch<'node, Target<B::Node>, M>> = patches
+        .into_iter()
+        .map(|patch| {
+            let (target, parent) = match tree.find_with_parent(&patch.node) {
+                Some(found) => found,
+                None => panic!("No DOM node at {:?} to patch", patch.node),
+            };
+            let node = target.node();
+            patch.at(Target {
+                tree: target,
+                node: node,
+                parent: parent,
+            })
+        })
+        .collect();
+
+    // update hooks run once everything else is patched, innermost first
+    let (updated, targets): (Vec<_>, Vec<_>) = targets.into_iter().partition(|patch| {
+        match patch.operation {
+            Operation::Updated(_) => true,
+            _ => false,
+        }
+    });
+
+    // every change to the DOM goes to the backend in one batch, what's left
+    // for Rust to do once the nodes exist is done in order afterwards
+    let mut batch = Batch::new();
+    let steps: Vec<Step<'node, B::Node, M>> = targets
+        .into_iter()
+        .map(|patch| encode_patch(&mut batch, patch))
+        .collect();
+    let created = if batch.is_empty() {
+        Vec::new()
+    } else {
+        backend.apply_batch(&batch)
+    };
+    let nodes = Nodes {
+        batch: &batch,
+        created: &created,
+    };
+
+    for step in steps.into_iter() {
+        step.finish(backend, &nodes, &send);
     }
+    for patch in updated.into_iter().rev() {
+        finish_patch(backend, patch, &send);
+    }
+}
 
-    let mut nodes = DomIterator::new(dnode.clone()).zip(0i32..);
-    let (mut current_node, mut node_index) = nodes.next().unwrap();
-    let mut with_nodes: Vec<Patch<'node, DNode, M>> = Vec::with_capacity(patches.len());
+/// Resolves the slots of a batch that has been applied.
+struct Nodes<'a, N: 'a> {
+    batch: &'a Batch<N>,
+    created: &'a [N],
+}
 
-    for patch in patches.into_iter() {
-        while node_index < patch.node {
-            match nodes.next() {
-                Some((next_node, next_index)) => {
-                    current_node = next_node;
-                    node_index = next_index;
-                }
-                None => panic!("Ran out of nodes before finishing all patches"),
-            };
+impl<'a, N: Clone> Nodes<'a, N> {
+    fn get(&self, slot: Slot) -> N {
+        self.batch.resolve(slot, self.created)
+    }
+}
+
+/// A node created by a batch, with what `create_tree` does besides creating
+/// the DOM left to do.
+struct Pending<'node, Msg: 'static + Debug> {
+    /// The anchor, for a fragment.
+    slot: Slot,
+    /// `None` for text and fragments.
+    vnode: Option<&'node VNode<Msg>>,
+    children: Vec<Pending<'node, Msg>>,
+    fragment: bool,
+}
+
+impl<'node, Msg: 'static + Debug> Pending<'node, Msg> {
+    /// The nodes to put into the parent, see `DomTree::nodes`.
+    fn slots(&self) -> Vec<Slot> {
+        if !self.fragment {
+            return vec![self.slot];
         }
-        with_nodes.push(patch.at(current_node.clone()));
+        let mut slots: Vec<Slot> = self.children.iter().flat_map(Pending::slots).collect();
+        slots.push(self.slot);
+        slots
     }
 
-    for patch in with_nodes.into_iter() {
-        apply_patch(patch, &send);
+    /// Install listeners, set the ref and run the create hook of the node
+    /// and its children, innermost first.
+    fn finish<B: Backend>(
+        self,
+        backend: &B,
+        nodes: &Nodes<B::Node>,
+        send: &Callback<Msg>,
+    ) -> DomTree<B::Node> {
+        let dnode = nodes.get(self.slot);
+        let children = self.children
+            .into_iter()
+            .map(|child| child.finish(backend, nodes, send))
+            .collect();
+        if let Some(vnode) = self.vnode {
+            for listener in vnode.listeners.iter() {
+                listener.install(&NodeTarget::new(backend, &dnode), send.clone());
+            }
+            if let Some(ref node_ref) = vnode.node_ref {
+                node_ref.set(dnode.clone());
+            }
+            if let Some(ref hook) = vnode.hooks.create {
+                hook.call(&dnode);
+            }
+        }
+        if self.fragment {
+            DomTree::fragment(dnode, children)
+        } else {
+            DomTree::new(dnode, children)
+        }
+    }
+}
+
+/// A child after a reorder.
+enum Reordered<'node, N, Msg: 'static + Debug> {
+    Kept(DomTree<N>),
+    Created(Pending<'node, Msg>),
+}
+
+/// What is left of a patch once its DOM operations are in the batch.
+enum Step<'node, N, Msg: 'static + Debug> {
+    /// Listeners, refs and hooks, which don't touch the DOM.
+    Patch(Patch<'node, Target<N>, Msg>),
+    Replace(DomTree<N>, Pending<'node, Msg>),
+    Append(DomTree<N>, Vec<Pending<'node, Msg>>),
+    Insert(DomTree<N>, usize, Pending<'node, Msg>),
+    Reorder(DomTree<N>, Vec<Reordered<'node, N, Msg>>),
+    Done,
+}
+
+impl<'node, N: Clone + PartialEq + 'static, Msg: 'static + Debug> Step<'node, N, Msg> {
+    fn finish<B>(self, backend: &B, nodes: &Nodes<N>, send: &Callback<Msg>)
+    where
+        B: Backend<Node = N>,
+    {
+        match self {
+            Step::Patch(patch) => finish_patch(backend, patch, send),
+            Step::Replace(tree, pending) => tree.replace(pending.finish(backend, nodes, send)),
+            Step::Append(tree, children) => {
+                for child in children.into_iter() {
+                    tree.push(child.finish(backend, nodes, send));
+                }
+            }
+            Step::Insert(tree, pos, pending) => {
+                let mut children = tree.children();
+                let pos = ::std::cmp::min(pos, children.len());
+                children.insert(pos, pending.finish(backend, nodes, send));
+                tree.set_children(children);
+            }
+            Step::Reorder(tree, children) => {
+                tree.set_children(
+                    children
+                        .into_iter()
+                        .map(|child| match child {
+                            Reordered::Kept(tree) => tree,
+                            Reordered::Created(pending) => pending.finish(backend, nodes, send),
+                        })
+                        .collect(),
+                );
+            }
+            Step::Done => {}
+        }
     }
 }
 
-fn apply_patch<'node, Msg>(patch: Patch<'node, DNode, Msg>, send: &Callback<Msg>)
+/// Write the DOM operations of `patch` to `batch`.
+fn encode_patch<'node, N, Msg>(
+    batch: &mut Batch<N>,
+    patch: Patch<'node, Target<N>, Msg>,
+) -> Step<'node, N, Msg>
 where
+    N: Clone + PartialEq + 'static,
     Msg: 'static + Debug,
 {
     use super::diff::Operation::*;
 
-	#[cfg(debug)]
-    js!(
-		if (window.DEBUG_VDOM && /apply_patch/.test(window.DEBUG_VDOM.toString())) {
-			console.log("Apply patch", @{&patch.node}, @{format!("{:?}", patch.operation)});
-			debugger;
-		}
-	);
-
+    let (tree, node, parent) = match patch.operation {
+        AddListener(_) | RemoveListener(_) | ReplaceListener(..) | SetRef(_) | ClearRef(_)
+        | Updated(_) | Removed(_) => return Step::Patch(patch),
+        _ => (patch.node.tree, patch.node.node, patch.node.parent),
+    };
+    let target = batch.input(node);
+    // where the children of the target go, and what they go before if it is
+    // a fragment
+    let (container, anchor) = if tree.is_fragment() {
+        (batch.input(parent.clone().expect("fragment without a parent")), Some(target))
+    } else {
+        (target, None)
+    };
     match patch.operation {
-        ReplaceNode(ref vnode) => {
-            let new_dnode = create_element(vnode, send);
-            patch.node.parent_node().unwrap().replace_child(
-                &new_dnode,
-                &patch.node,
-            );
-            //*dnode = new_dnode;
-        }
-        RemoveAttribute(ref attr) => {
-            attr.remove(&patch.node.try_into().unwrap());
-        }
-        SetAttribute(ref attr) => {
-            attr.set(&patch.node.try_into().unwrap());
-        }
-        ReplaceText(ref text) => {
-            patch.node.set_text_content(text);
-        }
-        RemoveLast(ref count) => {
-            js!(
-				var i = @{*count};
-				var domNode = @{patch.node.as_ref()};
-				while (i--) {
-					domNode.removeChild(domNode.lastChild);
-				}
-			);
-        }
-        Append(ref children) => {
-            use super::Child;
-            use stdweb::web::document;
-            let doc = document();
-            for child in children.iter() {
-                match child {
-                    &Child::Node(ref vnode) => {
-                        patch.node.append_child(&create_element(vnode, send));
-                    }
-                    &Child::Text(ref text) => {
-                        patch.node.append_child(&doc.create_text_node(text));
-                    }
+        ReplaceNode(vnode) => {
+            let pending = encode_tree(batch, vnode);
+            encode_replace(batch, &tree, target, parent, &pending);
+            Step::Replace(tree, pending)
+        }
+        RemoveAttribute(attr) => {
+            batch.remove_attr(target, attr);
+            Step::Done
+        }
+        SetAttribute(attr) => {
+            batch.set_attr(target, attr);
+            Step::Done
+        }
+        SetStyle(property, value) => {
+            batch.set_style(target, property, value);
+            Step::Done
+        }
+        RemoveStyle(property) => {
+            batch.remove_style(target, property);
+            Step::Done
+        }
+        ReplaceText(text) => {
+            batch.set_text_content(target, text);
+            Step::Done
+        }
+        ReplaceWithText(text) => {
+            let pending = Pending {
+                slot: batch.create_text_node(text),
+                vnode: None,
+                children: Vec::new(),
+                fragment: false,
+            };
+            encode_replace(batch, &tree, target, parent, &pending);
+            Step::Replace(tree, pending)
+        }
+        ReplaceWithFragment(children) => {
+            let pending = encode_fragment(batch, children);
+            encode_replace(batch, &tree, target, parent, &pending);
+            Step::Replace(tree, pending)
+        }
+        RemoveLast(count) => {
+            for _ in 0..count {
+                let last = tree.pop().expect("RemoveLast on a node without children");
+                for node in last.nodes() {
+                    let node = batch.input(node);
+                    batch.remove_child(container, node);
                 }
             }
+            Step::Done
         }
-        Insert(pos, ref vnode) => {
-            let new_child = create_element(vnode, send);
-            match patch
-                .node
-                .child_nodes()
-                .into_iter()
-                .skip(pos as usize)
-                .next() {
-                Some(sibling) => {
-                    patch.node.insert_before(&sibling, &new_child);
-                }
-                None => {
-                    patch.node.append_child(&new_child);
-                }
+        Append(children) => {
+            let children: Vec<_> = children
+                .iter()
+                .map(|child| {
+                    let pending = encode_child(batch, child);
+                    place(batch, container, &pending, anchor);
+                    pending
+                })
+                .collect();
+            Step::Append(tree, children)
+        }
+        Insert(pos, vnode) => {
+            let pending = encode_tree(batch, vnode);
+            let sibling = match tree.child(pos as usize) {
+                Some(sibling) => Some(batch.input(sibling.first_node())),
+                None => anchor,
+            };
+            place(batch, container, &pending, sibling);
+            Step::Insert(tree, pos as usize, pending)
+        }
+        Reorder(ref ops) => encode_reorder(batch, tree, container, anchor, ops),
+        AddListener(_) | RemoveListener(_) | ReplaceListener(..) | SetRef(_) | ClearRef(_)
+        | Updated(_) | Removed(_) => unreachable!(),
+    }
+}
+
+/// Put the nodes of `pending` into `parent`, before `sibling` or at the end.
+fn place<'node, N, Msg>(
+    batch: &mut Batch<N>,
+    parent: Slot,
+    pending: &Pending<'node, Msg>,
+    sibling: Option<Slot>,
+) where
+    N: Clone,
+    Msg: 'static + Debug,
+{
+    for slot in pending.slots() {
+        match sibling {
+            Some(sibling) => batch.insert_before(parent, slot, sibling),
+            None => batch.append_child(parent, slot),
+        }
+    }
+}
+
+/// Put the nodes of `pending` where those of `tree` are. Anything but a
+/// single node for a single node needs the `parent` they are in.
+fn encode_replace<'node, N, Msg>(
+    batch: &mut Batch<N>,
+    tree: &DomTree<N>,
+    target: Slot,
+    parent: Option<N>,
+    pending: &Pending<'node, Msg>,
+) where
+    N: Clone,
+    Msg: 'static + Debug,
+{
+    if !tree.is_fragment() && !pending.fragment {
+        return batch.replace(target, pending.slot);
+    }
+    let parent = batch.input(parent.expect("No parent to replace a fragment in"));
+    let first = batch.input(tree.first_node());
+    place(batch, parent, pending, Some(first));
+    for node in tree.nodes() {
+        let node = batch.input(node);
+        batch.remove_child(parent, node);
+    }
+}
+
+/// Reorder the children of `tree`. The children of a fragment are all put
+/// before its `anchor` again, `arrange` only knows about whole elements.
+fn encode_reorder<'node, N, Msg>(
+    batch: &mut Batch<N>,
+    tree: DomTree<N>,
+    parent: Slot,
+    anchor: Option<Slot>,
+    ops: &[KeyedOp<'node, Msg>],
+) -> Step<'node, N, Msg>
+where
+    N: Clone + PartialEq + 'static,
+    Msg: 'static + Debug,
+{
+    use super::diff::KeyedOp::*;
+
+    let old_children = tree.children();
+    let mut new_children: Vec<Option<(Slot, Reordered<'node, N, Msg>)>> =
+        Vec::with_capacity(old_children.len());
+
+    for op in ops.iter() {
+        match *op {
+            Remove(from) => {
+                let child = batch.input(old_children[from as usize].node());
+                batch.remove_child(parent, child);
             }
+            Move(from, to) => {
+                let child = old_children[from as usize].clone();
+                let slot = batch.input(child.node());
+                set_at(&mut new_children, to, (slot, Reordered::Kept(child)));
+            }
+            Insert(to, vnode) => {
+                let pending = encode_tree(batch, vnode);
+                set_at(&mut new_children, to, (pending.slot, Reordered::Created(pending)));
+            }
+        }
+    }
+    let (slots, new_children): (Vec<Slot>, Vec<_>) = new_children
+        .into_iter()
+        .map(|child| child.expect("Reorder left a hole in the child list"))
+        .unzip();
+
+    match anchor {
+        Some(anchor) => {
+            for slot in slots {
+                batch.insert_before(parent, slot, anchor);
+            }
+        }
+        None => batch.arrange(parent, &slots),
+    }
+    Step::Reorder(tree, new_children)
+}
+
+/// `create_tree`, as far as the DOM is concerned.
+fn encode_tree<'node, N, Msg>(batch: &mut Batch<N>, vnode: &'node VNode<Msg>) -> Pending<'node, Msg>
+where
+    N: Clone,
+    Msg: 'static + Debug,
+{
+    let slot = match vnode.namespace {
+        Some(namespace) => batch.create_element_ns(namespace, vnode.tag),
+        None => batch.create_element(vnode.tag),
+    };
+
+    for (_, attr) in vnode.attributes.iter() {
+        batch.set_attr(slot, attr);
+    }
+    for (property, value) in vnode.styles.iter() {
+        batch.set_style(slot, property, value);
+    }
+
+    let children = vnode
+        .children
+        .iter()
+        .map(|child| {
+            let pending = encode_child(batch, child);
+            place(batch, slot, &pending, None);
+            pending
+        })
+        .collect();
+
+    Pending {
+        slot: slot,
+        vnode: Some(vnode),
+        children: children,
+        fragment: false,
+    }
+}
+
+/// The children of a fragment and its anchor, not added to a parent yet.
+fn encode_fragment<'node, N, Msg>(
+    batch: &mut Batch<N>,
+    children: &'node [Child<Msg>],
+) -> Pending<'node, Msg>
+where
+    N: Clone,
+    Msg: 'static + Debug,
+{
+    let children = children.iter().map(|child| encode_child(batch, child)).collect();
+    Pending {
+        slot: batch.create_text_node(""),
+        vnode: None,
+        children: children,
+        fragment: true,
+    }
+}
+
+/// `create_child`, as far as the DOM is concerned.
+fn encode_child<'node, N, Msg>(
+    batch: &mut Batch<N>,
+    child: &'node Child<Msg>,
+) -> Pending<'node, Msg>
+where
+    N: Clone,
+    Msg: 'static + Debug,
+{
+    match *child {
+        Child::Text(ref text) => Pending {
+            slot: batch.create_text_node(text),
+            vnode: None,
+            children: Vec::new(),
+            fragment: false,
+        },
+        Child::Node(ref node) | Child::Keyed(_, ref node) => encode_tree(batch, node),
+        Child::Component(ref embedded) => {
+            let node = embedded.rendered().expect("embedded component was not resolved");
+            encode_tree(batch, node)
+        }
+        Child::Lazy(ref lazy) => {
+            let node = lazy.rendered().expect("lazy child was not resolved");
+            encode_tree(batch, node)
         }
+        Child::Fragment(ref fragment) => encode_fragment(batch, &fragment.children),
     }
 }
-/*
 
-////////////  APPLY FACTS  ////////////
+/// The patches that only touch Rust state.
+fn finish_patch<'node, B, Msg>(
+    backend: &B,
+    patch: Patch<'node, Target<B::Node>, Msg>,
+    send: &Callback<Msg>,
+) where
+    B: Backend,
+    Msg: 'static + Debug,
+{
+    use super::diff::Operation::*;
 
+    let node = patch.node.node;
+    match patch.operation {
+        AddListener(listener) => {
+            listener.install(&NodeTarget::new(backend, &node), send.clone());
+        }
+        RemoveListener(listener) => {
+            listener.remove();
+        }
+        ReplaceListener(old, new) => match old.take_installed() {
+            Some(installed) => new.adopt(installed, send.clone()),
+            None => new.install(&NodeTarget::new(backend, &node), send.clone()),
+        },
+        SetRef(node_ref) => {
+            node_ref.set(node);
+        }
+        ClearRef(node_ref) => {
+            node_ref.clear_if(&node);
+        }
+        Updated(hook) | Removed(hook) => {
+            hook.call(&node);
+        }
+        _ => unreachable!("{:?} changes the DOM", patch.operation),
+    }
+}
+
+pub fn replace<B: Backend>(backend: &B, old: &B::Node, new: &B::Node) {
+    if let Some(parent) = backend.parent_node(old) {
+        backend.replace_child(&parent, new, old);
+    }
+}
 
-function applyFacts(domNode, eventNode, facts)
+/// Put `children` into `parent` in order. Nodes that are already in place are
+/// not touched, so that untouched siblings (and whatever has focus) stay
+/// where they are.
+pub fn arrange<B, I>(backend: &B, parent: &B::Node, children: I)
+where
+    B: Backend,
+    I: IntoIterator<Item = B::Node>,
 {
-	for (var key in facts)
-	{
-		var value = facts[key];
-
-		switch (key)
-		{
-			case STYLE_KEY:
-				applyStyles(domNode, value);
-				break;
-
-			case EVENT_KEY:
-				applyEvents(domNode, eventNode, value);
-				break;
-
-			case ATTR_KEY:
-				applyAttrs(domNode, value);
-				break;
-
-			case ATTR_NS_KEY:
-				applyAttrsNS(domNode, value);
-				break;
-
-			case 'value':
-				if (domNode[key] !== value)
-				{
-					domNode[key] = value;
-				}
-				break;
-
-			default:
-				domNode[key] = value;
-				break;
-		}
-	}
-}
-
-*/
+    // the children of `parent` as they are after each move, read once
+    let mut current = backend.child_nodes(parent);
+    for (pos, child) in children.into_iter().enumerate() {
+        if current.get(pos) == Some(&child) {
+            continue;
+        }
+        match current.get(pos) {
+            Some(next) => backend.insert_before(parent, &child, next),
+            None => backend.append_child(parent, &child),
+        }
+        // everything before `pos` is in place already, so the child can only
+        // have come from further on
+        if let Some(from) = current.iter().skip(pos + 1).position(|node| *node == child) {
+            current.remove(pos + 1 + from);
+        }
+        current.insert(pos, child);
+    }
+}
+
+pub fn set_at<N>(nodes: &mut Vec<Option<N>>, pos: i32, node: N) {
+    let pos = pos as usize;
+    while nodes.len() <= pos {
+        nodes.push(None);
+    }
+    nodes[pos] = Some(node);
+}
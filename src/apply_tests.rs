@@ -0,0 +1,363 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::apply::apply;
+use super::component::Callback;
+use super::diff::diff;
+use super::mock::{MockBackend, MockNode};
+use super::test_support::{ignore, Click};
+use super::root::{create_element, create_tree};
+use super::backend::Backend;
+use super::{DomTree, Node};
+
+/// Render `old` into a mock document, patch it to `new` and check that the
+/// result looks exactly like `new` rendered from scratch.
+fn check(old: Node<&'static str>, new: Node<&'static str>) {
+    let backend = MockBackend;
+    let container = MockNode::element("body");
+    let tree = create_tree(&backend, &old, &ignore());
+    backend.append_child(&container, &tree.node());
+
+    apply(&backend, &tree, diff(&old, &new), ignore());
+
+    let patched = container.children();
+    assert_eq!(patched.len(), 1);
+    assert_eq!(
+        patched[0].snapshot(),
+        create_element(&backend, &new, &ignore()).snapshot()
+    );
+    assert_mirrors(&tree, &patched[0]);
+}
+
+/// Check that `tree` holds exactly the nodes found under `dnode`.
+fn assert_mirrors(tree: &DomTree<MockNode>, dnode: &MockNode) {
+    assert_eq!(tree.node(), *dnode);
+    let nodes: Vec<MockNode> = tree.children().iter().flat_map(DomTree::nodes).collect();
+    assert_eq!(nodes, dnode.children());
+    assert_children_mirror(tree);
+}
+
+fn assert_children_mirror(tree: &DomTree<MockNode>) {
+    for child in tree.children() {
+        if child.is_fragment() {
+            assert_children_mirror(&child);
+        } else {
+            assert_mirrors(&child, &child.node());
+        }
+    }
+}
+
+#[test]
+fn test_apply_text_and_attributes() {
+    use super::attribute::{class, href};
+
+    check(
+        vdom!(div { class = "a" } [ p [ text!("one") ] a { href = "/" } ]),
+        vdom!(div [ p [ text!("two") ] a { href = "/two"; class = "b" } ]),
+    );
+}
+
+#[test]
+fn test_apply_changed_root_tag() {
+    check(vdom!(a [ text!("link") ]), vdom!(b [ text!("bold") ]));
+}
+
+#[test]
+fn test_apply_append_and_remove_children() {
+    check(
+        vdom!(ul [ li [ text!("a") ] li [ text!("b") ] ]),
+        vdom!(ul [ li [ text!("a") ] li [ text!("b") ] li [ p [ text!("c") ] ] ]),
+    );
+    check(
+        vdom!(div [ ul [ li [ text!("a") ] li [ p [ text!("b") ] ] ] p [ text!("after") ] ]),
+        vdom!(div [ ul [ li [ text!("z") ] ] p [ text!("after!") ] ]),
+    );
+}
+
+#[test]
+fn test_apply_text_replaced_by_node() {
+    check(
+        vdom!(div [ text!("a") p [ text!("b") ] ]),
+        vdom!(div [ p [ text!("a") ] text!("b") ]),
+    );
+}
+
+#[test]
+fn test_apply_replaced_subtree_is_skipped() {
+    check(
+        vdom!(div [ ul [ li [ text!("a") ] ] p [ text!("b") ] span [ text!("c") ] ]),
+        vdom!(div [ ol [ li [ text!("a") ] ] text!("b") span [ text!("c!") ] ]),
+    );
+}
+
+#[test]
+fn test_apply_keyed_reorder() {
+    fn item(text: &'static str) -> Node<&'static str> {
+        vdom!(li [ text!(text) ])
+    }
+
+    let old = vdom!(div [
+        ul [
+            keyed!("a", item("a"))
+            keyed!("b", item("b"))
+            keyed!("c", item("c"))
+            keyed!("d", item("d"))
+        ]
+        p [ text!("after") ]
+    ]);
+    let new = vdom!(div [
+        ul [
+            keyed!("d", item("d!"))
+            keyed!("e", item("e"))
+            keyed!("b", item("b"))
+            keyed!("a", item("a!"))
+        ]
+        p [ text!("after!") ]
+    ]);
+
+    let backend = MockBackend;
+    let container = MockNode::element("body");
+    let tree = create_tree(&backend, &old, &ignore());
+    backend.append_child(&container, &tree.node());
+    let old_items = tree.node().children()[0].children();
+
+    apply(&backend, &tree, diff(&old, &new), ignore());
+
+    assert_eq!(tree.node().snapshot(), create_element(&backend, &new, &ignore()).snapshot());
+    assert_mirrors(&tree, &container.children()[0]);
+
+    // moved items are the same dom nodes they were before
+    let new_items = tree.node().children()[0].children();
+    assert_eq!(new_items[0], old_items[3]);
+    assert_eq!(new_items[2], old_items[1]);
+    assert_eq!(new_items[3], old_items[0]);
+}
+
+#[test]
+fn test_apply_replaces_listeners() {
+    let sent = Rc::new(RefCell::new(Vec::new()));
+    let send: Callback<&'static str> = {
+        let sent = sent.clone();
+        Rc::new(move |msg| sent.borrow_mut().push(msg))
+    };
+
+    let old: Node<&'static str> = vdom!(div [ button { on Click |_evt| "old" } ]);
+    let new: Node<&'static str> = vdom!(div [ button { on Click |_evt| "new" } ]);
+    let gone: Node<&'static str> = vdom!(div [ button [] ]);
+
+    let backend = MockBackend;
+    let container = MockNode::element("body");
+    let tree = create_tree(&backend, &old, &send);
+    backend.append_child(&container, &tree.node());
+    let button = tree.node().children()[0].clone();
+
+    button.dispatch(Click);
+    let installed = button.listener_ids();
+    apply(&backend, &tree, diff(&old, &new), send.clone());
+    // the DOM listener stays, only the closure it calls changes
+    assert_eq!(button.listener_ids(), installed);
+    button.dispatch(Click);
+    apply(&backend, &tree, diff(&new, &gone), send.clone());
+    assert!(button.listener_ids().is_empty());
+    button.dispatch(Click);
+
+    assert_eq!(*sent.borrow(), vec!["old", "new"]);
+}
+
+#[test]
+fn test_apply_mapped_listeners() {
+    #[derive(Debug, PartialEq)]
+    enum Parent {
+        Child(&'static str),
+    }
+
+    fn child(label: &'static str) -> Node<&'static str> {
+        vdom!(button { on Click |_evt| label })
+    }
+
+    let sent = Rc::new(RefCell::new(Vec::new()));
+    let send: Callback<Parent> = {
+        let sent = sent.clone();
+        Rc::new(move |msg| sent.borrow_mut().push(msg))
+    };
+
+    let old: Node<Parent> = vdom!(div [ (child("old").map(Parent::Child)) ]);
+    let new: Node<Parent> = vdom!(div [ (child("new").map(Parent::Child)) ]);
+
+    let backend = MockBackend;
+    let container = MockNode::element("body");
+    let tree = create_tree(&backend, &old, &send);
+    backend.append_child(&container, &tree.node());
+    let button = tree.node().children()[0].clone();
+
+    button.dispatch(Click);
+    apply(&backend, &tree, diff(&old, &new), send.clone());
+    button.dispatch(Click);
+
+    assert_eq!(*sent.borrow(), vec![Parent::Child("old"), Parent::Child("new")]);
+}
+
+#[test]
+fn test_svg_namespace_is_inherited() {
+    use super::attribute::{r, view_box, xlink_href, Attribute, XLINK_NAMESPACE};
+    use super::node::SVG_NAMESPACE;
+
+    let vnode: Node<&'static str> = vdom!(div [
+        svg { view_box = "0 0 10 10" } [
+            g [ circle { r = 2 } image { xlink_href = "#dot" } ]
+            foreignObject [ p [ text!("html") ] ]
+        ]
+    ]);
+    let dnode = create_element(&MockBackend, &vnode, &ignore());
+    let svg = dnode.children()[0].clone();
+    let g = svg.children()[0].clone();
+    let foreign = svg.children()[1].clone();
+
+    assert_eq!(dnode.namespace(), None);
+    for node in vec![&svg, &g, &g.children()[0], &g.children()[1], &foreign] {
+        assert_eq!(node.namespace(), Some(SVG_NAMESPACE.to_owned()));
+    }
+    assert_eq!(foreign.children()[0].namespace(), None);
+
+    assert_eq!(xlink_href("#dot").into_attr().namespace(), Some(XLINK_NAMESPACE));
+    assert_eq!(g.children()[1].attribute("xlink:href"), Some("#dot".into()));
+}
+
+#[test]
+fn test_explicit_namespace() {
+    use super::node::SVG_NAMESPACE;
+
+    let vnode: Node<&'static str> = vdom!(g { xmlns = SVG_NAMESPACE } [ rect ]);
+    let dnode = create_element(&MockBackend, &vnode, &ignore());
+    assert_eq!(dnode.namespace(), Some(SVG_NAMESPACE.to_owned()));
+    assert_eq!(dnode.children()[0].namespace(), Some(SVG_NAMESPACE.to_owned()));
+
+    // same tag in another namespace has to be a new element
+    check(
+        vdom!(div [ a [ text!("html") ] ]),
+        vdom!(div [ a { xmlns = SVG_NAMESPACE } [ text!("svg") ] ]),
+    );
+    check(vdom!(div [ p ]), vdom!(div [ svg [ circle ] p ]));
+}
+
+#[test]
+fn test_apply_styles() {
+    check(
+        vdom!(div { style = vec![("color", "red"), ("margin", "0")] } [ p ]),
+        vdom!(div { style = vec![("color", "blue")] } [ p { style = vec![("--gap", "2px")] } ]),
+    );
+
+    let vnode: Node<&'static str> = vdom!(div { style = vec![("margin-top", "1em")] });
+    let dnode = create_element(&MockBackend, &vnode, &ignore());
+    assert_eq!(dnode.style("margin-top"), Some("1em".to_owned()));
+}
+
+#[test]
+fn test_apply_fragments() {
+    use super::node::Fragment;
+
+    fn items(names: &[&'static str]) -> Fragment<&'static str> {
+        names.iter().map(|name| vdom!(li [ text!(*name) ])).collect()
+    }
+
+    // the children of a fragment are followed by an empty text node
+    let vnode: Node<&'static str> = vdom!(ul [ li [ text!("first") ] fragment!(items(&["a", "b"])) ]);
+    let dnode = create_element(&MockBackend, &vnode, &ignore());
+    assert_eq!(dnode.children().len(), 4);
+    assert_eq!(dnode.text_content(), "firstab");
+
+    check(
+        vdom!(div [ ul [ fragment!(items(&["a", "b"])) li [ text!("last") ] ] p [ text!("after") ] ]),
+        vdom!(div [ ul [ fragment!(items(&["a"])) li [ text!("last") ] ] p [ text!("after!") ] ]),
+    );
+    check(
+        vdom!(ul [ fragment!(items(&[])) li [ text!("last") ] ]),
+        vdom!(ul [ fragment!(items(&["a", "b"])) li [ text!("last") ] ]),
+    );
+
+    fn keyed(names: &[&'static str]) -> Fragment<&'static str> {
+        let mut fragment = Fragment::new();
+        for name in names {
+            fragment.append_keyed(*name, vdom!(li [ text!(*name) ]));
+        }
+        fragment
+    }
+    check(
+        vdom!(ul [ fragment!(keyed(&["a", "b"])) ]),
+        vdom!(ul [ fragment!(keyed(&["b", "c", "a"]).map(|msg| msg)) ]),
+    );
+    check(
+        vdom!(ul [ li [ text!("first") ] fragment!(keyed(&["a", "b", "c"])) li [ text!("last") ] ]),
+        vdom!(ul [ li [ text!("first") ] fragment!(keyed(&["c", "a"])) li [ text!("last") ] ]),
+    );
+}
+
+#[test]
+fn test_apply_fragment_keeps_its_siblings() {
+    use super::node::Fragment;
+
+    fn items(names: &[&'static str]) -> Fragment<&'static str> {
+        names.iter().map(|name| vdom!(li [ text!(*name) ])).collect()
+    }
+
+    let old: Node<&'static str> = vdom!(ul [ fragment!(items(&["a", "b"])) li [ text!("last") ] ]);
+    let new: Node<&'static str> = vdom!(ul [ fragment!(items(&["a"])) li [ text!("last") ] ]);
+    let tree = create_tree(&MockBackend, &old, &ignore());
+    let last = tree.node().children()[3].clone();
+
+    let patches = diff(&old, &new);
+    assert_eq!(patches.len(), 1);
+    assert_eq!(patches[0].node, vec![0]);
+    apply(&MockBackend, &tree, patches, ignore());
+    assert_eq!(tree.node().children()[2], last);
+    assert_eq!(tree.node().text_content(), "alast");
+}
+
+#[test]
+fn test_apply_replaces_fragments() {
+    use super::node::Fragment;
+
+    fn items(names: &[&'static str]) -> Fragment<&'static str> {
+        names.iter().map(|name| vdom!(li [ text!(*name) ])).collect()
+    }
+    fn nested() -> Fragment<&'static str> {
+        let mut fragment = items(&["a"]);
+        fragment.append_fragment(items(&["b", "c"]));
+        fragment.append_string("d".to_owned());
+        fragment
+    }
+
+    check(
+        vdom!(ul [ li [ text!("first") ] fragment!(items(&["a", "b"])) li [ text!("last") ] ]),
+        vdom!(ul [ li [ text!("first") ] li [ text!("middle") ] li [ text!("last") ] ]),
+    );
+    check(
+        vdom!(ul [ li [ text!("first") ] li [ text!("middle") ] li [ text!("last") ] ]),
+        vdom!(ul [ li [ text!("first") ] fragment!(items(&["a", "b"])) li [ text!("last") ] ]),
+    );
+    check(
+        vdom!(ul [ text!("text") fragment!(nested()) ]),
+        vdom!(ul [ fragment!(nested()) text!("text") ]),
+    );
+    check(
+        vdom!(ul [ fragment!(nested()) ]),
+        vdom!(ul [ fragment!(items(&["a", "b"])) ]),
+    );
+}
+
+#[test]
+fn test_apply_ignores_foreign_descendants() {
+    let old: Node<&'static str> = vdom!(div [ section [ p ] ul [ li [ text!("a") ] ] ]);
+    let new: Node<&'static str> = vdom!(div [ section [ p ] ul [ li [ text!("b") ] ] ]);
+    let container = MockNode::element("body");
+    let tree = create_tree(&MockBackend, &old, &ignore());
+    MockBackend.append_child(&container, &tree.node());
+
+    // e.g. a widget rendering into the `p` it was handed
+    let p = tree.node().children()[0].children()[0].clone();
+    MockBackend.append_child(&p, &MockNode::element("canvas"));
+
+    apply(&MockBackend, &tree, diff(&old, &new), ignore());
+    assert_eq!(tree.node().children()[1].text_content(), "b");
+    assert_eq!(p.children().len(), 1);
+}
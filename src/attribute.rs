@@ -1,15 +1,307 @@
 //! Strongly-typed abstractions for element attributes.
 
-use stdweb::web::Element;
-use stdweb::Value;
+use std::borrow::Cow;
 
+use super::backend::Backend;
+
+pub const XLINK_NAMESPACE: &'static str = "http://www.w3.org/1999/xlink";
+pub const XML_NAMESPACE: &'static str = "http://www.w3.org/XML/1998/namespace";
+pub const XMLNS_NAMESPACE: &'static str = "http://www.w3.org/2000/xmlns/";
+
+/// The value of an attribute or property.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl<'a> From<&'a str> for Value {
+    fn from(s: &'a str) -> Self {
+        Value::String(s.to_owned())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(v: Option<T>) -> Self {
+        v.map(Into::into).unwrap_or(Value::Null)
+    }
+}
+
+macro_rules! number_values {
+    ($($ty:ty),*) => {
+        $(impl From<$ty> for Value {
+            fn from(n: $ty) -> Self {
+                Value::Number(n as f64)
+            }
+        })*
+    }
+}
+
+number_values!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, f32, f64);
+
+/// Anything that can be added to a `Node` as an attribute or property: the
+/// typed values returned by the factories in this module, or a plain `Attr`.
 pub trait Attribute {
-    fn key(&self) -> &'static str;
-    fn set(&self, element: &Element);
+    fn key(&self) -> &str;
+    fn into_attr(self) -> Attr;
+}
+
+impl Attribute for Attr {
+    fn key(&self) -> &str {
+        &self.key
+    }
+
+    fn into_attr(self) -> Attr {
+        self
+    }
+}
+
+/// An attribute or property whose value has to be a `T`. The factories below
+/// only accept values that convert into the right `T`, so `checked = "yes"`
+/// doesn't compile.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TypedAttr<T> {
+    kind: AttrKind,
+    key: &'static str,
+    value: T,
+}
+
+impl<T: Into<Value>> TypedAttr<T> {
+    pub fn property(key: &'static str, value: T) -> Self {
+        TypedAttr {
+            kind: AttrKind::Property,
+            key: key,
+            value: value,
+        }
+    }
+
+    pub fn attribute(key: &'static str, value: T) -> Self {
+        TypedAttr {
+            kind: AttrKind::Attribute,
+            key: key,
+            value: value,
+        }
+    }
+
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T: Into<Value>> Attribute for TypedAttr<T> {
+    fn key(&self) -> &str {
+        self.key
+    }
+
+    fn into_attr(self) -> Attr {
+        Attr {
+            kind: self.kind,
+            key: Cow::Borrowed(self.key),
+            value: self.value.into(),
+        }
+    }
 }
 
+/// A boolean for enumerated attributes that have to be spelled out as
+/// `"true"` or `"false"`, such as `draggable` and most `aria-*` states.
 #[derive(Debug, PartialEq, Clone, Copy)]
-enum AttrKind {
+pub struct TrueFalse(pub bool);
+
+impl From<bool> for TrueFalse {
+    fn from(b: bool) -> Self {
+        TrueFalse(b)
+    }
+}
+
+impl From<TrueFalse> for Value {
+    fn from(b: TrueFalse) -> Self {
+        Value::String(if b.0 { "true" } else { "false" }.to_owned())
+    }
+}
+
+/// A length for `width`, `height` and the SVG geometry attributes: a plain
+/// number, or a string such as `"50%"` or `"2em"`.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Length {
+    Number(f64),
+    Percent(f64),
+    /// Any other length, e.g. with a CSS unit, written as is.
+    Other(String),
+}
+
+impl<'a> From<&'a str> for Length {
+    fn from(s: &'a str) -> Self {
+        let number = |s: &str| s.trim().parse::<f64>().ok();
+        if s.ends_with('%') {
+            if let Some(n) = number(&s[..s.len() - 1]) {
+                return Length::Percent(n);
+            }
+        } else if let Some(n) = number(s) {
+            return Length::Number(n);
+        }
+        Length::Other(s.to_owned())
+    }
+}
+
+impl From<String> for Length {
+    fn from(s: String) -> Self {
+        Length::from(&s[..])
+    }
+}
+
+impl From<Length> for Value {
+    fn from(length: Length) -> Self {
+        match length {
+            Length::Number(n) => Value::Number(n),
+            Length::Percent(n) => Value::String(format!("{}%", n)),
+            Length::Other(s) => Value::String(s),
+        }
+    }
+}
+
+macro_rules! number_lengths {
+    ($($ty:ty),*) => {
+        $(impl From<$ty> for Length {
+            fn from(n: $ty) -> Self {
+                Length::Number(n as f64)
+            }
+        })*
+    }
+}
+
+number_lengths!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize, f32, f64);
+
+/// Declares an enum of keyword values, each converting to the given string.
+macro_rules! keywords {
+    ($(#[$meta:meta])* pub enum $name:ident { $($variant:ident => $keyword:expr),* }) => {
+        $(#[$meta])*
+        #[derive(Debug, PartialEq, Clone, Copy)]
+        pub enum $name {
+            $($variant),*
+        }
+
+        impl $name {
+            pub fn as_str(&self) -> &'static str {
+                match *self {
+                    $($name::$variant => $keyword),*
+                }
+            }
+        }
+
+        impl From<$name> for Value {
+            fn from(keyword: $name) -> Self {
+                Value::String(keyword.as_str().to_owned())
+            }
+        }
+    }
+}
+
+keywords! {
+    /// The `type` of an `input` (or `button`).
+    pub enum InputType {
+        Button => "button",
+        Checkbox => "checkbox",
+        Color => "color",
+        Date => "date",
+        DatetimeLocal => "datetime-local",
+        Email => "email",
+        File => "file",
+        Hidden => "hidden",
+        Image => "image",
+        Month => "month",
+        Number => "number",
+        Password => "password",
+        Radio => "radio",
+        Range => "range",
+        Reset => "reset",
+        Search => "search",
+        Submit => "submit",
+        Tel => "tel",
+        Text => "text",
+        Time => "time",
+        Url => "url",
+        Week => "week"
+    }
+}
+
+/// The value of `type`: an `InputType` for `input` and `button`, or any other
+/// value written as is, e.g. a MIME type for `script` or `"a"` for an `ol`.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Type {
+    Input(InputType),
+    Other(String),
+}
+
+impl From<InputType> for Type {
+    fn from(t: InputType) -> Self {
+        Type::Input(t)
+    }
+}
+
+impl<'a> From<&'a str> for Type {
+    fn from(s: &'a str) -> Self {
+        Type::Other(s.to_owned())
+    }
+}
+
+impl From<String> for Type {
+    fn from(s: String) -> Self {
+        Type::Other(s)
+    }
+}
+
+impl From<Type> for Value {
+    fn from(t: Type) -> Self {
+        match t {
+            Type::Input(t) => t.into(),
+            Type::Other(s) => Value::String(s),
+        }
+    }
+}
+
+keywords! {
+    /// Text direction, for `dir`.
+    pub enum Dir {
+        Ltr => "ltr",
+        Rtl => "rtl",
+        Auto => "auto"
+    }
+}
+
+keywords! {
+    /// How much of a media file to load up front, for `preload`.
+    pub enum Preload {
+        None => "none",
+        Metadata => "metadata",
+        Auto => "auto"
+    }
+}
+
+keywords! {
+    /// How a `textarea` wraps its value when submitted, for `wrap`.
+    pub enum Wrap {
+        Soft => "soft",
+        Hard => "hard"
+    }
+}
+
+/// Whether an `Attr` is set with `setAttribute` or as a DOM property.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum AttrKind {
     Attribute,
     Property,
 }
@@ -17,67 +309,282 @@ enum AttrKind {
 #[derive(Debug, PartialEq)]
 pub struct Attr {
     kind: AttrKind,
-    pub key: &'static str,
+    /// Static for the attributes known up front, owned for names made up at
+    /// runtime such as `data-*`.
+    pub key: Cow<'static, str>,
     pub value: Value,
 }
 
 impl Attr {
-    pub fn property<T: Into<Value>>(key: &'static str, v: T) -> Self {
+    pub fn property<K: Into<Cow<'static, str>>, T: Into<Value>>(key: K, v: T) -> Self {
         Attr {
             kind: AttrKind::Property,
-            key: key,
+            key: key.into(),
             value: v.into(),
         }
     }
 
-    pub fn attribute<T: Into<Value>>(key: &'static str, v: T) -> Self {
+    pub fn attribute<K: Into<Cow<'static, str>>, T: Into<Value>>(key: K, v: T) -> Self {
         Attr {
             kind: AttrKind::Attribute,
-            key: key,
+            key: key.into(),
             value: v.into(),
         }
     }
 
-    pub fn set(&self, element: &Element) {
-        match self.kind {
-            AttrKind::Attribute => {
-                js!( @{element}.setAttribute(@{self.key}, @{&self.value}); );
-            }
-            AttrKind::Property => {
-                js!( @{element}[@{self.key}] = @{&self.value}; );
+    pub fn kind(&self) -> AttrKind {
+        self.kind
+    }
+
+    pub fn set<B: Backend>(&self, backend: &B, element: &B::Node) {
+        match (self.kind, self.namespace()) {
+            (AttrKind::Attribute, Some(ns)) => {
+                backend.set_attribute_ns(element, ns, &self.key, &self.value)
             }
+            (AttrKind::Attribute, None) => backend.set_attribute(element, &self.key, &self.value),
+            (AttrKind::Property, _) => backend.set_property(element, &self.key, &self.value),
         }
     }
 
-    pub fn remove(&self, element: &Element) {
+    /// The namespace of a prefixed attribute such as `xlink:href`, which has
+    /// to be set with `setAttributeNS`.
+    pub fn namespace(&self) -> Option<&'static str> {
         match self.kind {
-            AttrKind::Attribute => {
-                js!( @{element}.removeAttribute(@{self.key}); );
-            }
+            AttrKind::Attribute => prefix_namespace(&self.key),
+            AttrKind::Property => None,
+        }
+    }
+
+    /// The name this attribute goes by in HTML markup. Properties are named
+    /// after the DOM property they set, which doesn't always match.
+    pub fn html_name(&self) -> &str {
+        match self.kind {
+            AttrKind::Attribute => &self.key,
             AttrKind::Property => {
-                js!( delete @{element}[@{self.key}]; );
+                match &*self.key {
+                    "className" => "class",
+                    "htmlFor" => "for",
+                    "httpEquiv" => "http-equiv",
+                    "acceptCharset" => "accept-charset",
+                    "tabIndex" => "tabindex",
+                    "readOnly" => "readonly",
+                    "maxLength" => "maxlength",
+                    "minLength" => "minlength",
+                    "colSpan" => "colspan",
+                    "rowSpan" => "rowspan",
+                    "contentEditable" => "contenteditable",
+                    "accessKey" => "accesskey",
+                    "noValidate" => "novalidate",
+                    "isMap" => "ismap",
+                    "useMap" => "usemap",
+                    "defaultValue" => "value",
+                    "defaultChecked" => "checked",
+                    key => key,
+                }
+            }
+        }
+    }
+
+    pub fn remove<B: Backend>(&self, backend: &B, element: &B::Node) {
+        match (self.kind, self.namespace()) {
+            (AttrKind::Attribute, Some(ns)) => {
+                backend.remove_attribute_ns(element, ns, &self.key)
             }
+            (AttrKind::Attribute, None) => backend.remove_attribute(element, &self.key),
+            (AttrKind::Property, _) => backend.remove_property(element, &self.key),
         }
     }
 }
 
+/// A `data-*` attribute whose name is only known at runtime, e.g.
+/// `data("user-id", 7)` sets `data-user-id`. Names known up front can be
+/// written as is in `vdom!` instead.
+pub fn data<K: AsRef<str>, V: Into<Value>>(name: K, value: V) -> Attr {
+    Attr::attribute(format!("data-{}", name.as_ref()), value)
+}
+
+/// The namespace for the prefix of an attribute name such as `xlink:href`.
+pub fn prefix_namespace(key: &str) -> Option<&'static str> {
+    let prefix = match key.find(':') {
+        Some(end) => &key[..end],
+        None => return None,
+    };
+    match prefix {
+        "xlink" => Some(XLINK_NAMESPACE),
+        "xml" => Some(XML_NAMESPACE),
+        "xmlns" => Some(XMLNS_NAMESPACE),
+        _ => None,
+    }
+}
+
+// Most of these follow Elm's Html.Attributes: properties where setting the
+// property behaves, attributes where it doesn't (e.g. `maxlength` throws for
+// negative numbers, `list` and `form` are read-only properties).
+attrs! [
+    // GLOBAL
+    (id, property, id, String),
+    (class, property, className, String),
+    (title, property, title, String),
+    (hidden, property, hidden, bool),
+    (lang, property, lang, String),
+    (dir, property, dir, Dir),
+    (tabindex, property, tabIndex, i32),
+    (accesskey, property, accessKey, String),
+    (contenteditable, property, contentEditable, TrueFalse),
+    (spellcheck, attribute, spellcheck, TrueFalse),
+    (draggable, attribute, draggable, TrueFalse),
+    (itemprop, attribute, itemprop, String),
+    (role, attribute, role, String),
+
+    // FORMS
+    (accept, property, accept, String),
+    (accept_charset, property, acceptCharset, String),
+    (action, property, action, String),
+    (autocomplete, property, autocomplete, String),
+    (autofocus, property, autofocus, bool),
+    (checked, property, checked, bool),
+    (cols, attribute, cols, i32),
+    (disabled, property, disabled, bool),
+    (enctype, property, enctype, String),
+    (for_, property, htmlFor, String),
+    (form, attribute, form, String),
+    (list, attribute, list, String),
+    (max, property, max, String),
+    (maxlength, attribute, maxlength, i32),
+    (method, property, method, String),
+    (min, property, min, String),
+    (minlength, attribute, minlength, i32),
+    (multiple, property, multiple, bool),
+    (name, property, name, String),
+    (novalidate, property, noValidate, bool),
+    (pattern, property, pattern, String),
+    (placeholder, property, placeholder, String),
+    (readonly, property, readOnly, bool),
+    (required, property, required, bool),
+    (rows, attribute, rows, i32),
+    (selected, property, selected, bool),
+    (size, attribute, size, i32),
+    (step, property, step, String),
+    (type_, property, type, Type),
+    (value, property, value, String),
+    (wrap, property, wrap, Wrap),
+
+    // LINKS
+    (href, property, href, String),
+    (target, property, target, String),
+    (download, property, download, String),
+    (hreflang, property, hreflang, String),
+    (media, attribute, media, String),
+    (ping, property, ping, String),
+    (rel, attribute, rel, String),
+
+    // EMBEDDED CONTENT
+    (src, property, src, String),
+    (alt, property, alt, String),
+    (width, attribute, width, Length),
+    (height, attribute, height, Length),
+    (ismap, property, isMap, bool),
+    (usemap, property, useMap, String),
+    (shape, property, shape, String),
+    (coords, property, coords, String),
+    (sandbox, property, sandbox, String),
+    (srcdoc, property, srcdoc, String),
+
+    // AUDIO AND VIDEO
+    (autoplay, property, autoplay, bool),
+    (controls, property, controls, bool),
+    (loop_, property, loop, bool),
+    (preload, property, preload, Preload),
+    (poster, property, poster, String),
+    (default, property, default, bool),
+    (kind, property, kind, String),
+    (srclang, property, srclang, String),
+
+    // LISTS AND TABLES
+    (reversed, property, reversed, bool),
+    (start, property, start, i32),
+    (align, property, align, String),
+    (colspan, attribute, colspan, i32),
+    (rowspan, attribute, rowspan, i32),
+    (headers, property, headers, String),
+    (scope, property, scope, String),
+
+    // MISC
+    (cite, property, cite, String),
+    (datetime, attribute, datetime, String),
+    (manifest, attribute, manifest, String),
+    (charset, attribute, charset, String),
+    (content, property, content, String),
+    (http_equiv, property, httpEquiv, String),
+
+    // SVG, these have to be attributes since the matching properties are
+    // read-only
+    (view_box, attribute, viewBox, String),
+    (x, attribute, x, Length),
+    (y, attribute, y, Length),
+    (cx, attribute, cx, Length),
+    (cy, attribute, cy, Length),
+    (r, attribute, r, Length),
+    (d, attribute, d, String),
+    (points, attribute, points, String),
+    (transform, attribute, transform, String),
+    (fill, attribute, fill, String),
+    (stroke, attribute, stroke, String),
+    (stroke_width, attribute, "stroke-width", Length),
+    (xlink_href, attribute, "xlink:href", String)
+];
+
+// ARIA states and properties. Any other hyphenated attribute, including
+// `data-*`, can be written as is in `vdom!`, e.g. `{ data-user-id = 7 }`, or
+// made with `data` when the name is only known at runtime.
 attrs! [
-    // CSS
-    (class, property, className),
-    (style, property, style),
-
-    // INPUTS
-    (name, property, name),
-    (type_, property, type),
-    (value, property, value),
-    (checked, property, checked),
-    (disabled, property, disabled),
-
-    //
-    (href, property, href)
+    (aria_activedescendant, attribute, "aria-activedescendant", String),
+    (aria_atomic, attribute, "aria-atomic", TrueFalse),
+    (aria_autocomplete, attribute, "aria-autocomplete", String),
+    (aria_busy, attribute, "aria-busy", TrueFalse),
+    (aria_checked, attribute, "aria-checked", String),
+    (aria_colcount, attribute, "aria-colcount", i32),
+    (aria_colindex, attribute, "aria-colindex", i32),
+    (aria_colspan, attribute, "aria-colspan", i32),
+    (aria_controls, attribute, "aria-controls", String),
+    (aria_current, attribute, "aria-current", String),
+    (aria_describedby, attribute, "aria-describedby", String),
+    (aria_details, attribute, "aria-details", String),
+    (aria_disabled, attribute, "aria-disabled", TrueFalse),
+    (aria_errormessage, attribute, "aria-errormessage", String),
+    (aria_expanded, attribute, "aria-expanded", TrueFalse),
+    (aria_flowto, attribute, "aria-flowto", String),
+    (aria_haspopup, attribute, "aria-haspopup", String),
+    (aria_hidden, attribute, "aria-hidden", TrueFalse),
+    (aria_invalid, attribute, "aria-invalid", String),
+    (aria_keyshortcuts, attribute, "aria-keyshortcuts", String),
+    (aria_label, attribute, "aria-label", String),
+    (aria_labelledby, attribute, "aria-labelledby", String),
+    (aria_level, attribute, "aria-level", i32),
+    (aria_live, attribute, "aria-live", String),
+    (aria_modal, attribute, "aria-modal", TrueFalse),
+    (aria_multiline, attribute, "aria-multiline", TrueFalse),
+    (aria_multiselectable, attribute, "aria-multiselectable", TrueFalse),
+    (aria_orientation, attribute, "aria-orientation", String),
+    (aria_owns, attribute, "aria-owns", String),
+    (aria_placeholder, attribute, "aria-placeholder", String),
+    (aria_posinset, attribute, "aria-posinset", i32),
+    (aria_pressed, attribute, "aria-pressed", String),
+    (aria_readonly, attribute, "aria-readonly", TrueFalse),
+    (aria_relevant, attribute, "aria-relevant", String),
+    (aria_required, attribute, "aria-required", TrueFalse),
+    (aria_roledescription, attribute, "aria-roledescription", String),
+    (aria_rowcount, attribute, "aria-rowcount", i32),
+    (aria_rowindex, attribute, "aria-rowindex", i32),
+    (aria_rowspan, attribute, "aria-rowspan", i32),
+    (aria_selected, attribute, "aria-selected", TrueFalse),
+    (aria_setsize, attribute, "aria-setsize", i32),
+    (aria_sort, attribute, "aria-sort", String),
+    (aria_valuemax, attribute, "aria-valuemax", f64),
+    (aria_valuemin, attribute, "aria-valuemin", f64),
+    (aria_valuenow, attribute, "aria-valuenow", f64),
+    (aria_valuetext, attribute, "aria-valuetext", String)
 ];
 
 // TODO - CSS types/macros? ideally implement something like stylotron that
 // transparently manages a sheet filled with atomic styles.
-
-// TODO - copy the list of properties/attributes from Elm.
@@ -0,0 +1,145 @@
+//! The set of DOM operations that rendering depends on.
+//!
+//! `apply`, `create_element`, `Attr` and listeners only ever talk to the DOM
+//! through a `Backend`, so the same patches can be applied to a real browser
+//! document (see `StdwebBackend`) or to anything else that can pretend to be
+//! one.
+
+use std::any::Any;
+use std::boxed::FnBox;
+use std::rc::Rc;
+
+use futures::Future;
+
+use super::attribute::Value;
+use super::batch::{self, Batch};
+
+/// Receives events dispatched by a backend. What the `&Any` actually is
+/// depends on the backend, `events::Event::from_raw` knows how to recover a
+/// typed event from it.
+pub type EventHandler = Rc<Fn(&Any)>;
+
+/// Where a `Sub` can listen for events outside of any rendered node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GlobalTarget {
+    Window,
+    Document,
+}
+
+/// Timers, futures and global events, for running `Cmd`s and `Sub`s. This is
+/// the object-safe slice of `Backend` that embedded components need.
+pub trait Executor {
+    /// Call `callback` once, after `ms` milliseconds.
+    fn set_timeout(&self, ms: u32, callback: Box<FnBox()>);
+    /// Call `callback` every `ms` milliseconds until the handle is removed.
+    fn set_interval(&self, ms: u32, callback: Rc<Fn()>) -> ListenerHandle;
+    /// Drive `future` to completion in the background.
+    fn spawn(&self, future: Box<Future<Item = (), Error = ()>>);
+    fn add_global_listener(
+        &self,
+        target: GlobalTarget,
+        event_type: &'static str,
+        handler: EventHandler,
+    ) -> ListenerHandle;
+}
+
+pub trait Backend: Executor + 'static {
+    type Node: Clone + PartialEq + 'static;
+
+    fn create_element(&self, tag: &str) -> Self::Node;
+    fn create_element_ns(&self, namespace: &str, tag: &str) -> Self::Node;
+    fn create_text_node(&self, text: &str) -> Self::Node;
+    fn set_text_content(&self, node: &Self::Node, text: &str);
+
+    /// The lowercase tag name of an element, or `None` for any other node.
+    fn tag_name(&self, node: &Self::Node) -> Option<String>;
+    fn text_content(&self, node: &Self::Node) -> Option<String>;
+    /// Whether `node` is a comment. Only hydration looks for comments, so
+    /// backends that can't contain any don't need to say.
+    fn is_comment(&self, _node: &Self::Node) -> bool {
+        false
+    }
+
+    fn parent_node(&self, node: &Self::Node) -> Option<Self::Node>;
+    fn first_child(&self, node: &Self::Node) -> Option<Self::Node>;
+    fn last_child(&self, node: &Self::Node) -> Option<Self::Node>;
+    fn next_sibling(&self, node: &Self::Node) -> Option<Self::Node>;
+    fn child_nodes(&self, node: &Self::Node) -> Vec<Self::Node>;
+
+    fn append_child(&self, parent: &Self::Node, child: &Self::Node);
+    fn insert_before(&self, parent: &Self::Node, child: &Self::Node, reference: &Self::Node);
+    fn remove_child(&self, parent: &Self::Node, child: &Self::Node);
+    fn replace_child(&self, parent: &Self::Node, new_child: &Self::Node, old_child: &Self::Node);
+
+    fn set_attribute(&self, node: &Self::Node, key: &str, value: &Value);
+    fn remove_attribute(&self, node: &Self::Node, key: &str);
+    /// `key` is the qualified name, including any prefix (e.g. `xlink:href`).
+    fn set_attribute_ns(&self, node: &Self::Node, namespace: &str, key: &str, value: &Value);
+    fn remove_attribute_ns(&self, node: &Self::Node, namespace: &str, key: &str);
+    fn set_property(&self, node: &Self::Node, key: &str, value: &Value);
+    fn remove_property(&self, node: &Self::Node, key: &str);
+
+    /// Set one CSS property of the node's inline style.
+    fn set_style(&self, node: &Self::Node, property: &str, value: &str);
+    fn remove_style(&self, node: &Self::Node, property: &str);
+
+    fn add_event_listener(
+        &self,
+        node: &Self::Node,
+        event_type: &'static str,
+        handler: EventHandler,
+    ) -> ListenerHandle;
+
+    /// Call `callback` once, before the next repaint.
+    fn request_animation_frame(&self, callback: Box<FnBox()>);
+
+    /// Apply every instruction in `batch`, returning the nodes it created in
+    /// order. Backends for which a call is expensive should do this in one
+    /// go, the default is `batch::run`.
+    fn apply_batch(&self, batch: &Batch<Self::Node>) -> Vec<Self::Node>
+    where
+        Self: Sized,
+    {
+        batch::run(self, batch)
+    }
+}
+
+/// Something listeners can be installed on. This is the object-safe slice of
+/// `Backend` that `VListener::install` needs.
+pub trait EventTarget {
+    fn add_event_listener(&self, event_type: &'static str, handler: EventHandler) -> ListenerHandle;
+}
+
+/// A node paired with the backend that owns it.
+pub struct NodeTarget<'a, B: Backend + 'a> {
+    pub backend: &'a B,
+    pub node: &'a B::Node,
+}
+
+impl<'a, B: Backend> NodeTarget<'a, B> {
+    pub fn new(backend: &'a B, node: &'a B::Node) -> Self {
+        NodeTarget {
+            backend: backend,
+            node: node,
+        }
+    }
+}
+
+impl<'a, B: Backend> EventTarget for NodeTarget<'a, B> {
+    fn add_event_listener(&self, event_type: &'static str, handler: EventHandler) -> ListenerHandle {
+        self.backend.add_event_listener(self.node, event_type, handler)
+    }
+}
+
+/// Detaches an installed event listener when `remove` is called.
+pub struct ListenerHandle(Box<FnBox()>);
+
+impl ListenerHandle {
+    pub fn new<F: FnOnce() + 'static>(remove: F) -> Self {
+        ListenerHandle(Box::new(remove))
+    }
+
+    pub fn remove(self) {
+        (self.0)()
+    }
+}
@@ -0,0 +1,146 @@
+// Applies a `batch::Batch` to the document in a single call, for
+// `StdwebBackend::apply_batch`.
+//
+// Evaluates to `applyBatch(bytes, inputs)`: `bytes` is the encoded batch and
+// `inputs` the nodes it refers to. Returns the nodes the batch created, in
+// order. The encoding is described in `batch.rs`, and every instruction does
+// what `batch::run` makes `StdwebBackend` do for it; keep the three in step.
+
+(function () {
+    "use strict";
+
+    var decoder = new TextDecoder("utf-8");
+
+    return function applyBatch(bytes, inputs) {
+        var view = new DataView(bytes.buffer, bytes.byteOffset, bytes.byteLength);
+        var pos = 0;
+        var created = [];
+
+        function u32() {
+            var n = view.getUint32(pos, true);
+            pos += 4;
+            return n;
+        }
+
+        function node() {
+            var slot = u32();
+            return (slot & 1 ? created : inputs)[slot >>> 1];
+        }
+
+        function string() {
+            var len = u32();
+            var s = decoder.decode(bytes.subarray(pos, pos + len));
+            pos += len;
+            return s;
+        }
+
+        function value() {
+            switch (bytes[pos++]) {
+            case 0:
+                return null;
+            case 1:
+                return bytes[pos++] !== 0;
+            case 2:
+                var n = view.getFloat64(pos, true);
+                pos += 8;
+                return n;
+            default:
+                return string();
+            }
+        }
+
+        var target, child, reference, namespace, key, count, i;
+        while (pos < bytes.length) {
+            var op = bytes[pos++];
+            switch (op) {
+            case 0: // CREATE_ELEMENT
+                created.push(document.createElement(string()));
+                break;
+            case 1: // CREATE_ELEMENT_NS
+                namespace = string();
+                created.push(document.createElementNS(namespace, string()));
+                break;
+            case 2: // CREATE_TEXT_NODE
+                created.push(document.createTextNode(string()));
+                break;
+            case 3: // SET_TEXT_CONTENT
+                target = node();
+                target.textContent = string();
+                break;
+            case 4: // APPEND_CHILD
+                target = node();
+                target.appendChild(node());
+                break;
+            case 5: // INSERT_BEFORE
+                target = node();
+                child = node();
+                target.insertBefore(child, node());
+                break;
+            case 6: // REMOVE_CHILD
+                target = node();
+                target.removeChild(node());
+                break;
+            case 7: // REPLACE, see `apply::replace`
+                target = node();
+                child = node();
+                if (target.parentNode) {
+                    target.parentNode.replaceChild(child, target);
+                }
+                break;
+            case 8: // ARRANGE, see `apply::arrange`
+                target = node();
+                count = u32();
+                for (i = 0; i < count; i++) {
+                    child = node();
+                    reference = target.childNodes[i];
+                    if (reference !== child) {
+                        target.insertBefore(child, reference || null);
+                    }
+                }
+                break;
+            case 9: // SET_ATTRIBUTE
+                target = node();
+                key = string();
+                target.setAttribute(key, value());
+                break;
+            case 10: // REMOVE_ATTRIBUTE
+                target = node();
+                target.removeAttribute(string());
+                break;
+            case 11: // SET_ATTRIBUTE_NS
+                target = node();
+                namespace = string();
+                key = string();
+                target.setAttributeNS(namespace, key, value());
+                break;
+            case 12: // REMOVE_ATTRIBUTE_NS, which wants the local name
+                target = node();
+                namespace = string();
+                key = string();
+                target.removeAttributeNS(namespace, key.slice(key.indexOf(":") + 1));
+                break;
+            case 13: // SET_PROPERTY
+                target = node();
+                key = string();
+                target[key] = value();
+                break;
+            case 14: // REMOVE_PROPERTY
+                target = node();
+                delete target[string()];
+                break;
+            case 15: // SET_STYLE
+                target = node();
+                key = string();
+                target.style.setProperty(key, string());
+                break;
+            case 16: // REMOVE_STYLE
+                target = node();
+                target.style.removeProperty(string());
+                break;
+            default:
+                throw new Error("Unknown batch opcode " + op);
+            }
+        }
+        return created;
+    };
+})()
@@ -0,0 +1,393 @@
+//! DOM updates encoded into a single buffer.
+//!
+//! `apply` doesn't call the backend once per operation: it writes every
+//! change a patch makes to the DOM into a `Batch` and hands the whole batch
+//! to `Backend::apply_batch`. Backends where each call is expensive replace
+//! that with something that applies the buffer in one go, `StdwebBackend`
+//! passes it to `batch.js` in a single call into JavaScript. Everything else
+//! gets `run`, which makes one backend call per instruction.
+//!
+//! A batch is a sequence of instructions, each an opcode byte followed by its
+//! operands:
+//!
+//! * nodes are `u32`s, an index into the nodes passed along with the batch
+//!   (`inputs`) shifted left by one, or into the nodes created so far by the
+//!   batch, shifted left by one with the low bit set,
+//! * strings are a `u32` byte length followed by UTF-8,
+//! * values are a tag byte (`0` null, `1` bool, `2` number, `3` string)
+//!   followed by a byte for bools, an `f64` for numbers and a string for
+//!   strings.
+//!
+//! Numbers are little endian. `batch.js` has to be kept in step with this.
+
+use std::str;
+
+use super::apply::{arrange, replace};
+use super::attribute::{Attr, AttrKind, Value};
+use super::backend::Backend;
+
+const CREATE_ELEMENT: u8 = 0;
+const CREATE_ELEMENT_NS: u8 = 1;
+const CREATE_TEXT_NODE: u8 = 2;
+const SET_TEXT_CONTENT: u8 = 3;
+const APPEND_CHILD: u8 = 4;
+const INSERT_BEFORE: u8 = 5;
+const REMOVE_CHILD: u8 = 6;
+const REPLACE: u8 = 7;
+const ARRANGE: u8 = 8;
+const SET_ATTRIBUTE: u8 = 9;
+const REMOVE_ATTRIBUTE: u8 = 10;
+const SET_ATTRIBUTE_NS: u8 = 11;
+const REMOVE_ATTRIBUTE_NS: u8 = 12;
+const SET_PROPERTY: u8 = 13;
+const REMOVE_PROPERTY: u8 = 14;
+const SET_STYLE: u8 = 15;
+const REMOVE_STYLE: u8 = 16;
+
+/// A node an instruction refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slot {
+    /// One of the nodes passed along with the batch.
+    Input(u32),
+    /// The result of the batch's `n`th create instruction.
+    Created(u32),
+}
+
+impl Slot {
+    fn encode(self) -> u32 {
+        match self {
+            Slot::Input(index) => index << 1,
+            Slot::Created(index) => index << 1 | 1,
+        }
+    }
+
+    fn decode(word: u32) -> Self {
+        if word & 1 == 0 {
+            Slot::Input(word >> 1)
+        } else {
+            Slot::Created(word >> 1)
+        }
+    }
+}
+
+/// DOM operations to apply in one go, see the module docs.
+#[derive(Debug)]
+pub struct Batch<N> {
+    bytes: Vec<u8>,
+    inputs: Vec<N>,
+    created: u32,
+}
+
+impl<N: Clone> Batch<N> {
+    pub fn new() -> Self {
+        Batch {
+            bytes: Vec::new(),
+            inputs: Vec::new(),
+            created: 0,
+        }
+    }
+
+    /// The encoded instructions.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// The existing nodes the instructions refer to.
+    pub fn inputs(&self) -> &[N] {
+        &self.inputs
+    }
+
+    /// How many nodes applying the batch creates.
+    pub fn created(&self) -> usize {
+        self.created as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Refer to an existing node.
+    pub fn input(&mut self, node: N) -> Slot {
+        self.inputs.push(node);
+        Slot::Input(self.inputs.len() as u32 - 1)
+    }
+
+    /// The node a slot stands for, once the batch was applied and returned
+    /// `created`.
+    pub fn resolve(&self, slot: Slot, created: &[N]) -> N {
+        match slot {
+            Slot::Input(index) => self.inputs[index as usize].clone(),
+            Slot::Created(index) => created[index as usize].clone(),
+        }
+    }
+
+    pub fn create_element(&mut self, tag: &str) -> Slot {
+        self.op(CREATE_ELEMENT);
+        self.string(tag);
+        self.new_slot()
+    }
+
+    pub fn create_element_ns(&mut self, namespace: &str, tag: &str) -> Slot {
+        self.op(CREATE_ELEMENT_NS);
+        self.string(namespace);
+        self.string(tag);
+        self.new_slot()
+    }
+
+    pub fn create_text_node(&mut self, text: &str) -> Slot {
+        self.op(CREATE_TEXT_NODE);
+        self.string(text);
+        self.new_slot()
+    }
+
+    pub fn set_text_content(&mut self, node: Slot, text: &str) {
+        self.op(SET_TEXT_CONTENT);
+        self.slot(node);
+        self.string(text);
+    }
+
+    pub fn append_child(&mut self, parent: Slot, child: Slot) {
+        self.op(APPEND_CHILD);
+        self.slot(parent);
+        self.slot(child);
+    }
+
+    pub fn insert_before(&mut self, parent: Slot, child: Slot, reference: Slot) {
+        self.op(INSERT_BEFORE);
+        self.slot(parent);
+        self.slot(child);
+        self.slot(reference);
+    }
+
+    pub fn remove_child(&mut self, parent: Slot, child: Slot) {
+        self.op(REMOVE_CHILD);
+        self.slot(parent);
+        self.slot(child);
+    }
+
+    /// Put `new` where `old` is in its parent, see `apply::replace`.
+    pub fn replace(&mut self, old: Slot, new: Slot) {
+        self.op(REPLACE);
+        self.slot(old);
+        self.slot(new);
+    }
+
+    /// Put `children` into `parent` in order, see `apply::arrange`.
+    pub fn arrange(&mut self, parent: Slot, children: &[Slot]) {
+        self.op(ARRANGE);
+        self.slot(parent);
+        self.u32(children.len() as u32);
+        for &child in children.iter() {
+            self.slot(child);
+        }
+    }
+
+    /// `Attr::set`, for a node in the batch.
+    pub fn set_attr(&mut self, node: Slot, attr: &Attr) {
+        match (attr.kind(), attr.namespace()) {
+            (AttrKind::Attribute, Some(ns)) => {
+                self.op(SET_ATTRIBUTE_NS);
+                self.slot(node);
+                self.string(ns);
+            }
+            (AttrKind::Attribute, None) => {
+                self.op(SET_ATTRIBUTE);
+                self.slot(node);
+            }
+            (AttrKind::Property, _) => {
+                self.op(SET_PROPERTY);
+                self.slot(node);
+            }
+        }
+        self.string(&attr.key);
+        self.value(&attr.value);
+    }
+
+    /// `Attr::remove`, for a node in the batch.
+    pub fn remove_attr(&mut self, node: Slot, attr: &Attr) {
+        match (attr.kind(), attr.namespace()) {
+            (AttrKind::Attribute, Some(ns)) => {
+                self.op(REMOVE_ATTRIBUTE_NS);
+                self.slot(node);
+                self.string(ns);
+            }
+            (AttrKind::Attribute, None) => {
+                self.op(REMOVE_ATTRIBUTE);
+                self.slot(node);
+            }
+            (AttrKind::Property, _) => {
+                self.op(REMOVE_PROPERTY);
+                self.slot(node);
+            }
+        }
+        self.string(&attr.key);
+    }
+
+    pub fn set_style(&mut self, node: Slot, property: &str, value: &str) {
+        self.op(SET_STYLE);
+        self.slot(node);
+        self.string(property);
+        self.string(value);
+    }
+
+    pub fn remove_style(&mut self, node: Slot, property: &str) {
+        self.op(REMOVE_STYLE);
+        self.slot(node);
+        self.string(property);
+    }
+
+    fn new_slot(&mut self) -> Slot {
+        self.created += 1;
+        Slot::Created(self.created - 1)
+    }
+
+    fn op(&mut self, op: u8) {
+        self.bytes.push(op);
+    }
+
+    fn u32(&mut self, n: u32) {
+        for shift in [0, 8, 16, 24].iter() {
+            self.bytes.push((n >> shift) as u8);
+        }
+    }
+
+    fn slot(&mut self, slot: Slot) {
+        self.u32(slot.encode());
+    }
+
+    fn string(&mut self, s: &str) {
+        self.u32(s.len() as u32);
+        self.bytes.extend_from_slice(s.as_bytes());
+    }
+
+    fn value(&mut self, value: &Value) {
+        match *value {
+            Value::Null => self.bytes.push(0),
+            Value::Bool(b) => {
+                self.bytes.push(1);
+                self.bytes.push(b as u8);
+            }
+            Value::Number(n) => {
+                self.bytes.push(2);
+                let bits = n.to_bits();
+                self.u32(bits as u32);
+                self.u32((bits >> 32) as u32);
+            }
+            Value::String(ref s) => {
+                self.bytes.push(3);
+                self.string(s);
+            }
+        }
+    }
+}
+
+impl<N: Clone> Default for Batch<N> {
+    fn default() -> Self {
+        Batch::new()
+    }
+}
+
+/// Apply `batch` one backend call at a time, returning the nodes it created.
+/// This is what `Backend::apply_batch` does unless the backend knows better.
+pub fn run<B: Backend>(backend: &B, batch: &Batch<B::Node>) -> Vec<B::Node> {
+    let mut created = Vec::with_capacity(batch.created());
+    let mut reader = Reader {
+        bytes: batch.bytes(),
+        pos: 0,
+    };
+    while let Some(op) = reader.op() {
+        macro_rules! node {
+            () => { batch.resolve(reader.slot(), &created) }
+        }
+        match op {
+            CREATE_ELEMENT => {
+                let node = backend.create_element(reader.string());
+                created.push(node);
+            }
+            CREATE_ELEMENT_NS => {
+                let namespace = reader.string();
+                let node = backend.create_element_ns(namespace, reader.string());
+                created.push(node);
+            }
+            CREATE_TEXT_NODE => {
+                let node = backend.create_text_node(reader.string());
+                created.push(node);
+            }
+            SET_TEXT_CONTENT => backend.set_text_content(&node!(), reader.string()),
+            APPEND_CHILD => backend.append_child(&node!(), &node!()),
+            INSERT_BEFORE => backend.insert_before(&node!(), &node!(), &node!()),
+            REMOVE_CHILD => backend.remove_child(&node!(), &node!()),
+            REPLACE => replace(backend, &node!(), &node!()),
+            ARRANGE => {
+                let parent = node!();
+                let children: Vec<B::Node> = (0..reader.u32()).map(|_| node!()).collect();
+                arrange(backend, &parent, children);
+            }
+            SET_ATTRIBUTE => backend.set_attribute(&node!(), reader.string(), &reader.value()),
+            REMOVE_ATTRIBUTE => backend.remove_attribute(&node!(), reader.string()),
+            SET_ATTRIBUTE_NS => {
+                let node = node!();
+                let namespace = reader.string();
+                backend.set_attribute_ns(&node, namespace, reader.string(), &reader.value())
+            }
+            REMOVE_ATTRIBUTE_NS => {
+                let node = node!();
+                backend.remove_attribute_ns(&node, reader.string(), reader.string())
+            }
+            SET_PROPERTY => backend.set_property(&node!(), reader.string(), &reader.value()),
+            REMOVE_PROPERTY => backend.remove_property(&node!(), reader.string()),
+            SET_STYLE => backend.set_style(&node!(), reader.string(), reader.string()),
+            REMOVE_STYLE => backend.remove_style(&node!(), reader.string()),
+            _ => panic!("Unknown batch opcode {}", op),
+        }
+    }
+    created
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn op(&mut self) -> Option<u8> {
+        let op = self.bytes.get(self.pos).cloned();
+        self.pos += 1;
+        op
+    }
+
+    fn u32(&mut self) -> u32 {
+        let bytes = &self.bytes[self.pos..self.pos + 4];
+        self.pos += 4;
+        bytes.iter().rev().fold(0, |n, &byte| n << 8 | byte as u32)
+    }
+
+    fn slot(&mut self) -> Slot {
+        Slot::decode(self.u32())
+    }
+
+    fn string(&mut self) -> &'a str {
+        let len = self.u32() as usize;
+        let bytes = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        str::from_utf8(bytes).expect("batch strings are UTF-8")
+    }
+
+    fn value(&mut self) -> Value {
+        let tag = self.bytes[self.pos];
+        self.pos += 1;
+        match tag {
+            0 => Value::Null,
+            1 => {
+                self.pos += 1;
+                Value::Bool(self.bytes[self.pos - 1] != 0)
+            }
+            2 => {
+                let low = self.u32() as u64;
+                let high = self.u32() as u64;
+                Value::Number(f64::from_bits(high << 32 | low))
+            }
+            _ => Value::String(self.string().to_owned()),
+        }
+    }
+}
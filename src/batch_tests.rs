@@ -0,0 +1,156 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::apply::apply;
+use super::attribute::{class, Attr, Value};
+use super::backend::Backend;
+use super::batch::{run, Batch, Slot};
+use super::diff::diff;
+use super::mock::{MockBackend, MockNode};
+use super::test_support::{ignore, Click};
+use super::node::SVG_NAMESPACE;
+use super::root::{create_element, create_tree};
+use super::Node;
+
+#[test]
+fn test_run_every_instruction() {
+    let backend = MockBackend;
+    let body = MockNode::element("body");
+    let old = MockNode::element("p");
+    let kept = MockNode::text("kept");
+    backend.append_child(&body, &old);
+    backend.append_child(&body, &kept);
+
+    let mut batch = Batch::new();
+    let body_slot = batch.input(body.clone());
+    let old_slot = batch.input(old.clone());
+    let kept_slot = batch.input(kept.clone());
+
+    let list = batch.create_element("ul");
+    batch.set_attr(list, &Attr::attribute("data-n", 1.5));
+    batch.set_attr(list, &Attr::attribute("data-gone", "x"));
+    batch.remove_attr(list, &Attr::attribute("data-gone", "x"));
+    batch.set_attr(list, &Attr::property("hidden", false));
+    batch.set_attr(list, &Attr::property("title", "héllo"));
+    batch.set_attr(list, &Attr::property("value", Value::Null));
+    batch.set_attr(list, &Attr::property("id", "gone"));
+    batch.remove_attr(list, &Attr::property("id", "gone"));
+    batch.set_style(list, "color", "red");
+    batch.set_style(list, "margin", "0");
+    batch.remove_style(list, "margin");
+
+    let first = batch.create_text_node("first");
+    let second = batch.create_text_node("second");
+    batch.append_child(list, second);
+    batch.insert_before(list, first, second);
+    batch.set_text_content(second, "2nd");
+
+    let svg = batch.create_element_ns(SVG_NAMESPACE, "svg");
+    let link = batch.create_element_ns(SVG_NAMESPACE, "use");
+    batch.set_attr(link, &Attr::attribute("xlink:href", "#dot"));
+    batch.append_child(svg, link);
+
+    batch.replace(old_slot, list);
+    batch.append_child(body_slot, svg);
+    batch.arrange(body_slot, &[kept_slot, svg, list]);
+    batch.remove_child(body_slot, kept_slot);
+    assert_eq!(batch.created(), 5);
+
+    let created = run(&backend, &batch);
+    assert_eq!(created.len(), 5);
+    assert_eq!(batch.resolve(Slot::Created(0), &created).tag(), Some("ul".to_owned()));
+    assert_eq!(batch.resolve(body_slot, &created), body);
+
+    let children = body.children();
+    assert_eq!(children.len(), 2);
+    assert_eq!(children[0].namespace(), Some(SVG_NAMESPACE.to_owned()));
+    assert_eq!(
+        children[0].children()[0].attribute("xlink:href"),
+        Some(Value::from("#dot"))
+    );
+    assert_eq!(old.parent(), None);
+    assert_eq!(kept.parent(), None);
+
+    let list = &children[1];
+    assert_eq!(list.text_content(), "first2nd");
+    assert_eq!(list.attribute("data-n"), Some(Value::Number(1.5)));
+    assert_eq!(list.attribute("data-gone"), None);
+    assert_eq!(list.property("hidden"), Some(Value::Bool(false)));
+    assert_eq!(list.property("title"), Some(Value::from("héllo")));
+    assert_eq!(list.property("value"), Some(Value::Null));
+    assert_eq!(list.property("id"), None);
+    assert_eq!(list.style("color"), Some("red".to_owned()));
+    assert_eq!(list.style("margin"), None);
+}
+
+#[test]
+fn test_patch_is_applied_in_one_batch() {
+    let backend = MockBackend;
+    let old: Node<&'static str> = vdom!(div [
+        ul [
+            keyed!("a", vdom!(li [ text!("a") ]))
+            keyed!("b", vdom!(li [ text!("b") ]))
+            keyed!("c", vdom!(li [ text!("c") ]))
+        ]
+        p { class = "old" } [ text!("one") ]
+        span
+    ]);
+    let new: Node<&'static str> = vdom!(div [
+        ul [
+            keyed!("d", vdom!(li [ text!("d") ]))
+            keyed!("c", vdom!(li [ text!("c") ]))
+            keyed!("a", vdom!(li [ text!("a") ]))
+        ]
+        p { class = "new"; style = vec![("color", "red")] } [ text!("two") ]
+        section [ text!("three") ]
+        button { on Click |_evt| "clicked" } [ text!("go") ]
+    ]);
+    let container = MockNode::element("body");
+    let tree = create_tree(&backend, &old, &ignore());
+    backend.append_child(&container, &tree.node());
+
+    let before = backend.batches();
+    apply(&backend, &tree, diff(&old, &new), ignore());
+    assert_eq!(backend.batches(), before + 1);
+    assert_eq!(
+        container.children()[0].snapshot(),
+        create_element(&backend, &new, &ignore()).snapshot()
+    );
+}
+
+#[test]
+fn test_patch_without_dom_changes_skips_the_batch() {
+    let backend = MockBackend;
+    let old: Node<&'static str> = vdom!(button { on Click |_evt| "old" });
+    let new: Node<&'static str> = vdom!(button { on Click |_evt| "new" });
+    let tree = create_tree(&backend, &old, &ignore());
+
+    let before = backend.batches();
+    apply(&backend, &tree, diff(&old, &new), ignore());
+    assert_eq!(backend.batches(), before);
+}
+
+#[test]
+fn test_created_nodes_are_finished_after_the_batch() {
+    let backend = MockBackend;
+    let parents = Rc::new(RefCell::new(Vec::new()));
+    let old: Node<&'static str> = vdom!(div);
+    let new: Node<&'static str> = {
+        let mut button = vdom!(button { on Click |_evt| "clicked" } [ text!("go") ]);
+        let parents = parents.clone();
+        button.on_create(move |dnode: &MockNode| parents.borrow_mut().push(dnode.parent()));
+        vdom!(div [ (button) ])
+    };
+    let tree = create_tree(&backend, &old, &ignore());
+
+    let sent = Rc::new(RefCell::new(Vec::new()));
+    let send = sent.clone();
+    apply(&backend, &tree, diff(&old, &new), Rc::new(move |msg| send.borrow_mut().push(msg)));
+
+    // the whole batch is in place by the time hooks run
+    assert_eq!(*parents.borrow(), vec![Some(tree.node())]);
+    let button = tree.node().children()[0].clone();
+    assert_eq!(tree.children()[0].node(), button);
+    button.dispatch(Click);
+    assert_eq!(*sent.borrow(), vec!["clicked"]);
+}
@@ -0,0 +1,121 @@
+//! Side effects requested by `Component::update`.
+//!
+//! `update` doesn't perform effects itself, it returns a `Cmd` describing
+//! them and the root carries it out once `update` returns. Messages produced
+//! by a command are sent back to the component that returned it.
+
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::rc::Rc;
+
+use futures::Future;
+
+use super::backend::Executor;
+use super::component::Callback;
+
+pub enum Cmd<Msg> {
+    /// Do nothing.
+    None,
+    /// Run several commands, in order.
+    Batch(Vec<Cmd<Msg>>),
+    /// Send a message right after the current one has been handled.
+    Send(Msg),
+    /// Send a message after the given number of milliseconds.
+    Delay(u32, Msg),
+    /// Send the message a future resolves to, see `Cmd::perform`.
+    Perform(Box<Future<Item = Msg, Error = ()>>),
+}
+
+impl<Msg: 'static> Cmd<Msg> {
+    pub fn none() -> Self {
+        Cmd::None
+    }
+
+    pub fn batch(cmds: Vec<Cmd<Msg>>) -> Self {
+        Cmd::Batch(cmds)
+    }
+
+    pub fn send(msg: Msg) -> Self {
+        Cmd::Send(msg)
+    }
+
+    pub fn delay(ms: u32, msg: Msg) -> Self {
+        Cmd::Delay(ms, msg)
+    }
+
+    /// Wait for `future` and send whatever `tagger` makes of its result.
+    pub fn perform<F, T>(future: F, tagger: T) -> Self
+    where
+        F: Future + 'static,
+        T: FnOnce(Result<F::Item, F::Error>) -> Msg + 'static,
+    {
+        Cmd::Perform(Box::new(future.then(move |result| Ok(tagger(result)))))
+    }
+
+    /// Convert the messages this command produces, for passing commands from
+    /// a nested update function up to its parent.
+    pub fn map<To, F>(self, tagger: F) -> Cmd<To>
+    where
+        To: 'static,
+        F: Fn(Msg) -> To + 'static,
+    {
+        self.map_rc(Rc::new(tagger))
+    }
+
+    fn map_rc<To: 'static>(self, tagger: Rc<Fn(Msg) -> To>) -> Cmd<To> {
+        match self {
+            Cmd::None => Cmd::None,
+            Cmd::Batch(cmds) => {
+                Cmd::Batch(cmds.into_iter().map(|cmd| cmd.map_rc(tagger.clone())).collect())
+            }
+            Cmd::Send(msg) => Cmd::Send(tagger(msg)),
+            Cmd::Delay(ms, msg) => Cmd::Delay(ms, tagger(msg)),
+            Cmd::Perform(future) => Cmd::Perform(Box::new(future.map(move |msg| tagger(msg)))),
+        }
+    }
+
+    /// Carry out the command, passing the messages it produces to `send`.
+    pub fn run<E: Executor + ?Sized>(self, executor: &E, send: Callback<Msg>) {
+        match self {
+            Cmd::None => {}
+            Cmd::Batch(cmds) => {
+                for cmd in cmds {
+                    cmd.run(executor, send.clone());
+                }
+            }
+            Cmd::Send(msg) => send(msg),
+            Cmd::Delay(ms, msg) => executor.set_timeout(ms, Box::new(move || send(msg))),
+            Cmd::Perform(future) => executor.spawn(Box::new(future.map(move |msg| send(msg)))),
+        }
+    }
+}
+
+impl<Msg> Default for Cmd<Msg> {
+    fn default() -> Self {
+        Cmd::None
+    }
+}
+
+impl<Msg: Debug> Debug for Cmd<Msg> {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match *self {
+            Cmd::None => write!(f, "None"),
+            Cmd::Batch(ref cmds) => write!(f, "Batch({:?})", cmds),
+            Cmd::Send(ref msg) => write!(f, "Send({:?})", msg),
+            Cmd::Delay(ms, ref msg) => write!(f, "Delay({}, {:?})", ms, msg),
+            Cmd::Perform(_) => write!(f, "Perform(..)"),
+        }
+    }
+}
+
+/// Futures can't be compared, so a `Perform` is never equal to anything.
+impl<Msg: PartialEq> PartialEq for Cmd<Msg> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (&Cmd::None, &Cmd::None) => true,
+            (&Cmd::Batch(ref a), &Cmd::Batch(ref b)) => a == b,
+            (&Cmd::Send(ref a), &Cmd::Send(ref b)) => a == b,
+            (&Cmd::Delay(ms_a, ref a), &Cmd::Delay(ms_b, ref b)) => ms_a == ms_b && a == b,
+            _ => false,
+        }
+    }
+}
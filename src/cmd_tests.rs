@@ -0,0 +1,128 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use futures::future;
+use futures::sync::oneshot;
+
+use super::cmd::Cmd;
+use super::component::Component;
+use super::mock::MockBackend;
+use super::test_support::{mount_sync, Click};
+use super::Node;
+
+#[derive(Debug)]
+enum Msg {
+    Start,
+    Wait(oneshot::Receiver<String>),
+    Show(String),
+}
+
+impl PartialEq for Msg {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (&Msg::Start, &Msg::Start) => true,
+            (&Msg::Show(ref a), &Msg::Show(ref b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+/// Shows the last message it was told to show.
+struct Status(String);
+
+impl Component<Msg> for Status {
+    fn view(&self) -> Node<Msg> {
+        vdom!(div { on Click |_evt| Msg::Start } [ format!("{}", self.0) ])
+    }
+
+    fn update(&mut self, msg: Msg) -> Cmd<Msg> {
+        match msg {
+            Msg::Start => {
+                self.0 = "started".into();
+                Cmd::batch(vec![Cmd::none(), Cmd::delay(100, Msg::Show("done".into()))])
+            }
+            Msg::Wait(rx) => {
+                Cmd::perform(rx, |result| Msg::Show(result.unwrap_or("cancelled".into())))
+            }
+            Msg::Show(text) => {
+                self.0 = text;
+                Cmd::none()
+            }
+        }
+    }
+}
+
+#[test]
+fn test_update_returns_commands_without_running_them() {
+    let mut status = Status("".into());
+    assert_eq!(
+        status.update(Msg::Start),
+        Cmd::Batch(vec![Cmd::None, Cmd::Delay(100, Msg::Show("done".into()))])
+    );
+    assert_eq!(status.update(Msg::Show("hi".into())), Cmd::None);
+    assert_eq!(status.0, "hi");
+    assert_eq!(MockBackend.advance_time(1000), 0);
+}
+
+#[test]
+fn test_map() {
+    let cmd = Cmd::batch(vec![Cmd::send(1), Cmd::delay(5, 2)]).map(|n| n * 10);
+    assert_eq!(cmd, Cmd::Batch(vec![Cmd::Send(10), Cmd::Delay(5, 20)]));
+
+    let performed = Rc::new(RefCell::new(None));
+    let cmd = Cmd::perform(future::ok::<i32, ()>(4), |r| r.unwrap()).map(|n| n + 1);
+    cmd.run(&MockBackend, {
+        let performed = performed.clone();
+        Rc::new(move |n| *performed.borrow_mut() = Some(n))
+    });
+    assert_eq!(MockBackend.run_tasks(), 1);
+    assert_eq!(*performed.borrow(), Some(5));
+}
+
+#[test]
+fn test_root_runs_delay() {
+    let (root, container) = mount_sync(Status("".into()));
+    root.send(Msg::Start);
+    assert_eq!(container.text_content(), "started");
+
+    assert_eq!(MockBackend.advance_time(99), 0);
+    assert_eq!(container.text_content(), "started");
+    assert_eq!(MockBackend.advance_time(1), 1);
+    assert_eq!(container.text_content(), "done");
+}
+
+#[test]
+fn test_root_runs_future() {
+    let (root, container) = mount_sync(Status("".into()));
+    let (tx, rx) = oneshot::channel();
+    root.send(Msg::Wait(rx));
+
+    assert_eq!(MockBackend.run_tasks(), 0);
+    tx.send("resolved".into()).unwrap();
+    assert_eq!(MockBackend.run_tasks(), 1);
+    assert_eq!(container.text_content(), "resolved");
+}
+
+/// Embeds a `Status`, so its commands have to go through the root's host.
+struct Parent;
+
+impl Component<()> for Parent {
+    fn view(&self) -> Node<()> {
+        vdom!(div [ component!(Status("".into())) ])
+    }
+
+    fn update(&mut self, _: ()) -> Cmd<()> {
+        Cmd::none()
+    }
+}
+
+#[test]
+fn test_embedded_component_commands() {
+    let (_root, container) = mount_sync(Parent);
+
+    let status = container.children()[0].children()[0].clone();
+    status.dispatch(Click);
+    assert_eq!(container.text_content(), "started");
+    MockBackend.advance_time(100);
+    assert_eq!(container.text_content(), "done");
+}
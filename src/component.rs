@@ -1,9 +1,18 @@
 use std::rc::Rc;
 use super::node::Node;
+use super::cmd::Cmd;
+use super::sub::Sub;
 
 pub type Callback<T> = Rc<Fn(T)>;
 
 pub trait Component<Msg: 'static>: Sized + 'static {
     fn view(&self) -> Node<Msg>;
-    fn update(&mut self, Msg, Callback<Msg>);
+    /// Handle a message, returning any side effects to run afterwards.
+    fn update(&mut self, Msg) -> Cmd<Msg>;
+
+    /// Events from outside the view this component wants to hear about. Asked
+    /// for again after every update.
+    fn subscriptions(&self) -> Sub<Msg> {
+        Sub::none()
+    }
 }
@@ -1,21 +1,60 @@
 use std::fmt::Debug;
+
 use super::{Node, Attr, Child};
+use super::events::VListener;
+use super::hooks::Hook;
+use super::node_ref::NodeRef;
 
 #[derive(Debug, PartialEq)]
 pub enum Operation<'node, Msg: 'static + Debug> {
     ReplaceNode(&'node Node<Msg>),
     ReplaceText(&'node str),
+    ReplaceWithText(&'node str),
+    /// Replace the patched child with the children of a fragment.
+    ReplaceWithFragment(&'node [Child<Msg>]),
     RemoveAttribute(&'node Attr),
     SetAttribute(&'node Attr),
+    /// Set a CSS property of the inline style to the given value.
+    SetStyle(&'node str, &'node str),
+    RemoveStyle(&'node str),
     RemoveLast(i32),
     Append(&'node [Child<Msg>]),
     Insert(i32, &'node Node<Msg>),
+    Reorder(Vec<KeyedOp<'node, Msg>>),
+    AddListener(&'node VListener<Msg>),
+    RemoveListener(&'node VListener<Msg>),
+    ReplaceListener(&'node VListener<Msg>, &'node VListener<Msg>),
+    /// Point the ref at the patched node.
+    SetRef(&'node NodeRef),
+    /// Empty the ref if it still points at the patched node, which is about
+    /// to be removed or is no longer referenced by the new tree.
+    ClearRef(&'node NodeRef),
+    /// Run the update hook of the patched node, after all other patches.
+    Updated(&'node Hook),
+    /// Run the remove hook of the patched node, which has been removed by an
+    /// earlier patch.
+    Removed(&'node Hook),
+}
+
+/// One step of reordering a keyed child list. Positions refer to the index of
+/// a child within its parent, `from` in the old list and `to` in the new one.
+#[derive(Debug, PartialEq)]
+pub enum KeyedOp<'node, Msg: 'static + Debug> {
+    Remove(i32),
+    Move(i32, i32),
+    Insert(i32, &'node Node<Msg>),
 }
 
+/// The position of a node relative to the root of a diff: the index of each
+/// child to descend into, so `[]` is the root and `[2, 0]` the first child of
+/// its third child. A fragment counts as a single child, its children are
+/// below it.
+pub type Path = Vec<usize>;
+
 impl<'a, M: 'static + Debug> Operation<'a, M> {
-    pub fn at(self, index: &i32) -> Patch<'a, i32, M> {
+    pub fn at(self, path: &[usize]) -> Patch<'a, Path, M> {
         Patch {
-            node: *index,
+            node: path.to_vec(),
             operation: self,
         }
     }
@@ -37,364 +76,325 @@ impl<'node, K, M: 'static + Debug> Patch<'node, K, M> {
     }
 }
 
+/// Compute the patches turning `old` into `new`. Every patch is addressed by
+/// the path of the node it applies to in the *old* tree, so `apply` can find
+/// all of them before changing anything.
 pub fn diff<'root, M: 'static + Debug>(
     old: &'root Node<M>,
     new: &'root Node<M>,
-) -> Vec<Patch<'root, i32, M>> {
-    let mut patches: Vec<Patch<'root, i32, M>> = Vec::with_capacity(32);
-    let mut index = 0i32;
-    diff_node(old, new, &mut patches, &mut index);
+) -> Vec<Patch<'root, Path, M>> {
+    diff_at(old, new, Vec::new())
+}
+
+/// Like `diff`, for a subtree whose root is at `path` in the whole tree.
+pub fn diff_at<'root, M: 'static + Debug>(
+    old: &'root Node<M>,
+    new: &'root Node<M>,
+    path: Path,
+) -> Vec<Patch<'root, Path, M>> {
+    let mut patches: Vec<Patch<'root, Path, M>> = Vec::with_capacity(32);
+    let mut path = path;
+    diff_node(old, new, &mut patches, &mut path);
     patches
 }
 
 fn diff_node<'root, 'node: 'root, M: 'static + Debug>(
     old: &'node Node<M>,
     new: &'node Node<M>,
-    patches: &mut Vec<Patch<'root, i32, M>>,
-    index: &mut i32,
+    patches: &mut Vec<Patch<'root, Path, M>>,
+    path: &mut Path,
 ) {
     use self::Operation::*;
 
-    /* todo - investigate whether it's worth deriving PartialEq for Node
-	if (old == new) {
-		return;
-	}
-	*/
+    // Subtrees rendered by embedded components or lazy children that haven't
+    // changed are shared between the old and new trees.
+    if old as *const Node<M> == new as *const Node<M> {
+        return;
+    }
 
     // Bail if you run into different types of nodes. Implies that the
     // structure has changed significantly and it's not worth a diff.
-    if new.tag != old.tag {
-        patches.push(ReplaceNode(new).at(index));
+    if new.tag != old.tag || new.namespace != old.namespace {
+        patches.push(ReplaceNode(new).at(path));
+        removed(old, patches, path);
         return;
     }
 
-    diff_attributes(old, new, patches, index);
-    diff_children(old, new, patches, index);
+    diff_attributes(old, new, patches, path);
+    diff_styles(old, new, patches, path);
+    diff_listeners(old, new, patches, path);
+    diff_ref(old, new, patches, path);
+    if let Some(ref hook) = new.hooks.update {
+        patches.push(Updated(hook).at(path));
+    }
+    diff_children(&old.children, &new.children, patches, path);
 }
 
 fn diff_attributes<'root, 'node: 'root, M: 'static + Debug>(
     old: &'node Node<M>,
     new: &'node Node<M>,
-    patches: &mut Vec<Patch<'root, i32, M>>,
-    index: &i32,
+    patches: &mut Vec<Patch<'root, Path, M>>,
+    path: &[usize],
 ) {
     use std::collections::HashSet;
     use self::Operation::*;
     let keys: HashSet<_> = old.attributes.keys().chain(new.attributes.keys()).collect();
     for key in keys.into_iter() {
         if !new.attributes.contains_key(key) {
-            patches.push(RemoveAttribute(old.attributes.get(key).unwrap()).at(index));
+            patches.push(RemoveAttribute(old.attributes.get(key).unwrap()).at(path));
         } else if !old.attributes.contains_key(key) {
-            patches.push(SetAttribute(new.attributes.get(key).unwrap()).at(index));
+            patches.push(SetAttribute(new.attributes.get(key).unwrap()).at(path));
         } else {
             let new_attr = new.attributes.get(key);
             if old.attributes.get(key) != new_attr {
-                patches.push(SetAttribute(new_attr.unwrap()).at(index));
+                patches.push(SetAttribute(new_attr.unwrap()).at(path));
             }
         }
     }
 }
 
-fn diff_children<'root, 'node: 'root, M: 'static + Debug>(
-    old_parent: &'node Node<M>,
-    new_parent: &'node Node<M>,
-    patches: &mut Vec<Patch<'root, i32, M>>,
-    index: &mut i32,
+fn diff_styles<'root, 'node: 'root, M: 'static + Debug>(
+    old: &'node Node<M>,
+    new: &'node Node<M>,
+    patches: &mut Vec<Patch<'root, Path, M>>,
+    path: &[usize],
 ) {
     use self::Operation::*;
-    let old_len = old_parent.children.len();
-    let new_len = new_parent.children.len();
-
-    // Figure out if there are inserts or removals
-
-    if old_len > new_len {
-        patches.push(RemoveLast((old_len - new_len) as i32).at(index));
-    } else if old_len < new_len {
-        patches.push(Append(&new_parent.children[old_len..]).at(index));
+    for (property, value) in new.styles.iter() {
+        if old.styles.get(property) != Some(value) {
+            patches.push(SetStyle(property, value).at(path));
+        }
     }
+    for property in old.styles.keys() {
+        if !new.styles.contains_key(property) {
+            patches.push(RemoveStyle(property).at(path));
+        }
+    }
+}
 
-    // Pairwise diff everything else
-    let pairs = old_parent.children.iter().zip(new_parent.children.iter());
-
-    for (old_child, new_child) in pairs {
-        *index += 1;
-        match (old_child, new_child) {
-            (&Child::Node(ref old_node), &Child::Node(ref new_node)) => {
-                diff_node(old_node, new_node, patches, index);
-            }
-            (&Child::Text(ref old_text), &Child::Text(ref new_text)) => {
-                if old_text != new_text {
-                    patches.push(ReplaceText(new_text.as_str()).at(index))
-                }
-            }
-            (_, &Child::Text(ref new_text)) => {
-                patches.push(ReplaceText(new_text.as_str()).at(index))
+/// Listeners are matched up by their event type. Closures can't be compared,
+/// so every matched pair is replaced to pick up whatever the new `view()`
+/// captured; the new listener adopts the DOM handler of the old one, see
+/// `VListener::adopt`.
+fn diff_listeners<'root, 'node: 'root, M: 'static + Debug>(
+    old: &'node Node<M>,
+    new: &'node Node<M>,
+    patches: &mut Vec<Patch<'root, Path, M>>,
+    path: &[usize],
+) {
+    use self::Operation::*;
+    let mut matched = vec![false; old.listeners.len()];
+
+    for new_listener in new.listeners.iter() {
+        let old_pos = old.listeners.iter().enumerate().position(|(pos, old_listener)| {
+            !matched[pos] && old_listener.key() == new_listener.key()
+        });
+        match old_pos {
+            Some(pos) => {
+                matched[pos] = true;
+                patches.push(ReplaceListener(&*old.listeners[pos], &**new_listener).at(path));
             }
-            (_, &Child::Node(ref new_node)) => patches.push(ReplaceNode(new_node).at(index)),
+            None => patches.push(AddListener(&**new_listener).at(path)),
         }
     }
 
-    if old_len > new_len {
-        // advance the node counter to compensate for the nodes we are removing from the dom.
-        // this is needed to keep node indexes in sync with those generated in apply.
-        *index += count_children(&old_parent.children[new_len..]);
+    for (pos, was_matched) in matched.into_iter().enumerate() {
+        if !was_matched {
+            patches.push(RemoveListener(&*old.listeners[pos]).at(path));
+        }
     }
 }
 
-fn count_children<M>(children: &[Child<M>]) -> i32 {
-    children.iter().fold(0, |count, child| {
-        count + 1 +
-            match child {
-                &Child::Text(_) => 0,
-                &Child::Node(ref node) => count_children(&node.children[..]),
-            }
-    })
+fn diff_ref<'root, 'node: 'root, M: 'static + Debug>(
+    old: &'node Node<M>,
+    new: &'node Node<M>,
+    patches: &mut Vec<Patch<'root, Path, M>>,
+    path: &[usize],
+) {
+    use self::Operation::*;
+    if old.node_ref == new.node_ref {
+        return;
+    }
+    if let Some(ref node_ref) = old.node_ref {
+        patches.push(ClearRef(node_ref).at(path));
+    }
+    if let Some(ref node_ref) = new.node_ref {
+        patches.push(SetRef(node_ref).at(path));
+    }
 }
 
-////////////  KEYED DIFF  ////////////
+/// Clear the refs and run the remove hooks in a subtree that is being
+/// removed.
+fn removed<'root, 'node: 'root, M: 'static + Debug>(
+    old: &'node Node<M>,
+    patches: &mut Vec<Patch<'root, Path, M>>,
+    path: &mut Path,
+) {
+    if let Some(ref node_ref) = old.node_ref {
+        patches.push(Operation::ClearRef(node_ref).at(path));
+    }
+    if let Some(ref hook) = old.hooks.remove {
+        patches.push(Operation::Removed(hook).at(path));
+    }
+    removed_children(&old.children, patches, path);
+}
 
+/// `removed` for a child that is being removed.
+fn removed_child<'root, 'node: 'root, M: 'static + Debug>(
+    old: &'node Child<M>,
+    patches: &mut Vec<Patch<'root, Path, M>>,
+    path: &mut Path,
+) {
+    match *old {
+        Child::Fragment(ref fragment) => removed_children(&fragment.children, patches, path),
+        _ => {
+            if let Some(node) = old.node() {
+                removed(node, patches, path);
+            }
+        }
+    }
+}
 
-/*
-fn diff_keyed_children(old_parent, new_parent, patches, root_index) {
-	let local_patches = Vec::new();
-
-	let changes: HashMap<String, Entry> = HashMap::new(); // Dict String Entry
-	let inserts: Vec<(i32, Entry)> = Vec::new(); // Array { index : Int, entry : Entry }
-	// type Entry = { tag : String, vnode : VNode, index : Int, data : _ }
-
-	let old_children = old_parent.children;
-	let new_children = new_parent.children;
-	let old_len = old_children.length;
-	let new_len = new_children.length;
-	let old_index = 0;
-	let new_index = 0;
-
-	let index = root_index;
-
-	while (old_index < old_len && new_index < new_len)
-	{
-		let old = old_children[old_index];
-		let new = new_children[new_index];
-
-		let old_key = old._0;
-		let new_key = new._0;
-		let old_node = old._1;
-		let new_node = new._1;
-
-		// check if keys match
-
-		if (old_key === new_key)
-		{
-			index++;
-			diff_node(old_node, new_node, local_patches, index);
-			index += old_node.descendants_count || 0;
-
-			old_index++;
-			new_index++;
-			continue;
-		}
-
-		// look ahead 1 to detect insertions and removals.
-
-		let old_look_ahead = old_index + 1 < old_len;
-		let new_look_ahead = new_index + 1 < new_len;
-
-		if (old_look_ahead)
-		{
-			let old_next = old_children[old_index + 1];
-			let old_next_key = old_next._0;
-			let old_next_node = old_next._1;
-			let old_match = new_key === old_next_key;
-		}
-
-		if (new_look_ahead)
-		{
-			let new_next = new_children[new_index + 1];
-			let new_next_key = new_next._0;
-			let new_next_node = new_next._1;
-			let new_match = old_key === new_next_key;
-		}
-
-
-		// swap old and new
-		if (old_look_ahead && new_look_ahead && new_match && old_match)
-		{
-			index++;
-			diff_node(old_node, new_next_node, local_patches, index);
-			insert_node(changes, local_patches, old_key, new_node, new_index, inserts);
-			index += old_node.descendants_count || 0;
-
-			index++;
-			remove_node(changes, local_patches, old_key, old_next_node, index);
-			index += old_next_node.descendants_count || 0;
-
-			old_index += 2;
-			new_index += 2;
-			continue;
-		}
-
-		// insert new
-		if (new_look_ahead && new_match)
-		{
-			index++;
-			insert_node(changes, local_patches, new_key, new_node, new_index, inserts);
-			diff_node(old_node, new_next_node, local_patches, index);
-			index += old_node.descendants_count || 0;
-
-			old_index += 1;
-			new_index += 2;
-			continue;
-		}
-
-		// remove old
-		if (old_look_ahead && old_match)
-		{
-			index++;
-			remove_node(changes, local_patches, old_key, old_node, index);
-			index += old_node.descendants_count || 0;
-
-			index++;
-			diff_node(old_next_node, new_node, local_patches, index);
-			index += old_next_node.descendants_count || 0;
-
-			old_index += 2;
-			new_index += 1;
-			continue;
-		}
-
-		// remove old, insert new
-		if (old_look_ahead && new_look_ahead && old_next_key === new_next_key)
-		{
-			index++;
-			remove_node(changes, local_patches, old_key, old_node, index);
-			insert_node(changes, local_patches, new_key, new_node, new_index, inserts);
-			index += old_node.descendants_count || 0;
-
-			index++;
-			diff_node(old_next_node, new_next_node, local_patches, index);
-			index += old_next_node.descendants_count || 0;
-
-			old_index += 2;
-			new_index += 2;
-			continue;
-		}
-
-		break;
-	}
-
-	// eat up any remaining nodes with remove_node and insert_node
-
-	while (old_index < old_len)
-	{
-		index++;
-		let old = old_children[old_index];
-		let old_node = old._1;
-		remove_node(changes, local_patches, old._0, old_node, index);
-		index += old_node.descendants_count || 0;
-		old_index++;
-	}
-
-	let end_inserts;
-	while (new_index < new_len)
-	{
-		end_inserts = end_inserts || [];
-		let new = new_children[new_index];
-		insert_node(changes, local_patches, new._0, new._1, undefined, end_inserts);
-		new_index++;
-	}
-
-	if (local_patches.length > 0 || inserts.length > 0 || typeof end_inserts !== 'undefined')
-	{
-		patches.push(make_patch('p-reorder', root_index, {
-			patches: local_patches,
-			inserts: inserts,
-			end_inserts: end_inserts
-		}));
-	}
+fn removed_children<'root, 'node: 'root, M: 'static + Debug>(
+    old: &'node [Child<M>],
+    patches: &mut Vec<Patch<'root, Path, M>>,
+    path: &mut Path,
+) {
+    for (pos, child) in old.iter().enumerate() {
+        path.push(pos);
+        removed_child(child, patches, path);
+        path.pop();
+    }
 }
 
+/// Diff the children of the node or fragment at `path`.
+fn diff_children<'root, 'node: 'root, M: 'static + Debug>(
+    old_children: &'node [Child<M>],
+    new_children: &'node [Child<M>],
+    patches: &mut Vec<Patch<'root, Path, M>>,
+    path: &mut Path,
+) {
+    use self::Operation::*;
 
-////////////  CHANGES FROM KEYED DIFF  ////////////
+    if is_keyed(old_children) && is_keyed(new_children) {
+        return diff_keyed_children(old_children, new_children, patches, path);
+    }
 
-fn insert_node(changes, local_patches, key, vnode, new_index, inserts)
-{
-	let entry = changes[key];
+    let old_len = old_children.len();
+    let new_len = new_children.len();
 
-	// never seen this key before
-	if (typeof entry === 'undefined')
-	{
-		entry = {
-			tag: 'insert',
-			vnode: vnode,
-			index: new_index,
-			data: undefined
-		};
+    // Figure out if there are inserts or removals
 
-		inserts.push({ index: new_index, entry: entry });
-		changes[key] = entry;
+    if old_len > new_len {
+        patches.push(RemoveLast((old_len - new_len) as i32).at(path));
+    } else if old_len < new_len {
+        patches.push(Append(&new_children[old_len..]).at(path));
+    }
 
-		return;
-	}
+    for (pos, old_child) in old_children.iter().enumerate() {
+        path.push(pos);
+        match (old_child, new_children.get(pos)) {
+            (&Child::Text(ref old_text), Some(&Child::Text(ref new_text))) => {
+                if old_text != new_text {
+                    patches.push(ReplaceText(new_text.as_str()).at(path))
+                }
+            }
+            (&Child::Fragment(ref old_fragment), Some(&Child::Fragment(ref new_fragment))) => {
+                diff_children(&old_fragment.children, &new_fragment.children, patches, path)
+            }
+            (_, Some(&Child::Text(ref new_text))) => {
+                patches.push(ReplaceWithText(new_text.as_str()).at(path));
+                removed_child(old_child, patches, path);
+            }
+            (_, Some(&Child::Fragment(ref new_fragment))) => {
+                patches.push(ReplaceWithFragment(&new_fragment.children).at(path));
+                removed_child(old_child, patches, path);
+            }
+            (&Child::Text(_), Some(new_child)) | (&Child::Fragment(_), Some(new_child)) => {
+                patches.push(ReplaceNode(new_child.node().unwrap()).at(path));
+                removed_child(old_child, patches, path);
+            }
+            (_, Some(new_child)) => {
+                diff_node(old_child.node().unwrap(), new_child.node().unwrap(), patches, path)
+            }
+            // removed by the `RemoveLast` above
+            (_, None) => removed_child(old_child, patches, path),
+        }
+        path.pop();
+    }
+}
 
-	// this key was removed earlier, old match!
-	if (entry.tag === 'remove')
-	{
-		inserts.push({ index: new_index, entry: entry });
+////////////  KEYED DIFF  ////////////
 
-		entry.tag = 'move';
-		let sub_patches = Vec::new();
-		diff_node(entry.vnode, vnode, sub_patches, entry.index);
-		entry.index = new_index;
-		entry.data.data = {
-			patches: sub_patches,
-			entry: entry
-		};
+fn is_keyed<M>(children: &[Child<M>]) -> bool {
+    !children.is_empty() && children.iter().all(|child| child.key().is_some())
+}
 
-		return;
-	}
+/// Diff two lists of keyed children.
+///
+/// Children are matched up by key rather than by position. Matched pairs are
+/// diffed against each other at the path of the old child, and a single
+/// `Reorder` patch on the parent describes how to move, insert and remove DOM
+/// nodes so their order matches the new list. Because `apply` resolves every
+/// patch path to a DOM node before mutating anything, patches for moved
+/// children still land on the right node.
+fn diff_keyed_children<'root, 'node: 'root, M: 'static + Debug>(
+    old_children: &'node [Child<M>],
+    new_children: &'node [Child<M>],
+    patches: &mut Vec<Patch<'root, Path, M>>,
+    path: &mut Path,
+) {
+    use std::collections::HashMap;
+    use std::collections::VecDeque;
+    use self::KeyedOp::*;
+
+    // The old positions for each key, in order so that duplicate keys are
+    // matched up first-come first-served.
+    let mut old_positions: HashMap<&str, VecDeque<usize>> = HashMap::new();
+    for (pos, child) in old_children.iter().enumerate() {
+        old_positions
+            .entry(child.key().unwrap())
+            .or_insert_with(VecDeque::new)
+            .push_back(pos);
+    }
 
-	// this key has already been inserted or moved, old duplicate!
-	insert_node(changes, local_patches, key + POSTFIX, vnode, new_index, inserts);
-}
+    let mut ops = Vec::with_capacity(new_children.len());
+    let mut child_patches = Vec::new();
+    let mut kept = vec![false; old_children.len()];
+    let mut moved = old_children.len() != new_children.len();
+
+    for (to, new_child) in new_children.iter().enumerate() {
+        let new_node = new_child.node().unwrap();
+        let from = old_positions.get_mut(new_child.key().unwrap()).and_then(
+            |positions| positions.pop_front(),
+        );
+        match from {
+            Some(from) => {
+                kept[from] = true;
+                moved = moved || from != to;
+                path.push(from);
+                diff_node(old_children[from].node().unwrap(), new_node, &mut child_patches, path);
+                path.pop();
+                ops.push(Move(from as i32, to as i32));
+            }
+            None => {
+                moved = true;
+                ops.push(Insert(to as i32, new_node));
+            }
+        }
+    }
 
+    for (from, was_kept) in kept.into_iter().enumerate() {
+        if !was_kept {
+            ops.push(Remove(from as i32));
+            path.push(from);
+            removed(old_children[from].node().unwrap(), &mut child_patches, path);
+            path.pop();
+        }
+    }
 
-fn remove_node(changes, local_patches, key, vnode, index)
-{
-	let entry = changes[key];
-
-	// never seen this key before
-	if (typeof entry === 'undefined')
-	{
-		let patch = Patch(index, Remove)undefined);
-		local_patches.push(patch);
-
-		changes[key] = {
-			tag: 'remove',
-			vnode: vnode,
-			index: index,
-			data: patch
-		};
-
-		return;
-	}
-
-	// this key was inserted earlier, old match!
-	if (entry.tag === 'insert')
-	{
-		entry.tag = 'move';
-		let sub_patches = Vec::new();
-		diff_node(vnode, entry.vnode, sub_patches, index);
-
-		let patch = Patch(index, Remove){
-			patches: sub_patches,
-			entry: entry
-		});
-		local_patches.push(patch);
-
-		return;
-	}
-
-	// this key has already been removed or moved, old duplicate!
-	remove_node(changes, local_patches, key + POSTFIX, vnode, index);
+    if moved {
+        patches.push(Operation::Reorder(ops).at(path));
+    }
+    patches.extend(child_patches);
 }
-*/
@@ -15,6 +15,161 @@ fn test_diff_changed_tag() {
 
     assert_eq!(
         diff(&old_node, &new_node),
-        vec![ ReplaceNode(&new_node).at(&0) ]
+        vec![ ReplaceNode(&new_node).at(&[]) ]
     );
-}
\ No newline at end of file
+}
+#[test]
+fn test_diff_keyed_unchanged() {
+    let old_node: Node<()> = vdom!(
+        ul [
+            keyed!("a", vdom!(li [ text!("a") ]))
+            keyed!("b", vdom!(li [ text!("b") ]))
+        ]
+    );
+
+    let new_node: Node<()> = vdom!(
+        ul [
+            keyed!("a", vdom!(li [ text!("a") ]))
+            keyed!("b", vdom!(li [ text!("b") ]))
+        ]
+    );
+
+    assert_eq!(diff(&old_node, &new_node), vec![]);
+}
+
+#[test]
+fn test_diff_keyed_reorder() {
+    use super::diff::KeyedOp::*;
+
+    let old_node: Node<()> = vdom!(
+        ul [
+            keyed!("a", vdom!(li [ text!("a") ]))
+            keyed!("b", vdom!(li [ text!("b") ]))
+            keyed!("c", vdom!(li [ text!("c") ]))
+        ]
+    );
+
+    let new_node: Node<()> = vdom!(
+        ul [
+            keyed!("c", vdom!(li [ text!("c") ]))
+            keyed!("a", vdom!(li [ text!("changed") ]))
+            keyed!("d", vdom!(li [ text!("d") ]))
+        ]
+    );
+
+    let inserted = new_node.children[2].node().unwrap();
+
+    assert_eq!(
+        diff(&old_node, &new_node),
+        vec![
+            Reorder(vec![Move(2, 0), Move(0, 1), Insert(2, inserted), Remove(1)]).at(&[]),
+            ReplaceText("changed").at(&[0, 0]),
+        ]
+    );
+}
+
+#[test]
+fn test_diff_listeners() {
+    use std::any::Any;
+    use super::events::Event;
+
+    struct Click;
+    struct Focus;
+
+    impl Event for Click {
+        fn event_type() -> &'static str { "click" }
+        fn from_raw(_: &Any) -> Option<Self> { None }
+    }
+
+    impl Event for Focus {
+        fn event_type() -> &'static str { "focus" }
+        fn from_raw(_: &Any) -> Option<Self> { None }
+    }
+
+    let old_node: Node<()> = vdom!(
+        button { on Click |_evt| (); on Focus |_evt| () }
+    );
+
+    let new_node: Node<()> = vdom!(
+        button { on Click |_evt| () }
+    );
+
+    assert_eq!(
+        diff(&old_node, &new_node),
+        vec![
+            ReplaceListener(&*old_node.listeners[0], &*new_node.listeners[0]).at(&[]),
+            RemoveListener(&*old_node.listeners[1]).at(&[]),
+        ]
+    );
+}
+
+#[test]
+fn test_diff_styles() {
+    let old_node: Node<()> = vdom!(
+        p { style = vec![("color", "red"), ("margin-top", "4px"), ("display", "block")] }
+    );
+
+    let new_node: Node<()> = vdom!(
+        p { style = vec![("color", "blue"), ("display", "block"), ("font-weight", "bold")] }
+    );
+
+    let mut patches = diff(&old_node, &new_node);
+    patches.sort_by_key(|patch| format!("{:?}", patch.operation));
+    assert_eq!(
+        patches,
+        vec![
+            RemoveStyle("margin-top").at(&[]),
+            SetStyle("color", "blue").at(&[]),
+            SetStyle("font-weight", "bold").at(&[]),
+        ]
+    );
+}
+
+#[test]
+fn test_diff_paths() {
+    let old_node: Node<()> = vdom!(
+        div [ p [ text!("a") ] ul [ li li [ text!("b") ] ] ]
+    );
+
+    let new_node: Node<()> = vdom!(
+        div [ p [ text!("a") ] ul [ li li [ text!("c") ] ] text!("d") ]
+    );
+
+    assert_eq!(
+        diff(&old_node, &new_node),
+        vec![
+            Append(&new_node.children[2..]).at(&[]),
+            ReplaceText("c").at(&[1, 1, 0]),
+        ]
+    );
+}
+
+#[test]
+fn test_diff_fragments() {
+    use super::node::Fragment;
+
+    fn items(names: &[&'static str]) -> Fragment<()> {
+        names.iter().map(|name| vdom!(li [ text!(*name) ])).collect()
+    }
+
+    let old_node: Node<()> = vdom!(
+        ul [ fragment!(items(&["a", "b"])) li [ text!("c") ] fragment!(items(&["d"])) ]
+    );
+    let new_node: Node<()> = vdom!(
+        ul [ fragment!(items(&["a", "x", "y"])) li [ text!("c") ] li [ text!("d") ] ]
+    );
+
+    // fragments are diffed child by child, siblings after them stay put
+    let appended = match new_node.children[0] {
+        super::Child::Fragment(ref fragment) => &fragment.children[2..],
+        _ => unreachable!(),
+    };
+    assert_eq!(
+        diff(&old_node, &new_node),
+        vec![
+            Append(appended).at(&[0]),
+            ReplaceText("x").at(&[0, 1, 0]),
+            ReplaceNode(new_node.children[2].node().unwrap()).at(&[2]),
+        ]
+    );
+}
@@ -0,0 +1,144 @@
+//! The DOM nodes created for a virtual tree, kept in the same shape.
+//!
+//! `create_tree` (and `hydrate`) record the DOM node for every virtual node
+//! they render, and `apply` keeps the record up to date as it adds, moves and
+//! removes nodes. Patches are applied to the recorded nodes directly instead
+//! of looking them up in the document.
+//!
+//! A fragment has no DOM node of its own: its children are children of the
+//! nearest element above it. Its entry holds an empty text node placed after
+//! them instead, the anchor, which gives the fragment a position among its
+//! siblings even while it is empty.
+
+use std::cell::RefCell;
+use std::mem;
+use std::rc::Rc;
+
+/// The DOM node rendered for one virtual node and the trees rendered for its
+/// children, in order. Text nodes have no children, fragments hold their
+/// anchor.
+///
+/// A `DomTree` is a shared handle: clones refer to the same entry, so a
+/// handle to a node stays valid while its parent's children are moved around
+/// or the node itself is replaced.
+pub struct DomTree<N>(Rc<RefCell<Entry<N>>>);
+
+struct Entry<N> {
+    node: N,
+    children: Vec<DomTree<N>>,
+    fragment: bool,
+}
+
+impl<N: Clone> DomTree<N> {
+    pub fn new(node: N, children: Vec<DomTree<N>>) -> Self {
+        DomTree(Rc::new(RefCell::new(Entry {
+            node: node,
+            children: children,
+            fragment: false,
+        })))
+    }
+
+    pub fn leaf(node: N) -> Self {
+        DomTree::new(node, Vec::new())
+    }
+
+    /// The tree of a fragment whose children are followed by `anchor`.
+    pub fn fragment(anchor: N, children: Vec<DomTree<N>>) -> Self {
+        DomTree(Rc::new(RefCell::new(Entry {
+            node: anchor,
+            children: children,
+            fragment: true,
+        })))
+    }
+
+    pub fn is_fragment(&self) -> bool {
+        self.0.borrow().fragment
+    }
+
+    /// The DOM nodes this tree puts into its parent, in order: the node
+    /// itself, or for a fragment the nodes of its children and the anchor.
+    pub fn nodes(&self) -> Vec<N> {
+        let entry = self.0.borrow();
+        if !entry.fragment {
+            return vec![entry.node.clone()];
+        }
+        let mut nodes: Vec<N> = entry.children.iter().flat_map(DomTree::nodes).collect();
+        nodes.push(entry.node.clone());
+        nodes
+    }
+
+    /// The first of `nodes`, for inserting siblings before this tree.
+    pub fn first_node(&self) -> N {
+        let entry = self.0.borrow();
+        match entry.children.first() {
+            Some(child) if entry.fragment => child.first_node(),
+            _ => entry.node.clone(),
+        }
+    }
+
+    pub fn node(&self) -> N {
+        self.0.borrow().node.clone()
+    }
+
+    pub fn children(&self) -> Vec<DomTree<N>> {
+        self.0.borrow().children.clone()
+    }
+
+    pub fn child(&self, pos: usize) -> Option<DomTree<N>> {
+        self.0.borrow().children.get(pos).cloned()
+    }
+
+    /// The tree at `path` below this one, see `diff::Path`.
+    pub fn find(&self, path: &[usize]) -> Option<DomTree<N>> {
+        let mut tree = self.clone();
+        for &pos in path {
+            tree = match tree.child(pos) {
+                Some(child) => child,
+                None => return None,
+            };
+        }
+        Some(tree)
+    }
+
+    /// Like `find`, along with the DOM node the nodes of the tree found are
+    /// children of: the node of the closest element on the way. `None` for
+    /// this tree itself, whose parent isn't known.
+    pub fn find_with_parent(&self, path: &[usize]) -> Option<(DomTree<N>, Option<N>)> {
+        let mut tree = self.clone();
+        let mut parent = None;
+        for &pos in path {
+            if !tree.is_fragment() {
+                parent = Some(tree.node());
+            }
+            tree = match tree.child(pos) {
+                Some(child) => child,
+                None => return None,
+            };
+        }
+        Some((tree, parent))
+    }
+
+    pub fn push(&self, child: DomTree<N>) {
+        self.0.borrow_mut().children.push(child);
+    }
+
+    pub fn pop(&self) -> Option<DomTree<N>> {
+        self.0.borrow_mut().children.pop()
+    }
+
+    pub fn set_children(&self, children: Vec<DomTree<N>>) {
+        self.0.borrow_mut().children = children;
+    }
+
+    /// Make this entry describe what `other` rendered, for when the node was
+    /// replaced. Handles to this entry see the new node.
+    pub fn replace(&self, other: DomTree<N>) {
+        mem::swap(&mut *self.0.borrow_mut(), &mut *other.0.borrow_mut());
+    }
+}
+
+impl<N> Clone for DomTree<N> {
+    fn clone(&self) -> Self {
+        DomTree(self.0.clone())
+    }
+}
@@ -0,0 +1,561 @@
+//! Stateful components nested inside another component's view.
+//!
+//! An embedded component is described in a view by an `Embedded`, which only
+//! knows how to create the component. Before a new view is diffed, `resolve`
+//! walks it alongside the previous one and hands each `Embedded` the instance
+//! found at the same place in the old tree, then fills in what it renders.
+//!
+//! Messages for an embedded component, from its listeners, commands and
+//! subscriptions alike, go to its own `update` (and its `emit`), after which
+//! the root re-renders just that component. While an instance
+//! hasn't changed, its rendered tree is shared between renders and `diff`
+//! skips it.
+
+use std::any::Any;
+use std::boxed::FnBox;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::mem;
+use std::rc::Rc;
+
+use futures::Future;
+
+use super::backend::{EventHandler, Executor, GlobalTarget, ListenerHandle};
+use super::component::{Callback, Component};
+use super::diff::Path;
+use super::node::{Node, Child};
+use super::sub::Subscriptions;
+
+/// Converts the callback for one message type that listeners are installed
+/// with into a callback for the message type of a mapped view, e.g. by
+/// tagging each message, see `MappedVListener`.
+pub type ListenerMapper<From, To> = Rc<Fn(Callback<To>) -> Callback<From>>;
+
+/// What embedded component instances need from the root they're rendered
+/// under: a way to be re-rendered and somewhere to run their commands.
+pub trait Host: Executor {
+    /// Re-render the component instance with the given id.
+    fn redraw(&self, id: usize);
+}
+
+/// The host for trees that are rendered once and never updated.
+struct Detached;
+
+impl Executor for Detached {
+    fn set_timeout(&self, _: u32, _: Box<FnBox()>) {}
+
+    fn set_interval(&self, _: u32, _: Rc<Fn()>) -> ListenerHandle {
+        ListenerHandle::new(|| {})
+    }
+
+    fn spawn(&self, _: Box<Future<Item = (), Error = ()>>) {}
+
+    fn add_global_listener(&self, _: GlobalTarget, _: &'static str, _: EventHandler) -> ListenerHandle {
+        ListenerHandle::new(|| {})
+    }
+}
+
+impl Host for Detached {
+    fn redraw(&self, _: usize) {}
+}
+
+/// A type-erased embedded component, as seen from its parent's view.
+pub trait Embed<Msg> {
+    /// Identifies the component instance, 0 before there is one.
+    fn id(&self) -> usize;
+    /// Whether the instance received messages since it was last rendered.
+    fn is_dirty(&self) -> bool;
+    fn instance(&self) -> Option<Rc<Any>>;
+    /// Take over an existing instance, if it is the right kind of component.
+    fn adopt(&self, instance: Rc<Any>);
+    /// Render the component, creating the instance if needed. `parent`
+    /// receives what the component emits.
+    fn render(&self, host: &Rc<Host>, parent: &Callback<Msg>) -> Node<Msg>;
+}
+
+pub struct Embedded<Msg: 'static> {
+    embed: Box<Embed<Msg>>,
+    rendered: Option<Rc<Node<Msg>>>,
+    /// Inherited from the parent node, for the rendered tree.
+    namespace: Option<&'static str>,
+}
+
+impl<Msg: 'static> Embedded<Msg> {
+    pub fn new<C, ChildMsg, F>(init: F) -> Self
+    where
+        C: Component<ChildMsg>,
+        ChildMsg: 'static,
+        F: FnOnce() -> C + 'static,
+    {
+        Embedded::from_embed(Box::new(Embedding::<C, ChildMsg, Msg>::new(Box::new(init), None)))
+    }
+
+    pub fn with_emit<C, ChildMsg, F, E>(init: F, emit: E) -> Self
+    where
+        C: Component<ChildMsg>,
+        ChildMsg: 'static,
+        F: FnOnce() -> C + 'static,
+        E: Fn(&ChildMsg) -> Option<Msg> + 'static,
+    {
+        Embedded::from_embed(Box::new(
+            Embedding::<C, ChildMsg, Msg>::new(Box::new(init), Some(Rc::new(emit))),
+        ))
+    }
+
+    fn from_embed(embed: Box<Embed<Msg>>) -> Self {
+        Embedded {
+            embed: embed,
+            rendered: None,
+            namespace: None,
+        }
+    }
+
+    /// What the component rendered, once resolved.
+    pub fn rendered(&self) -> Option<&Node<Msg>> {
+        self.rendered.as_ref().map(|node| &**node)
+    }
+
+    pub fn rendered_rc(&self) -> Option<Rc<Node<Msg>>> {
+        self.rendered.clone()
+    }
+
+    pub fn id(&self) -> usize {
+        self.embed.id()
+    }
+
+    /// Whether the instance received messages since it was last rendered.
+    pub fn is_dirty(&self) -> bool {
+        self.embed.is_dirty()
+    }
+
+    /// Render the component again, creating the instance if needed.
+    pub fn render(&self, host: &Rc<Host>, parent: &Callback<Msg>) -> Node<Msg> {
+        let mut node = self.embed.render(host, parent);
+        if let Some(namespace) = self.namespace {
+            node.inherit_namespace(namespace);
+        }
+        node
+    }
+
+    pub fn inherit_namespace(&mut self, namespace: &'static str) {
+        if self.namespace.is_none() {
+            self.namespace = Some(namespace);
+        }
+    }
+
+    /// Render the component without keeping track of it, for when there is no
+    /// root to redraw it (e.g. rendering to HTML).
+    pub fn render_detached(&self) -> Node<Msg> {
+        let host = Rc::new(Detached) as Rc<Host>;
+        let parent: Callback<Msg> = Rc::new(|_| {});
+        let mut node = self.render(&host, &parent);
+        resolve(&mut node, None, &host, &parent);
+        node
+    }
+
+    pub fn map_listeners<To: 'static>(self, mapper: ListenerMapper<Msg, To>) -> Embedded<To> {
+        let mut embedded = Embedded::from_embed(Box::new(MappedEmbed {
+            inner: self.embed,
+            mapper: mapper,
+        }));
+        embedded.namespace = self.namespace;
+        embedded
+    }
+}
+
+/// Hand every embedded component in `new` the instance at the same position
+/// (or under the same key) in `old`, and render them. Components that haven't
+/// changed reuse their rendered tree from `old`. What they emit goes to
+/// `parent`, the callback `new`'s listeners are installed with.
+pub fn resolve<Msg>(
+    new: &mut Node<Msg>,
+    old: Option<&Node<Msg>>,
+    host: &Rc<Host>,
+    parent: &Callback<Msg>,
+) {
+    let old_children: &[Child<Msg>] = old.map(|old| &old.children[..]).unwrap_or(&[]);
+    resolve_children(&mut new.children, old_children, host, parent);
+}
+
+fn resolve_children<Msg>(
+    new: &mut [Child<Msg>],
+    old_children: &[Child<Msg>],
+    host: &Rc<Host>,
+    parent: &Callback<Msg>,
+) {
+    let old_keyed: HashMap<&str, &Child<Msg>> = old_children
+        .iter()
+        .filter_map(|child| child.key().map(|key| (key, child)))
+        .collect();
+
+    for (pos, child) in new.iter_mut().enumerate() {
+        let old_child = match child.key() {
+            Some(key) => old_keyed.get(key).cloned(),
+            None => old_children.get(pos),
+        };
+        match *child {
+            Child::Text(_) => {}
+            Child::Node(ref mut node) | Child::Keyed(_, ref mut node) => {
+                resolve(node, old_child.and_then(Child::node), host, parent);
+            }
+            Child::Component(ref mut embedded) => {
+                let old_embedded = match old_child {
+                    Some(&Child::Component(ref old_embedded)) => Some(old_embedded),
+                    _ => None,
+                };
+                resolve_embedded(embedded, old_embedded, host, parent);
+            }
+            Child::Lazy(ref mut lazy) => {
+                let old_lazy = match old_child {
+                    Some(&Child::Lazy(ref old_lazy)) => Some(old_lazy),
+                    _ => None,
+                };
+                lazy.resolve(old_lazy, host, parent);
+            }
+            Child::Fragment(ref mut fragment) => {
+                let old_children: &[Child<Msg>] = match old_child {
+                    Some(&Child::Fragment(ref old_fragment)) => &old_fragment.children,
+                    _ => &[],
+                };
+                resolve_children(&mut fragment.children, old_children, host, parent);
+            }
+        }
+    }
+}
+
+fn resolve_embedded<Msg>(
+    new: &mut Embedded<Msg>,
+    old: Option<&Embedded<Msg>>,
+    host: &Rc<Host>,
+    parent: &Callback<Msg>,
+) {
+    if let Some(old) = old {
+        if let Some(instance) = old.embed.instance() {
+            new.embed.adopt(instance);
+        }
+        if new.id() == old.id() && !new.embed.is_dirty() {
+            if let Some(rendered) = take_over(&old.rendered) {
+                new.rendered = Some(rendered);
+                return;
+            }
+        }
+    }
+
+    let mut rendered = new.render(host, parent);
+    resolve(&mut rendered, old.and_then(Embedded::rendered), host, parent);
+    new.rendered = Some(Rc::new(rendered));
+}
+
+/// Share a tree rendered for the old view with the new one, unless another
+/// child of the new view already took it over (e.g. with a duplicate key).
+/// Every rendered tree is only used in one place that way, so a single
+/// component's redraw can replace what it rendered in place.
+pub fn take_over<Msg>(rendered: &Option<Rc<Node<Msg>>>) -> Option<Rc<Node<Msg>>> {
+    match *rendered {
+        Some(ref rendered) if Rc::strong_count(rendered) == 1 => Some(rendered.clone()),
+        _ => None,
+    }
+}
+
+/// Find the embedded component instance `id` in `node`, along with the path
+/// of the node it rendered.
+pub fn find<Msg>(node: &Node<Msg>, id: usize) -> Option<(Path, &Embedded<Msg>)> {
+    let mut path = Vec::new();
+    find_from(&node.children, id, &mut path).map(|embedded| (path, embedded))
+}
+
+fn find_from<'a, Msg>(
+    children: &'a [Child<Msg>],
+    id: usize,
+    path: &mut Path,
+) -> Option<&'a Embedded<Msg>> {
+    for (pos, child) in children.iter().enumerate() {
+        path.push(pos);
+        if let Child::Component(ref embedded) = *child {
+            if embedded.id() == id {
+                return Some(embedded);
+            }
+        }
+        let found = match *child {
+            Child::Fragment(ref fragment) => find_from(&fragment.children, id, path),
+            _ => child.node().and_then(|node| find_from(&node.children, id, path)),
+        };
+        if found.is_some() {
+            return found;
+        }
+        path.pop();
+    }
+    None
+}
+
+/// Where the embedded component instance `id` in `node` keeps what it
+/// rendered, for replacing it. `None` if it isn't there, or if getting to it
+/// goes through a rendered tree that is still shared with another tree.
+pub fn rendered_slot<Msg>(node: &mut Node<Msg>, id: usize) -> Option<&mut Option<Rc<Node<Msg>>>> {
+    children_rendered_slot(&mut node.children, id)
+}
+
+fn children_rendered_slot<Msg>(
+    children: &mut [Child<Msg>],
+    id: usize,
+) -> Option<&mut Option<Rc<Node<Msg>>>> {
+    for child in children.iter_mut() {
+        let slot = match *child {
+            Child::Text(_) => None,
+            Child::Node(ref mut node) | Child::Keyed(_, ref mut node) => rendered_slot(node, id),
+            Child::Component(ref mut embedded) => {
+                if embedded.id() == id {
+                    return Some(&mut embedded.rendered);
+                }
+                embedded
+                    .rendered
+                    .as_mut()
+                    .and_then(Rc::get_mut)
+                    .and_then(|own| rendered_slot(own, id))
+            }
+            Child::Lazy(ref mut lazy) => lazy.rendered_mut().and_then(|own| rendered_slot(own, id)),
+            Child::Fragment(ref mut fragment) => children_rendered_slot(&mut fragment.children, id),
+        };
+        if slot.is_some() {
+            return slot;
+        }
+    }
+    None
+}
+
+/// Sets a flag for as long as it lives, so that it is cleared again even if
+/// a component panics.
+pub struct Raised<'a>(&'a Cell<bool>);
+
+impl<'a> Raised<'a> {
+    pub fn new(flag: &'a Cell<bool>) -> Self {
+        flag.set(true);
+        Raised(flag)
+    }
+}
+
+impl<'a> Drop for Raised<'a> {
+    fn drop(&mut self) {
+        self.0.set(false);
+    }
+}
+
+////////////  INSTANCES  ////////////
+
+thread_local! {
+    // 0 is the id of an `Embed` without an instance
+    static NEXT_INSTANCE_ID: Cell<usize> = Cell::new(1);
+}
+
+struct Instance<C, ChildMsg, Msg> {
+    /// Never reused, so a redraw still pending for a dropped instance can't
+    /// reach another one.
+    id: usize,
+    comp: RefCell<C>,
+    queue: RefCell<VecDeque<ChildMsg>>,
+    updating: Cell<bool>,
+    subs: Subscriptions<ChildMsg>,
+    dirty: Cell<bool>,
+    host: RefCell<Option<Rc<Host>>>,
+    /// The `emit` of the view that last rendered the component, and where
+    /// what it returns goes.
+    emit: RefCell<Option<Rc<Fn(&ChildMsg) -> Option<Msg>>>>,
+    parent: RefCell<Option<Callback<Msg>>>,
+}
+
+impl<C, ChildMsg, Msg> Instance<C, ChildMsg, Msg>
+where
+    C: Component<ChildMsg>,
+    ChildMsg: 'static,
+    Msg: 'static,
+{
+    /// Every message for the component comes through here, whether from its
+    /// listeners, commands or subscriptions: `emit` sees it, then `update`
+    /// gets it, then the parent gets whatever `emit` returned.
+    fn send(this: &Rc<Self>, msg: ChildMsg) {
+        let emit = this.emit.borrow().clone();
+        let parent_msg = emit.and_then(|emit| emit(&msg));
+        Instance::update(this, msg);
+        if let Some(parent_msg) = parent_msg {
+            let parent = this.parent.borrow().clone();
+            if let Some(parent) = parent {
+                parent(parent_msg);
+            }
+        }
+    }
+
+    /// Messages sent from inside `update` are queued, like `Root::send`.
+    fn update(this: &Rc<Self>, msg: ChildMsg) {
+        this.queue.borrow_mut().push_back(msg);
+        if this.updating.get() {
+            return;
+        }
+
+        {
+            let _updating = Raised::new(&this.updating);
+            loop {
+                let next = this.queue.borrow_mut().pop_front();
+                match next {
+                    Some(msg) => {
+                        let cmd = this.comp.borrow_mut().update(msg);
+                        let host = this.host.borrow().clone();
+                        if let Some(host) = host {
+                            let recur: Callback<ChildMsg> = {
+                                let this = this.clone();
+                                Rc::new(move |msg| Instance::send(&this, msg))
+                            };
+                            cmd.run(&*host, recur);
+                        }
+                    }
+                    None => break,
+                }
+            }
+        }
+
+        Instance::subscribe(this);
+        this.dirty.set(true);
+        let host = this.host.borrow().clone();
+        if let Some(host) = host {
+            host.redraw(this.id);
+        }
+    }
+
+    /// Install the component's subscriptions on its host. Their messages
+    /// don't keep the instance alive, so they stop once it's dropped.
+    fn subscribe(this: &Rc<Self>) {
+        let host = match this.host.borrow().clone() {
+            Some(host) => host,
+            None => return,
+        };
+        let sub = this.comp.borrow().subscriptions();
+        let weak = Rc::downgrade(this);
+        this.subs.update(sub, &*host, Rc::new(move |msg| if let Some(this) = weak.upgrade() {
+            Instance::send(&this, msg)
+        }));
+    }
+}
+
+struct Embedding<C, ChildMsg, Msg> {
+    init: RefCell<Option<Box<FnBox() -> C>>>,
+    instance: RefCell<Option<Rc<Instance<C, ChildMsg, Msg>>>>,
+    emit: Option<Rc<Fn(&ChildMsg) -> Option<Msg>>>,
+}
+
+impl<C, ChildMsg, Msg> Embedding<C, ChildMsg, Msg>
+where
+    C: Component<ChildMsg>,
+    ChildMsg: 'static,
+    Msg: 'static,
+{
+    fn new(init: Box<FnBox() -> C>, emit: Option<Rc<Fn(&ChildMsg) -> Option<Msg>>>) -> Self {
+        Embedding {
+            init: RefCell::new(Some(init)),
+            instance: RefCell::new(None),
+            emit: emit,
+        }
+    }
+
+    fn get_instance(&self) -> Rc<Instance<C, ChildMsg, Msg>> {
+        let mut instance = self.instance.borrow_mut();
+        if instance.is_none() {
+            let init = self.init.borrow_mut().take().expect(
+                "embedded component was already initialized",
+            );
+            *instance = Some(Rc::new(Instance {
+                id: NEXT_INSTANCE_ID.with(|next| {
+                    let id = next.get();
+                    next.set(id + 1);
+                    id
+                }),
+                comp: RefCell::new(init()),
+                queue: RefCell::new(VecDeque::new()),
+                updating: Cell::new(false),
+                subs: Subscriptions::new(),
+                dirty: Cell::new(false),
+                host: RefCell::new(None),
+                emit: RefCell::new(None),
+                parent: RefCell::new(None),
+            }));
+        }
+        instance.as_ref().unwrap().clone()
+    }
+}
+
+impl<C, ChildMsg, Msg> Embed<Msg> for Embedding<C, ChildMsg, Msg>
+where
+    C: Component<ChildMsg>,
+    ChildMsg: 'static,
+    Msg: 'static,
+{
+    fn id(&self) -> usize {
+        self.instance.borrow().as_ref().map(|i| i.id).unwrap_or(0)
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.instance.borrow().as_ref().map(|i| i.dirty.get()).unwrap_or(true)
+    }
+
+    fn instance(&self) -> Option<Rc<Any>> {
+        self.instance.borrow().as_ref().map(|i| i.clone() as Rc<Any>)
+    }
+
+    fn adopt(&self, instance: Rc<Any>) {
+        if let Ok(instance) = instance.downcast::<Instance<C, ChildMsg, Msg>>() {
+            *self.instance.borrow_mut() = Some(instance);
+        }
+    }
+
+    fn render(&self, host: &Rc<Host>, parent: &Callback<Msg>) -> Node<Msg> {
+        let instance = self.get_instance();
+        *instance.emit.borrow_mut() = self.emit.clone();
+        *instance.parent.borrow_mut() = Some(parent.clone());
+        let first = mem::replace(&mut *instance.host.borrow_mut(), Some(host.clone())).is_none();
+        if first {
+            Instance::subscribe(&instance);
+        }
+        instance.dirty.set(false);
+
+        // the component's listeners send to the instance, which passes what
+        // `emit` returns on to `parent`
+        let view = instance.comp.borrow().view();
+        view.map_listeners(Rc::new(move |_| {
+            let instance = instance.clone();
+            Rc::new(move |msg| Instance::send(&instance, msg))
+        }))
+    }
+}
+
+/// An `Embed` whose rendered listeners are passed through a mapper, for
+/// components nested inside other embedded components or `Node::map`.
+struct MappedEmbed<From: 'static, To> {
+    inner: Box<Embed<From>>,
+    mapper: ListenerMapper<From, To>,
+}
+
+impl<From, To> Embed<To> for MappedEmbed<From, To>
+where
+    From: 'static,
+    To: 'static,
+{
+    fn id(&self) -> usize {
+        self.inner.id()
+    }
+
+    fn is_dirty(&self) -> bool {
+        self.inner.is_dirty()
+    }
+
+    fn instance(&self) -> Option<Rc<Any>> {
+        self.inner.instance()
+    }
+
+    fn adopt(&self, instance: Rc<Any>) {
+        self.inner.adopt(instance)
+    }
+
+    fn render(&self, host: &Rc<Host>, parent: &Callback<To>) -> Node<To> {
+        self.inner
+            .render(host, &(self.mapper)(parent.clone()))
+            .map_listeners(self.mapper.clone())
+    }
+}
@@ -0,0 +1,238 @@
+use std::cell::Cell;
+use std::panic::{self, AssertUnwindSafe};
+
+use super::cmd::Cmd;
+use super::component::Component;
+use super::embed::Embedded;
+use super::mock::{MockBackend, MockNode};
+use super::test_support::{mount_sync, Click};
+use super::Node;
+
+thread_local! {
+    static VIEWS: Cell<usize> = Cell::new(0);
+}
+
+fn views() -> usize {
+    VIEWS.with(Cell::get)
+}
+
+struct Counter(i32);
+
+#[derive(Debug)]
+struct Inc;
+
+impl Component<Inc> for Counter {
+    fn view(&self) -> Node<Inc> {
+        vdom!(button { on Click |_evt| Inc } [ format!("{}", self.0) ])
+    }
+
+    fn update(&mut self, _: Inc) -> Cmd<Inc> {
+        self.0 += 1;
+        Cmd::none()
+    }
+}
+
+/// Shows two counters, and how many times the second one was clicked.
+struct Parent {
+    second_clicks: i32,
+    show_first: bool,
+}
+
+#[derive(Debug)]
+enum ParentMsg {
+    SecondClicked,
+    HideFirst,
+}
+
+impl Component<ParentMsg> for Parent {
+    fn view(&self) -> Node<ParentMsg> {
+        VIEWS.with(|v| v.set(v.get() + 1));
+        let mut node = vdom!(div [
+            p [ format!("{}", self.second_clicks) ]
+            button { on Click |_evt| ParentMsg::HideFirst } [ text!("hide") ]
+        ]);
+        if self.show_first {
+            node.append_component(|| Counter(10));
+        }
+        node.append_component_with(|| Counter(20), |_: &Inc| Some(ParentMsg::SecondClicked));
+        node
+    }
+
+    fn update(&mut self, msg: ParentMsg) -> Cmd<ParentMsg> {
+        match msg {
+            ParentMsg::SecondClicked => self.second_clicks += 1,
+            ParentMsg::HideFirst => self.show_first = false,
+        }
+        Cmd::none()
+    }
+}
+
+fn render() -> MockNode {
+    // the root state is kept alive by the listeners it installed
+    mount_sync(Parent {
+        second_clicks: 0,
+        show_first: true,
+    }).1
+}
+
+#[test]
+fn test_embedded_component_updates_itself() {
+    let container = render();
+    let dnode = container.children()[0].clone();
+    assert_eq!(dnode.text_content(), "0hide1020");
+
+    let views_before = views();
+    let first = dnode.children()[2].clone();
+    first.dispatch(Click);
+    first.dispatch(Click);
+
+    assert_eq!(dnode.text_content(), "0hide1220");
+    assert_eq!(dnode.children()[2], first);
+    assert_eq!(views(), views_before);
+}
+
+#[test]
+fn test_embedded_component_emits_to_parent() {
+    let container = render();
+    let dnode = container.children()[0].clone();
+
+    dnode.children()[3].dispatch(Click);
+    assert_eq!(dnode.text_content(), "1hide1021");
+}
+
+#[test]
+fn test_embedded_component_instances_follow_position() {
+    let container = render();
+    let dnode = container.children()[0].clone();
+
+    dnode.children()[3].dispatch(Click);
+    dnode.children()[1].dispatch(Click);
+
+    // the second counter now sits where the first used to, which is where
+    // it's matched up by position, so it takes over the first's instance.
+    assert_eq!(dnode.text_content(), "1hide10");
+}
+
+#[test]
+fn test_embedded_component_ids_are_not_reused() {
+    let render_once = || {
+        let embedded: Embedded<()> = Embedded::new(|| Counter(0));
+        embedded.render_detached();
+        embedded.id()
+    };
+    // the second instance is likely allocated where the first one was
+    let first = render_once();
+    let second = render_once();
+    assert!(first != 0 && second != 0);
+    assert!(first != second);
+}
+
+/// Panics the first time it is updated.
+#[derive(Default)]
+struct Fragile {
+    panicked: bool,
+    count: i32,
+}
+
+impl Component<Inc> for Fragile {
+    fn view(&self) -> Node<Inc> {
+        vdom!(button { on Click |_evt| Inc } [ format!("{}", self.count) ])
+    }
+
+    fn update(&mut self, _: Inc) -> Cmd<Inc> {
+        if !self.panicked {
+            self.panicked = true;
+            panic!("update failed");
+        }
+        self.count += 1;
+        Cmd::none()
+    }
+}
+
+struct Holder;
+
+impl Component<()> for Holder {
+    fn view(&self) -> Node<()> {
+        let mut node = vdom!(div);
+        node.append_component(Fragile::default);
+        node
+    }
+
+    fn update(&mut self, _: ()) -> Cmd<()> {
+        Cmd::none()
+    }
+}
+
+#[test]
+fn test_embedded_component_recovers_from_a_panicking_update() {
+    let container = mount_sync(Holder).1;
+    let button = container.children()[0].children()[0].clone();
+
+    let clicked = panic::catch_unwind(AssertUnwindSafe(|| button.dispatch(Click)));
+    assert!(clicked.is_err());
+
+    // later messages are still handled, not queued behind the failed update
+    button.dispatch(Click);
+    assert_eq!(button.text_content(), "1");
+}
+
+#[derive(Debug)]
+enum Step {
+    Start,
+    Done,
+}
+
+/// Finishes 100ms after it is started.
+#[derive(Default)]
+struct Delayed {
+    done: bool,
+}
+
+impl Component<Step> for Delayed {
+    fn view(&self) -> Node<Step> {
+        let label = if self.done { "done" } else { "start" };
+        vdom!(button { on Click |_evt| Step::Start } [ text!(label) ])
+    }
+
+    fn update(&mut self, msg: Step) -> Cmd<Step> {
+        match msg {
+            Step::Start => return Cmd::delay(100, Step::Done),
+            Step::Done => self.done = true,
+        }
+        Cmd::none()
+    }
+}
+
+/// Counts how often its embedded component finished.
+#[derive(Default)]
+struct Watcher {
+    finished: i32,
+}
+
+impl Component<()> for Watcher {
+    fn view(&self) -> Node<()> {
+        let mut node = vdom!(div [ p [ format!("{}", self.finished) ] ]);
+        node.append_component_with(Delayed::default, |msg: &Step| match *msg {
+            Step::Done => Some(()),
+            Step::Start => None,
+        });
+        node
+    }
+
+    fn update(&mut self, _: ()) -> Cmd<()> {
+        self.finished += 1;
+        Cmd::none()
+    }
+}
+
+#[test]
+fn test_embedded_component_emits_messages_from_commands() {
+    let container = mount_sync(Watcher::default()).1;
+    let dnode = container.children()[0].clone();
+    assert_eq!(dnode.text_content(), "0start");
+
+    dnode.children()[1].dispatch(Click);
+    assert_eq!(dnode.text_content(), "0start");
+    MockBackend.advance_time(100);
+    assert_eq!(dnode.text_content(), "1done");
+}
@@ -1,69 +1,203 @@
 //! abstraction of stdweb::web::event
-use std::cell::RefCell;
+use std::any::Any;
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
 
-use stdweb::web::{Element, IEventTarget, EventListenerHandle};
+#[cfg(feature = "web")]
+use stdweb::Reference;
+#[cfg(feature = "web")]
 use stdweb::web::event::ConcreteEvent;
 
+use super::backend::{EventHandler, EventTarget, ListenerHandle};
+use super::embed::ListenerMapper;
+
+/// An event type that listeners can be registered for.
+pub trait Event: Sized + 'static {
+    fn event_type() -> &'static str;
+
+    /// Recover a typed event from whatever the backend dispatched.
+    fn from_raw(raw: &Any) -> Option<Self>;
+}
+
+#[cfg(feature = "web")]
+impl<T: ConcreteEvent> Event for T {
+    fn event_type() -> &'static str {
+        T::EVENT_TYPE
+    }
+
+    fn from_raw(raw: &Any) -> Option<Self> {
+        raw.downcast_ref::<Reference>().and_then(
+            |reference| reference.clone().downcast(),
+        )
+    }
+}
+
 pub trait VListener<Msg> {
     fn key(&self) -> &'static str;
-    fn install(&self, element: &Element, update: Rc<Fn(Msg)>);
-    fn remove(self);
+    /// Unique among the listeners created on this thread, for referring to
+    /// the listener from outside the tree, see `owned::ListenerId`. A
+    /// listener that adopts another one's handler takes its id as well.
+    fn id(&self) -> u64;
+    fn install(&self, target: &EventTarget, update: Rc<Fn(Msg)>);
+    /// Detach the handler installed by the last call to `install`, if any.
+    fn remove(&self);
+    /// Give up the handler installed by `install` without detaching it, for
+    /// the listener replacing this one to `adopt`.
+    fn take_installed(&self) -> Option<Installed>;
+    /// Take over `installed`: events it receives are dispatched to this
+    /// listener from now on. The DOM isn't touched.
+    fn adopt(&self, installed: Installed, update: Rc<Fn(Msg)>);
+}
+
+impl<'a, Msg> Debug for VListener<Msg> + 'a {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "VListener({:?})", self.key())
+    }
 }
 
-pub struct ConcreteVListener<Evt: ConcreteEvent, Msg> {
-    handle: RefCell<Option<EventListenerHandle>>,
+/// Listeners wrap closures, which can't be compared, so two listeners are only
+/// equal if they are the same listener.
+impl<'a, Msg> PartialEq for VListener<Msg> + 'a {
+    fn eq(&self, other: &Self) -> bool {
+        self as *const Self as *const () == other as *const Self as *const ()
+    }
+}
+
+thread_local! {
+    static NEXT_LISTENER_ID: Cell<u64> = Cell::new(0);
+}
+
+/// A handler attached to the DOM by `VListener::install`. It calls whatever
+/// closure it currently holds, so the listener it belongs to can be replaced
+/// without detaching it.
+pub struct Installed {
+    id: u64,
+    handle: ListenerHandle,
+    handler: Rc<RefCell<EventHandler>>,
+}
+
+pub struct ConcreteVListener<Evt: Event, Msg> {
+    id: Cell<u64>,
+    installed: RefCell<Option<Installed>>,
     mapper: Rc<Fn(Evt) -> Msg>,
 }
 
 impl<Evt, Msg> ConcreteVListener<Evt, Msg>
 where
-    Evt: 'static + ConcreteEvent,
+    Evt: Event,
     Msg: 'static,
 {
     pub fn new<F: 'static + Fn(Evt) -> Msg>(mapper: F) -> Self {
         ConcreteVListener {
-            handle: RefCell::new(None),
+            id: Cell::new(NEXT_LISTENER_ID.with(|next| {
+                let id = next.get();
+                next.set(id + 1);
+                id
+            })),
+            installed: RefCell::new(None),
             mapper: Rc::new(mapper),
         }
     }
 
-    /*
-    pub fn map<Tagger, TargetMsg>(self, tagger: Tagger) -> ConcreteVListener<Evt, TargetMsg>
-    where
-        TargetMsg: 'static,
-        Tagger: 'static + Fn(Msg) -> TargetMsg,
-    {
-        let mapper = self.mapper;
-        ConcreteVListener {
-            handle: self.handle,
-            mapper: Rc::new(move |evt| tagger(mapper(evt))),
-        }
+    fn handler(&self, update: Rc<Fn(Msg)>) -> EventHandler {
+        let map = self.mapper.clone();
+        Rc::new(move |raw: &Any| if let Some(evt) = Evt::from_raw(raw) {
+            update(map(evt));
+        })
     }
-    */
 }
 
 impl<Evt, Msg> VListener<Msg> for ConcreteVListener<Evt, Msg>
 where
-    Evt: ConcreteEvent + 'static,
+    Evt: Event,
     Msg: 'static,
 {
     fn key(&self) -> &'static str {
-        Evt::EVENT_TYPE
+        Evt::event_type()
+    }
+
+    fn id(&self) -> u64 {
+        self.id.get()
     }
 
-    fn install(&self, element: &Element, update: Rc<Fn(Msg)>) {
-        let mut handle = self.handle.borrow_mut();
-        *handle = Some(element.add_event_listener({
-            let update = update.clone();
-            let map = self.mapper.clone();
-            move |evt: Evt| { update(map(evt)); }
-        }))
+    fn install(&self, target: &EventTarget, update: Rc<Fn(Msg)>) {
+        let handler = Rc::new(RefCell::new(self.handler(update)));
+        let handle = target.add_event_listener(self.key(), {
+            let handler = handler.clone();
+            Rc::new(move |raw: &Any| {
+                // cloned first, the handler may be swapped while it runs
+                let current = handler.borrow().clone();
+                current(raw)
+            })
+        });
+        *self.installed.borrow_mut() = Some(Installed {
+            id: self.id.get(),
+            handle: handle,
+            handler: handler,
+        });
     }
 
-    fn remove(self) {
-        if let Some(handle) = self.handle.into_inner() {
-            handle.remove();
+    fn remove(&self) {
+        if let Some(installed) = self.installed.borrow_mut().take() {
+            installed.handle.remove();
         }
     }
+
+    fn take_installed(&self) -> Option<Installed> {
+        self.installed.borrow_mut().take()
+    }
+
+    fn adopt(&self, installed: Installed, update: Rc<Fn(Msg)>) {
+        *installed.handler.borrow_mut() = self.handler(update);
+        self.id.set(installed.id);
+        *self.installed.borrow_mut() = Some(installed);
+    }
+}
+
+/// Wraps a listener for one message type so that it produces another, see
+/// `Node::map`. Embedded components use it to send their listeners' messages
+/// to themselves.
+pub struct MappedVListener<Inner: 'static, Outer> {
+    inner: Box<VListener<Inner>>,
+    mapper: ListenerMapper<Inner, Outer>,
+}
+
+impl<Inner, Outer> MappedVListener<Inner, Outer> {
+    pub fn new(inner: Box<VListener<Inner>>, mapper: ListenerMapper<Inner, Outer>) -> Self {
+        MappedVListener {
+            inner: inner,
+            mapper: mapper,
+        }
+    }
+}
+
+impl<Inner, Outer> VListener<Outer> for MappedVListener<Inner, Outer>
+where
+    Inner: 'static,
+    Outer: 'static,
+{
+    fn key(&self) -> &'static str {
+        self.inner.key()
+    }
+
+    fn id(&self) -> u64 {
+        self.inner.id()
+    }
+
+    fn install(&self, target: &EventTarget, update: Rc<Fn(Outer)>) {
+        self.inner.install(target, (self.mapper)(update))
+    }
+
+    fn remove(&self) {
+        self.inner.remove()
+    }
+
+    fn take_installed(&self) -> Option<Installed> {
+        self.inner.take_installed()
+    }
+
+    fn adopt(&self, installed: Installed, update: Rc<Fn(Outer)>) {
+        self.inner.adopt(installed, (self.mapper)(update))
+    }
 }
@@ -0,0 +1,52 @@
+//! Callbacks run with the real DOM node when the virtual node it was
+//! rendered for is created, updated or removed, for integrating widgets
+//! that manage their own DOM (maps, editors, charts).
+
+use std::any::Any;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::rc::Rc;
+
+/// A lifecycle callback. It is only called if the backend's node type is
+/// the `N` it was created for.
+#[derive(Clone)]
+pub struct Hook(Rc<Fn(&Any)>);
+
+impl Hook {
+    pub fn new<N: 'static, F: Fn(&N) + 'static>(hook: F) -> Self {
+        Hook(Rc::new(move |node: &Any| {
+            if let Some(node) = node.downcast_ref::<N>() {
+                hook(node);
+            }
+        }))
+    }
+
+    pub fn call<N: 'static>(&self, node: &N) {
+        (self.0)(node)
+    }
+}
+
+impl PartialEq for Hook {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Debug for Hook {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "Hook")
+    }
+}
+
+/// The hooks of one node.
+#[derive(Clone, Default, Debug)]
+pub struct Hooks {
+    /// Called by `create_element` (or `hydrate`) once the element, its
+    /// attributes and its children are in place.
+    pub create: Option<Hook>,
+    /// Called after every patch that diffed the node against a newer
+    /// version of itself, once the whole patch has been applied.
+    pub update: Option<Hook>,
+    /// Called after the node was taken out of the document, either on its
+    /// own or along with an ancestor.
+    pub remove: Option<Hook>,
+}
@@ -0,0 +1,112 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::apply::apply;
+use super::backend::Backend;
+use super::diff::diff;
+use super::mock::{MockBackend, MockNode};
+use super::test_support::ignore;
+use super::root::{create_element, create_tree};
+use super::Node;
+
+type Log = Rc<RefCell<Vec<String>>>;
+
+/// Log the lifecycle of `node` under `name`, along with its text.
+fn logged(log: &Log, name: &'static str, mut node: Node<()>) -> Node<()> {
+    let (create, update, remove) = (log.clone(), log.clone(), log.clone());
+    node.on_create(move |dnode: &MockNode| {
+        create.borrow_mut().push(format!("create {} {}", name, dnode.text_content()))
+    });
+    node.on_update(move |dnode: &MockNode| {
+        update.borrow_mut().push(format!("update {} {}", name, dnode.text_content()))
+    });
+    node.on_remove(move |dnode: &MockNode| {
+        remove.borrow_mut().push(format!("remove {} {}", name, dnode.text_content()))
+    });
+    node
+}
+
+/// Render `old`, patch it to `new` and return what the hooks logged while
+/// patching.
+fn patch(log: &Log, old: Node<()>, new: Node<()>) -> Vec<String> {
+    let container = MockNode::element("body");
+    let tree = create_tree(&MockBackend, &old, &ignore());
+    MockBackend.append_child(&container, &tree.node());
+    log.borrow_mut().clear();
+    apply(&MockBackend, &tree, diff(&old, &new), ignore());
+    log.borrow().clone()
+}
+
+#[test]
+fn test_create_hook_sees_children() {
+    let log = Log::default();
+    create_element(
+        &MockBackend,
+        &vdom!(div [ (logged(&log, "a", vdom!(p [ b [ text!("x") ] ]))) ]),
+        &ignore(),
+    );
+    assert_eq!(*log.borrow(), vec!["create a x"]);
+}
+
+#[test]
+fn test_update_hooks_run_after_patching() {
+    let log = Log::default();
+    let view = |text: &str| {
+        let inner = logged(&log, "inner", vdom!(p [ text!(text) ]));
+        logged(&log, "outer", vdom!(div [ (inner) ]))
+    };
+
+    assert_eq!(
+        patch(&log, view("old"), view("new")),
+        vec!["update inner new", "update outer new"]
+    );
+}
+
+#[test]
+fn test_remove_hooks() {
+    let log = Log::default();
+    let p = |name, text: &str| logged(&log, name, vdom!(p [ text!(text) ]));
+
+    assert_eq!(
+        patch(&log, vdom!(div [ (p("a", "1")) (p("b", "2")) ]), vdom!(div [ (p("a", "1")) ])),
+        vec!["remove b 2", "update a 1"]
+    );
+
+    assert_eq!(
+        patch(&log, vdom!(div [ section [ (p("a", "1")) ] ]), vdom!(div [ text!("gone") ])),
+        vec!["remove a 1"]
+    );
+
+    assert_eq!(
+        patch(
+            &log,
+            vdom!(ul [ keyed!("a", p("a", "1")) keyed!("b", p("b", "2")) ]),
+            vdom!(ul [ keyed!("b", p("b", "2")) ]),
+        ),
+        vec!["remove a 1", "update b 2"]
+    );
+}
+
+#[test]
+fn test_removed_node_is_detached() {
+    let removed = Rc::new(RefCell::new(None));
+    let old: Node<()> = {
+        let removed = removed.clone();
+        vdom!(p { on_remove MockNode |dnode| *removed.borrow_mut() = Some(dnode.parent()) })
+    };
+    patch(&Log::default(), vdom!(div [ (old) ]), vdom!(div [ section ]));
+    assert_eq!(*removed.borrow(), Some(None));
+}
+
+#[test]
+fn test_replaced_node_runs_remove_and_create() {
+    let log = Log::default();
+    let mut old = logged(&log, "p", vdom!(p [ text!("1") ]));
+    old.on_update(|_: &MockNode| panic!("replaced nodes aren't updated"));
+    let new = logged(&log, "section", vdom!(section [ text!("2") ]));
+
+    assert_eq!(
+        patch(&log, vdom!(div [ (old) ]), vdom!(div [ (new) ])),
+        vec!["create section 2", "remove p 1"]
+    );
+}
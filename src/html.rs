@@ -0,0 +1,131 @@
+//! Serialize virtual nodes to HTML, for rendering on the server.
+//!
+//! A parser merges adjacent text into a single node and drops empty text
+//! altogether, so text children that wouldn't survive the round trip are
+//! marked with comments: `<!-- -->` separates two pieces of text and `<!---->`
+//! stands in for an empty one. `hydrate` knows to look past them. The anchor
+//! after the children of a fragment is empty text as well, see `dom_tree`.
+
+use std::fmt::{Result as FmtResult, Write};
+
+use super::attribute::Value;
+use super::node::{Node, Child};
+
+/// Elements that never have children or a closing tag.
+const VOID_ELEMENTS: &'static [&'static str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param",
+    "source", "track", "wbr",
+];
+
+/// Render `node` and its descendants to an HTML string. Listeners are skipped.
+pub fn to_html<M>(node: &Node<M>) -> String {
+    let mut html = String::new();
+    write_html(&mut html, node).unwrap();
+    html
+}
+
+/// Write the HTML for `node` and its descendants to `out`.
+pub fn write_html<M, W: Write>(out: &mut W, node: &Node<M>) -> FmtResult {
+    write!(out, "<{}", node.tag)?;
+
+    // sort attributes so the output doesn't depend on hash order
+    let mut attributes: Vec<_> = node.attributes.values().collect();
+    attributes.sort_by(|a, b| a.html_name().cmp(b.html_name()));
+
+    for attr in attributes {
+        match attr.value {
+            Value::Null | Value::Bool(false) => {}
+            Value::Bool(true) => write!(out, " {}", attr.html_name())?,
+            Value::Number(n) => write!(out, " {}=\"{}\"", attr.html_name(), format_number(n))?,
+            Value::String(ref s) => {
+                write!(out, " {}=\"", attr.html_name())?;
+                escape(out, s, true)?;
+                out.write_char('"')?;
+            }
+        }
+    }
+    if !node.styles.is_empty() {
+        let mut styles: Vec<_> = node.styles.iter().collect();
+        styles.sort();
+        out.write_str(" style=\"")?;
+        for (pos, &(property, value)) in styles.iter().enumerate() {
+            if pos > 0 {
+                out.write_char(' ')?;
+            }
+            escape(out, &format!("{}: {};", property, value), true)?;
+        }
+        out.write_char('"')?;
+    }
+    out.write_char('>')?;
+
+    if VOID_ELEMENTS.contains(&node.tag) {
+        return Ok(());
+    }
+
+    write_children(out, &node.children, &mut false)?;
+
+    write!(out, "</{}>", node.tag)
+}
+
+/// `after_text` says whether the last thing written was text.
+fn write_children<M, W: Write>(
+    out: &mut W,
+    children: &[Child<M>],
+    after_text: &mut bool,
+) -> FmtResult {
+    for child in children.iter() {
+        match *child {
+            Child::Text(ref text) if text.is_empty() => out.write_str("<!---->")?,
+            Child::Text(ref text) => {
+                if *after_text {
+                    out.write_str("<!-- -->")?;
+                }
+                escape(out, text, false)?;
+            }
+            Child::Node(ref node) | Child::Keyed(_, ref node) => write_html(out, node)?,
+            Child::Component(ref embedded) => {
+                match embedded.rendered() {
+                    Some(node) => write_html(out, node)?,
+                    None => write_html(out, &embedded.render_detached())?,
+                }
+            }
+            Child::Lazy(ref lazy) => {
+                match lazy.rendered() {
+                    Some(node) => write_html(out, node)?,
+                    None => write_html(out, &lazy.render())?,
+                }
+            }
+            Child::Fragment(ref fragment) => {
+                write_children(out, &fragment.children, after_text)?;
+                out.write_str("<!---->")?;
+            }
+        }
+        *after_text = match *child {
+            Child::Text(ref text) => !text.is_empty(),
+            _ => false,
+        };
+    }
+    Ok(())
+}
+
+fn format_number(n: f64) -> String {
+    if n.fract() == 0.0 && n.abs() < 1e15 {
+        format!("{}", n as i64)
+    } else {
+        format!("{}", n)
+    }
+}
+
+fn escape<W: Write>(out: &mut W, text: &str, in_attribute: bool) -> FmtResult {
+    for c in text.chars() {
+        match c {
+            '&' => out.write_str("&amp;")?,
+            '<' => out.write_str("&lt;")?,
+            '>' => out.write_str("&gt;")?,
+            '"' if in_attribute => out.write_str("&quot;")?,
+            '\'' if in_attribute => out.write_str("&#39;")?,
+            c => out.write_char(c)?,
+        }
+    }
+    Ok(())
+}
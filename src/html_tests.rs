@@ -0,0 +1,151 @@
+use super::html::to_html;
+use super::attribute::{class, checked, disabled, href, type_, Attr, InputType};
+use super::Node;
+
+#[test]
+fn test_html_escaping() {
+    let node: Node<()> = vdom!(
+        p { class = "a \"quoted\" <class>" } [ text!("fish & <chips>") ]
+    );
+
+    assert_eq!(
+        to_html(&node),
+        "<p class=\"a &quot;quoted&quot; &lt;class&gt;\">fish &amp; &lt;chips&gt;</p>"
+    );
+}
+
+#[test]
+fn test_html_void_and_boolean_attributes() {
+    let node: Node<()> = vdom!(
+        form [
+            input { type_ = InputType::Checkbox; checked = true; disabled = false }
+            br
+            a { href = "/next" } [ text!("next") ]
+            button { type_ = InputType::Submit } [ text!("go") ]
+            script { type_ = "module" }
+        ]
+    );
+
+    assert_eq!(
+        to_html(&node),
+        "<form><input checked type=\"checkbox\"><br><a href=\"/next\">next</a>\
+         <button type=\"submit\">go</button><script type=\"module\"></script></form>"
+    );
+}
+
+#[test]
+fn test_html_attribute_names_and_numbers() {
+    let mut node: Node<()> = Node::new("td");
+    node.add_attribute(Attr::property("colSpan", 2));
+    node.add_attribute(Attr::attribute("data-ratio", 0.5));
+
+    assert_eq!(to_html(&node), "<td colspan=\"2\" data-ratio=\"0.5\"></td>");
+}
+
+#[test]
+fn test_html_styles() {
+    let node: Node<()> = vdom!(
+        p { class = "a"; style = vec![("margin-top", "1em"), ("font-family", "\"Fira Sans\"")] }
+    );
+
+    assert_eq!(
+        to_html(&node),
+        "<p class=\"a\" style=\"font-family: &quot;Fira Sans&quot;; margin-top: 1em;\"></p>"
+    );
+}
+
+#[test]
+fn test_html_catalogue_and_hyphenated_attributes() {
+    use super::attribute::{aria_label, for_, id, novalidate, placeholder, tabindex};
+
+    let node: Node<()> = vdom!(
+        form { novalidate = true; data-form-id = 7 } [
+            label { for_ = "q"; aria_label = "Search" }
+            input { id = "q"; placeholder = "Search…"; tabindex = 1; aria-describedby = "hint" }
+        ]
+    );
+
+    assert_eq!(
+        to_html(&node),
+        "<form data-form-id=\"7\" novalidate>\
+         <label aria-label=\"Search\" for=\"q\"></label>\
+         <input aria-describedby=\"hint\" id=\"q\" placeholder=\"Search…\" tabindex=\"1\">\
+         </form>"
+    );
+}
+
+#[test]
+fn test_html_typed_attributes() {
+    use super::attribute::{aria_expanded, dir, draggable, rows, wrap, Dir, TrueFalse, Wrap};
+    use super::Attribute;
+
+    let node: Node<()> = vdom!(
+        textarea { dir = Dir::Rtl; draggable = true; rows = 4; wrap = Wrap::Hard; aria_expanded = false }
+    );
+
+    assert_eq!(
+        to_html(&node),
+        "<textarea aria-expanded=\"false\" dir=\"rtl\" draggable=\"true\" rows=\"4\" wrap=\"hard\"></textarea>"
+    );
+    assert_eq!(draggable(false).value(), &TrueFalse(false));
+    assert_eq!(rows(2).key(), "rows");
+}
+
+#[test]
+fn test_html_spellcheck_and_data_attributes() {
+    use super::attribute::{data, spellcheck};
+
+    let field = "user-id";
+    let mut node: Node<()> = vdom!(div { spellcheck = false });
+    node.add_attribute(data(field, 7));
+    node.add_attribute(data(String::from("role"), "admin"));
+
+    assert_eq!(
+        to_html(&node),
+        "<div data-role=\"admin\" data-user-id=\"7\" spellcheck=\"false\"></div>"
+    );
+    assert_eq!(node.attributes["data-user-id"], data("user-id", 7));
+}
+
+#[test]
+fn test_html_marks_adjacent_and_empty_text() {
+    let node: Node<()> = vdom!(
+        p [ text!("a") text!("b") text!("") text!("c") em [ text!("d") ] text!("e") ]
+    );
+
+    assert_eq!(to_html(&node), "<p>a<!-- -->b<!---->c<em>d</em>e</p>");
+}
+
+#[test]
+fn test_html_lengths() {
+    use super::attribute::{cx, height, r, stroke_width, width, Length};
+
+    let node: Node<()> = vdom!(
+        svg { width = "50%"; height = 20 } [
+            circle { cx = 2.5; r = "1em"; stroke_width = " 3 " }
+        ]
+    );
+
+    assert_eq!(
+        to_html(&node),
+        "<svg height=\"20\" width=\"50%\">\
+         <circle cx=\"2.5\" r=\"1em\" stroke-width=\"3\"></circle>\
+         </svg>"
+    );
+    assert_eq!(Length::from("12.5%"), Length::Percent(12.5));
+    assert_eq!(Length::from("auto"), Length::Other("auto".to_owned()));
+}
+
+#[test]
+fn test_html_marks_the_end_of_fragments() {
+    use super::node::Fragment;
+
+    let mut fragment: Fragment<()> = Fragment::new();
+    fragment.append_string("b".to_owned());
+    fragment.append_child(vdom!(em [ text!("c") ]));
+    let node: Node<()> = vdom!(
+        p [ text!("a") fragment!(fragment) text!("d") fragment!(Fragment::new()) ]
+    );
+
+    assert_eq!(to_html(&node), "<p>a<!-- -->b<em>c</em><!---->d<!----></p>");
+}
@@ -0,0 +1,251 @@
+//! Adopt existing (usually server-rendered) DOM nodes instead of creating
+//! them from scratch.
+
+use std::fmt::Debug;
+
+use super::backend::{Backend, NodeTarget};
+use super::component::Callback;
+use super::node::{Node, Child};
+use super::dom_tree::DomTree;
+use super::root::{create_child, create_tree};
+
+/// A difference between the existing DOM and the virtual tree it was
+/// hydrated with. `path` holds the child positions leading from the root to
+/// the node in question. Every mismatch is repaired as it is found.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Mismatch {
+    /// An element or text node was found where an element was expected.
+    Tag {
+        path: Vec<usize>,
+        expected: &'static str,
+        found: Option<String>,
+    },
+    /// An element or a text node with different content was found where
+    /// text was expected.
+    Text {
+        path: Vec<usize>,
+        expected: String,
+        found: Option<String>,
+    },
+    /// The existing DOM had fewer children than the virtual node.
+    Missing { path: Vec<usize> },
+    /// The existing DOM had more children than the virtual node.
+    Extra { path: Vec<usize> },
+}
+
+/// Walk `dnode` alongside `vnode`, setting attributes and installing
+/// listeners on matching elements. Mismatching nodes are replaced with newly
+/// created ones and reported. Returns the DOM nodes now representing `vnode`,
+/// whose root is only different from `dnode` if it had to be replaced.
+///
+/// Attributes and styles on the existing elements that `vnode` doesn't
+/// mention are left alone. The comments `to_html` marks text children with
+/// are removed.
+pub fn hydrate<B, Msg>(
+    backend: &B,
+    dnode: &B::Node,
+    vnode: &Node<Msg>,
+    send: &Callback<Msg>,
+    mismatches: &mut Vec<Mismatch>,
+) -> DomTree<B::Node>
+where
+    B: Backend,
+    Msg: 'static + Debug,
+{
+    let mut path = Vec::new();
+    hydrate_node(backend, dnode, vnode, send, &mut path, mismatches)
+}
+
+fn hydrate_node<B, Msg>(
+    backend: &B,
+    dnode: &B::Node,
+    vnode: &Node<Msg>,
+    send: &Callback<Msg>,
+    path: &mut Vec<usize>,
+    mismatches: &mut Vec<Mismatch>,
+) -> DomTree<B::Node>
+where
+    B: Backend,
+    Msg: 'static + Debug,
+{
+    // browsers report case-sensitive SVG tag names (e.g. `foreignObject`)
+    // in lowercase too
+    let tag = backend.tag_name(dnode);
+    if !tag.as_ref().map_or(false, |tag| tag.eq_ignore_ascii_case(vnode.tag)) {
+        mismatches.push(Mismatch::Tag {
+            path: path.clone(),
+            expected: vnode.tag,
+            found: tag,
+        });
+        let tree = create_tree(backend, vnode, send);
+        replace(backend, dnode, &tree.node());
+        return tree;
+    }
+
+    for (_, attr) in vnode.attributes.iter() {
+        attr.set(backend, dnode);
+    }
+    for (property, value) in vnode.styles.iter() {
+        backend.set_style(dnode, property, value);
+    }
+
+    let mut dchildren = backend.child_nodes(dnode).into_iter();
+    let children = hydrate_children(
+        backend,
+        dnode,
+        &vnode.children,
+        &mut dchildren,
+        send,
+        path,
+        mismatches,
+    );
+
+    let mut pos = vnode.children.len();
+    while let Some(extra) = next_child(backend, dnode, &mut dchildren, false) {
+        path.push(pos);
+        mismatches.push(Mismatch::Extra { path: path.clone() });
+        path.pop();
+        backend.remove_child(dnode, &extra);
+        pos += 1;
+    }
+
+    for listener in vnode.listeners.iter() {
+        listener.install(&NodeTarget::new(backend, dnode), send.clone());
+    }
+    if let Some(ref node_ref) = vnode.node_ref {
+        node_ref.set(dnode.clone());
+    }
+    if let Some(ref hook) = vnode.hooks.create {
+        hook.call(dnode);
+    }
+
+    DomTree::new(dnode.clone(), children)
+}
+
+/// Hydrate `children` with what is left of the children of `parent`. The
+/// children of a fragment and its anchor are children of `parent` too.
+fn hydrate_children<B, Msg, I>(
+    backend: &B,
+    parent: &B::Node,
+    children: &[Child<Msg>],
+    dchildren: &mut I,
+    send: &Callback<Msg>,
+    path: &mut Vec<usize>,
+    mismatches: &mut Vec<Mismatch>,
+) -> Vec<DomTree<B::Node>>
+where
+    B: Backend,
+    Msg: 'static + Debug,
+    I: Iterator<Item = B::Node>,
+{
+    let mut trees = Vec::with_capacity(children.len());
+    for (pos, child) in children.iter().enumerate() {
+        path.push(pos);
+        let tree = match *child {
+            Child::Text(ref text) => {
+                hydrate_text(backend, parent, text, dchildren, path, mismatches)
+            }
+            Child::Fragment(ref fragment) => {
+                let children = hydrate_children(
+                    backend,
+                    parent,
+                    &fragment.children,
+                    dchildren,
+                    send,
+                    path,
+                    mismatches,
+                );
+                let anchor = hydrate_text(backend, parent, "", dchildren, path, mismatches);
+                DomTree::fragment(anchor.node(), children)
+            }
+            _ => match next_child(backend, parent, dchildren, false) {
+                Some(dchild) => {
+                    hydrate_node(backend, &dchild, child.node().unwrap(), send, path, mismatches)
+                }
+                None => {
+                    mismatches.push(Mismatch::Missing { path: path.clone() });
+                    let tree = create_child(backend, child, send);
+                    backend.append_child(parent, &tree.node());
+                    tree
+                }
+            },
+        };
+        trees.push(tree);
+        path.pop();
+    }
+    trees
+}
+
+fn hydrate_text<B, I>(
+    backend: &B,
+    parent: &B::Node,
+    text: &str,
+    dchildren: &mut I,
+    path: &[usize],
+    mismatches: &mut Vec<Mismatch>,
+) -> DomTree<B::Node>
+where
+    B: Backend,
+    I: Iterator<Item = B::Node>,
+{
+    match next_child(backend, parent, dchildren, text.is_empty()) {
+        None => {
+            mismatches.push(Mismatch::Missing { path: path.to_vec() });
+            let text_node = backend.create_text_node(text);
+            backend.append_child(parent, &text_node);
+            DomTree::leaf(text_node)
+        }
+        Some(ref dchild) if backend.is_comment(dchild) => {
+            // the placeholder `to_html` writes for empty text
+            let text_node = backend.create_text_node(text);
+            replace(backend, dchild, &text_node);
+            DomTree::leaf(text_node)
+        }
+        Some(dchild) => {
+            let found = match backend.tag_name(&dchild) {
+                Some(_) => None,
+                None => backend.text_content(&dchild),
+            };
+            if found.as_ref().map(String::as_str) != Some(text) {
+                mismatches.push(Mismatch::Text {
+                    path: path.to_vec(),
+                    expected: text.to_owned(),
+                    found: found,
+                });
+                let text_node = backend.create_text_node(text);
+                replace(backend, &dchild, &text_node);
+                DomTree::leaf(text_node)
+            } else {
+                DomTree::leaf(dchild)
+            }
+        }
+    }
+}
+
+/// The next child of `parent` to hydrate with. Comments `to_html` wrote to
+/// separate text are removed on the way, a comment is only returned if it
+/// may be the `placeholder` for empty text.
+fn next_child<B, I>(
+    backend: &B,
+    parent: &B::Node,
+    dchildren: &mut I,
+    placeholder: bool,
+) -> Option<B::Node>
+where
+    B: Backend,
+    I: Iterator<Item = B::Node>,
+{
+    for dchild in dchildren {
+        if !backend.is_comment(&dchild) || placeholder {
+            return Some(dchild);
+        }
+        backend.remove_child(parent, &dchild);
+    }
+    None
+}
+
+fn replace<B: Backend>(backend: &B, old: &B::Node, new: &B::Node) {
+    if let Some(parent) = backend.parent_node(old) {
+        backend.replace_child(&parent, new, old);
+    }
+}
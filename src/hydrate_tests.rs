@@ -0,0 +1,212 @@
+use std::rc::Rc;
+
+use super::attribute::class;
+use super::backend::Backend;
+use super::cmd::Cmd;
+use super::component::{Callback, Component};
+use super::html::to_html;
+use super::hydrate::{hydrate, Mismatch};
+use super::mock::{MockBackend, MockNode};
+use super::test_support::{ignore, Click};
+use super::root::{create_element, hydrate_with, Redraw};
+use super::Node;
+
+struct Counter(i32);
+
+impl Component<i32> for Counter {
+    fn view(&self) -> Node<i32> {
+        vdom!(div [
+            p [ format!("count: {}", self.0) ]
+            button { on Click |_evt| 1 } [ text!("+") ]
+        ])
+    }
+
+    fn update(&mut self, msg: i32) -> Cmd<i32> {
+        self.0 += msg;
+        Cmd::none()
+    }
+}
+
+/// Build the DOM a server render of `vnode` would produce, without listeners.
+fn server_render(vnode: &Node<i32>) -> MockNode {
+    let container = MockNode::element("body");
+    let dnode = create_element(&MockBackend, vnode, &(Rc::new(|_| {}) as Callback<i32>));
+    MockBackend.append_child(&container, &dnode);
+    dnode
+}
+
+/// Parse HTML like a browser would, keeping comments and merging adjacent
+/// text. Only good for what `to_html` writes without attributes or void
+/// elements, attributes are set by hydrating anyway.
+fn parse(html: &str) -> MockNode {
+    let container = MockNode::element("body");
+    let mut open = vec![container.clone()];
+    let mut rest = html;
+    while !rest.is_empty() {
+        let parent = open.last().unwrap().clone();
+        if rest.starts_with("<!--") {
+            let end = rest.find("-->").unwrap();
+            MockBackend.append_child(&parent, &MockNode::comment(&rest[4..end]));
+            rest = &rest[end + 3..];
+        } else if rest.starts_with("</") {
+            open.pop();
+            rest = &rest[rest.find('>').unwrap() + 1..];
+        } else if rest.starts_with('<') {
+            let end = rest.find('>').unwrap();
+            let element = MockNode::element(rest[1..end].split(' ').next().unwrap());
+            MockBackend.append_child(&parent, &element);
+            open.push(element);
+            rest = &rest[end + 1..];
+        } else {
+            let end = rest.find('<').unwrap_or(rest.len());
+            let text = rest[..end]
+                .replace("&lt;", "<")
+                .replace("&gt;", ">")
+                .replace("&amp;", "&");
+            MockBackend.append_child(&parent, &MockNode::text(&text));
+            rest = &rest[end..];
+        }
+    }
+    container.children()[0].clone()
+}
+
+struct Label {
+    name: &'static str,
+    count: i32,
+}
+
+impl Component<i32> for Label {
+    fn view(&self) -> Node<i32> {
+        vdom!(p [
+            text!(self.name)
+            text!("")
+            text!(format!(" & {}", self.count))
+            button { class = "more"; on Click |_evt| 1 } [ text!("+") ]
+            text!("")
+        ])
+    }
+
+    fn update(&mut self, msg: i32) -> Cmd<i32> {
+        self.count += msg;
+        Cmd::none()
+    }
+}
+
+#[test]
+fn test_hydrate_adopts_existing_nodes() {
+    let dnode = server_render(&Counter(3).view());
+    let button = dnode.children()[1].clone();
+
+    let (root, mismatches) = hydrate_with(MockBackend, Counter(3), dnode.clone());
+    root.set_redraw(Redraw::Sync);
+    assert_eq!(mismatches, vec![]);
+
+    button.dispatch(Click);
+    assert_eq!(dnode.children()[1], button);
+    assert_eq!(dnode.text_content(), "count: 4+");
+}
+
+#[test]
+fn test_hydrate_repairs_mismatches() {
+    let stale: Node<i32> = vdom!(div [
+        p [ text!("count: 0") ]
+        span [ text!("+") ]
+        hr []
+    ]);
+    let dnode = server_render(&stale);
+
+    let (root, mismatches) = hydrate_with(MockBackend, Counter(3), dnode.clone());
+    root.set_redraw(Redraw::Sync);
+    assert_eq!(
+        mismatches,
+        vec![
+            Mismatch::Text {
+                path: vec![0, 0],
+                expected: "count: 3".to_owned(),
+                found: Some("count: 0".to_owned()),
+            },
+            Mismatch::Tag {
+                path: vec![1],
+                expected: "button",
+                found: Some("span".to_owned()),
+            },
+            Mismatch::Extra { path: vec![2] },
+        ]
+    );
+    assert_eq!(
+        dnode.snapshot(),
+        create_element(&MockBackend, &Counter(3).view(), &(Rc::new(|_| {}) as Callback<i32>))
+            .snapshot()
+    );
+
+    dnode.children()[1].dispatch(Click);
+    assert_eq!(dnode.text_content(), "count: 4+");
+}
+
+#[test]
+fn test_hydrate_server_html() {
+    let label = Label { name: "fish", count: 2 };
+    let html = to_html(&label.view());
+    assert_eq!(html, "<p>fish<!----> &amp; 2<button class=\"more\">+</button><!----></p>");
+    let dnode = parse(&html);
+    assert_eq!(dnode.children().len(), 5);
+
+    let (root, mismatches) = hydrate_with(MockBackend, label, dnode.clone());
+    root.set_redraw(Redraw::Sync);
+    assert_eq!(mismatches, vec![]);
+    assert_eq!(
+        dnode.snapshot(),
+        create_element(&MockBackend, &Label { name: "fish", count: 2 }.view(), &ignore()).snapshot()
+    );
+
+    dnode.children()[3].dispatch(Click);
+    assert_eq!(dnode.text_content(), "fish & 3+");
+}
+
+#[test]
+fn test_hydrate_removes_text_separators() {
+    let vnode: Node<i32> = vdom!(p [ text!("a") text!("b") em [ text!("c") ] ]);
+    let dnode = parse(&to_html(&vnode));
+    assert_eq!(dnode.children().len(), 4);
+
+    let mut mismatches = Vec::new();
+    hydrate(&MockBackend, &dnode, &vnode, &ignore(), &mut mismatches);
+    assert_eq!(mismatches, vec![]);
+    assert_eq!(dnode.snapshot(), create_element(&MockBackend, &vnode, &ignore()).snapshot());
+}
+
+#[test]
+fn test_hydrate_fragments() {
+    use super::node::Fragment;
+
+    fn items(names: &[&'static str]) -> Fragment<i32> {
+        names.iter().map(|name| vdom!(li [ text!(*name) ])).collect()
+    }
+
+    let vnode: Node<i32> = vdom!(
+        ul [ fragment!(items(&["a", "b"])) li [ text!("c") ] fragment!(items(&[])) ]
+    );
+    let dnode = parse(&to_html(&vnode));
+    assert_eq!(dnode.children().len(), 5);
+
+    let mut mismatches = Vec::new();
+    let tree = hydrate(&MockBackend, &dnode, &vnode, &ignore(), &mut mismatches);
+    assert_eq!(mismatches, vec![]);
+    assert_eq!(dnode.snapshot(), create_element(&MockBackend, &vnode, &ignore()).snapshot());
+    assert!(tree.children()[0].is_fragment());
+    assert_eq!(tree.children()[0].nodes(), &dnode.children()[..3]);
+
+    // a fragment without its anchor gets one
+    let dnode = parse("<ul><li>a</li><li>b</li></ul>");
+    let mut mismatches = Vec::new();
+    hydrate(&MockBackend, &dnode, &vnode, &ignore(), &mut mismatches);
+    assert_eq!(
+        mismatches,
+        vec![
+            Mismatch::Missing { path: vec![0] },
+            Mismatch::Missing { path: vec![1] },
+            Mismatch::Missing { path: vec![2] },
+        ]
+    );
+    assert_eq!(dnode.snapshot(), create_element(&MockBackend, &vnode, &ignore()).snapshot());
+}
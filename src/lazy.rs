@@ -0,0 +1,132 @@
+//! Subtrees that are only rebuilt when their input changes.
+//!
+//! A `Lazy` child holds an argument and a function rendering it. When a new
+//! view is resolved against the previous one, a lazy child with the same
+//! function and an equal argument at the same place (or under the same key)
+//! in the old tree takes over the node rendered last time instead of calling its function.
+//! The shared node is then skipped by `diff` like an unchanged embedded
+//! component.
+//!
+//! The function is assumed to depend on nothing but its argument.
+
+use std::any::{Any, TypeId};
+use std::rc::Rc;
+
+use super::component::Callback;
+use super::embed::{resolve, take_over, Host, ListenerMapper};
+use super::node::Node;
+
+pub struct Lazy<Msg: 'static> {
+    key: Option<String>,
+    arg: Rc<Any>,
+    same: fn(&Any, &Any) -> bool,
+    /// The type of the view function, which sets apart one function from
+    /// another.
+    view_type: TypeId,
+    view: Rc<Fn() -> Node<Msg>>,
+    rendered: Option<Rc<Node<Msg>>>,
+    /// Inherited from the parent node, for the rendered tree.
+    namespace: Option<&'static str>,
+}
+
+impl<Msg: 'static> Lazy<Msg> {
+    pub fn new<A, F>(arg: A, view: F) -> Self
+    where
+        A: PartialEq + 'static,
+        F: Fn(&A) -> Node<Msg> + 'static,
+    {
+        let arg = Rc::new(arg);
+        let view_arg = arg.clone();
+        Lazy {
+            key: None,
+            arg: arg,
+            same: same::<A>,
+            view_type: TypeId::of::<F>(),
+            view: Rc::new(move || view(&view_arg)),
+            rendered: None,
+            namespace: None,
+        }
+    }
+
+    /// Like `new`, for a child in a keyed list.
+    pub fn keyed<K, A, F>(key: K, arg: A, view: F) -> Self
+    where
+        K: Into<String>,
+        A: PartialEq + 'static,
+        F: Fn(&A) -> Node<Msg> + 'static,
+    {
+        let mut lazy = Lazy::new(arg, view);
+        lazy.key = Some(key.into());
+        lazy
+    }
+
+    pub fn key(&self) -> Option<&str> {
+        self.key.as_ref().map(String::as_str)
+    }
+
+    /// The rendered node, once resolved.
+    pub fn rendered(&self) -> Option<&Node<Msg>> {
+        self.rendered.as_ref().map(|node| &**node)
+    }
+
+    /// The rendered node, unless it is still shared with another tree.
+    pub fn rendered_mut(&mut self) -> Option<&mut Node<Msg>> {
+        self.rendered.as_mut().and_then(Rc::get_mut)
+    }
+
+    /// Call the view function, regardless of what was rendered before.
+    pub fn render(&self) -> Node<Msg> {
+        let mut node = (self.view)();
+        if let Some(namespace) = self.namespace {
+            node.inherit_namespace(namespace);
+        }
+        node
+    }
+
+    /// Take over what `old` rendered if it was rendered by the same function
+    /// from an equal argument, otherwise render and resolve the embedded components in the
+    /// new tree.
+    pub fn resolve(&mut self, old: Option<&Lazy<Msg>>, host: &Rc<Host>, parent: &Callback<Msg>) {
+        if let Some(old) = old {
+            let same_place = self.view_type == old.view_type && self.namespace == old.namespace;
+            if same_place && (self.same)(&*self.arg, &*old.arg) {
+                if let Some(rendered) = take_over(&old.rendered) {
+                    self.rendered = Some(rendered);
+                    return;
+                }
+            }
+        }
+
+        let mut rendered = self.render();
+        resolve(&mut rendered, old.and_then(Lazy::rendered), host, parent);
+        self.rendered = Some(Rc::new(rendered));
+    }
+
+    pub fn inherit_namespace(&mut self, namespace: &'static str) {
+        if self.namespace.is_none() {
+            self.namespace = Some(namespace);
+        }
+    }
+
+    /// Map the listeners of whatever the view function renders. What was
+    /// already rendered is dropped.
+    pub fn map_listeners<To: 'static>(self, mapper: ListenerMapper<Msg, To>) -> Lazy<To> {
+        let view = self.view;
+        Lazy {
+            key: self.key,
+            arg: self.arg,
+            same: self.same,
+            view_type: self.view_type,
+            view: Rc::new(move || view().map_listeners(mapper.clone())),
+            rendered: None,
+            namespace: self.namespace,
+        }
+    }
+}
+
+fn same<A: PartialEq + 'static>(a: &Any, b: &Any) -> bool {
+    match (a.downcast_ref::<A>(), b.downcast_ref::<A>()) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
@@ -0,0 +1,197 @@
+use std::cell::Cell;
+
+use super::cmd::Cmd;
+use super::component::Component;
+use super::html::to_html;
+use super::mock::MockBackend;
+use super::test_support::{mount_sync, Click};
+use super::root::Redraw;
+use super::Node;
+
+thread_local! {
+    static ROW_VIEWS: Cell<usize> = Cell::new(0);
+}
+
+fn row_views() -> usize {
+    ROW_VIEWS.with(Cell::get)
+}
+
+fn row(&(ref name, count): &(String, i32)) -> Node<Msg> {
+    ROW_VIEWS.with(|v| v.set(v.get() + 1));
+    vdom!(tr [ td [ format!("{}: {}", name, count) ] ])
+}
+
+#[derive(Debug)]
+enum Msg {
+    Set(usize, i32),
+    Reverse,
+    Rename(&'static str),
+}
+
+struct Table {
+    title: &'static str,
+    rows: Vec<(String, i32)>,
+}
+
+impl Component<Msg> for Table {
+    fn view(&self) -> Node<Msg> {
+        let mut body = vdom!(tbody);
+        for data in self.rows.iter() {
+            body.append_keyed_lazy(data.0.clone(), data.clone(), row);
+        }
+        vdom!(table [ caption [ text!(self.title) ] (body) ])
+    }
+
+    fn update(&mut self, msg: Msg) -> Cmd<Msg> {
+        match msg {
+            Msg::Set(pos, count) => self.rows[pos].1 = count,
+            Msg::Reverse => self.rows.reverse(),
+            Msg::Rename(title) => self.title = title,
+        }
+        Cmd::none()
+    }
+}
+
+fn table() -> Table {
+    Table {
+        title: "counts",
+        rows: vec![("a".into(), 1), ("b".into(), 2), ("c".into(), 3)],
+    }
+}
+
+#[test]
+fn test_lazy_rows_only_render_when_changed() {
+    let (root, container) = mount_sync(table());
+    let before = row_views();
+    let rows = container.children()[0].children()[1].children();
+
+    root.send(Msg::Rename("totals"));
+    assert_eq!(row_views(), before);
+    assert_eq!(container.text_content(), "totalsa: 1b: 2c: 3");
+
+    root.send(Msg::Set(1, 20));
+    assert_eq!(row_views(), before + 1);
+    assert_eq!(container.text_content(), "totalsa: 1b: 20c: 3");
+
+    // keyed lazy rows keep their DOM nodes when moved
+    root.send(Msg::Reverse);
+    assert_eq!(row_views(), before + 1);
+    assert_eq!(container.text_content(), "totalsc: 3b: 20a: 1");
+    let reversed = container.children()[0].children()[1].children();
+    assert_eq!(reversed[2], rows[0]);
+    assert_eq!(reversed[0], rows[2]);
+}
+
+#[test]
+fn test_lazy_rows_with_duplicate_keys_are_not_shared() {
+    let (root, container) = mount_sync(Table {
+        title: "dupes",
+        rows: vec![("a".into(), 1), ("a".into(), 1)],
+    });
+    let before = row_views();
+
+    // only one of the rows takes over what was rendered for the key, so that
+    // each rendered tree can still be updated in place
+    root.send(Msg::Rename("still dupes"));
+    assert_eq!(row_views(), before + 1);
+    assert_eq!(container.text_content(), "still dupesa: 1a: 1");
+
+    root.send(Msg::Set(0, 2));
+    assert_eq!(container.text_content(), "still dupesa: 2a: 1");
+}
+
+#[test]
+fn test_lazy_to_html() {
+    assert_eq!(
+        to_html(&table().view()),
+        "<table><caption>counts</caption><tbody>\
+         <tr><td>a: 1</td></tr><tr><td>b: 2</td></tr><tr><td>c: 3</td></tr>\
+         </tbody></table>"
+    );
+}
+
+struct Counter(i32);
+
+impl Component<()> for Counter {
+    fn view(&self) -> Node<()> {
+        vdom!(button { on Click |_evt| () } [ format!("{}", self.0) ])
+    }
+
+    fn update(&mut self, _: ()) -> Cmd<()> {
+        self.0 += 1;
+        Cmd::none()
+    }
+}
+
+/// A counter in a lazy subtree that never changes, next to a title.
+struct Page(&'static str);
+
+impl Component<&'static str> for Page {
+    fn view(&self) -> Node<&'static str> {
+        vdom!(div [
+            h1 [ text!(self.0) ]
+            lazy!((), |_: &()| vdom!(section [ component!(Counter(0)) ]))
+        ])
+    }
+
+    fn update(&mut self, title: &'static str) -> Cmd<&'static str> {
+        self.0 = title;
+        Cmd::none()
+    }
+}
+
+#[test]
+fn test_component_in_reused_lazy_subtree() {
+    let (root, container) = mount_sync(Page("one"));
+    root.set_redraw(Redraw::AnimationFrame);
+    let button = container.children()[0].children()[1].children()[0].clone();
+
+    // the full redraw reuses the lazy subtree, so the counter has to be
+    // redrawn on its own
+    button.dispatch(Click);
+    root.send("two");
+    MockBackend.run_animation_frames();
+    assert_eq!(container.text_content(), "two1");
+
+    button.dispatch(Click);
+    MockBackend.run_animation_frames();
+    assert_eq!(container.text_content(), "two2");
+}
+
+fn show(n: &i32) -> Node<()> {
+    vdom!(p [ format!("show {}", n) ])
+}
+
+fn edit(n: &i32) -> Node<()> {
+    vdom!(form [ format!("edit {}", n) ])
+}
+
+/// Shows or edits the same item, by switching view functions.
+struct Item(bool);
+
+impl Component<()> for Item {
+    fn view(&self) -> Node<()> {
+        let mut node = vdom!(div);
+        if self.0 {
+            node.append_lazy(1, edit);
+        } else {
+            node.append_lazy(1, show);
+        }
+        node
+    }
+
+    fn update(&mut self, _: ()) -> Cmd<()> {
+        self.0 = !self.0;
+        Cmd::none()
+    }
+}
+
+#[test]
+fn test_lazy_with_another_view_function_renders() {
+    let (root, container) = mount_sync(Item(false));
+    assert_eq!(container.text_content(), "show 1");
+
+    root.send(());
+    assert_eq!(container.text_content(), "edit 1");
+    assert_eq!(container.children()[0].children()[0].tag(), Some("form".into()));
+}
@@ -1,25 +1,90 @@
 #![feature(fnbox)]
 
+extern crate futures;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+#[cfg(feature = "web")]
 #[macro_use]
 extern crate stdweb;
 
 #[macro_use]
 mod macros;
 
+pub mod backend;
+pub mod batch;
+#[cfg(feature = "web")]
+mod stdweb_backend;
 mod node;
+mod node_ref;
+mod hooks;
+mod lazy;
 mod component;
+mod cmd;
+mod sub;
 mod events;
 mod diff;
-mod dom_iterator;
 mod apply;
+mod dom_tree;
 mod root;
+mod html;
+mod hydrate;
+pub mod embed;
+pub mod owned;
+pub mod native;
+pub mod remote;
+pub mod remote_client;
+mod websocket;
 
 #[cfg(test)]
 mod diff_tests;
+#[cfg(test)]
+mod apply_tests;
+#[cfg(test)]
+mod batch_tests;
+#[cfg(test)]
+mod html_tests;
+#[cfg(test)]
+mod hydrate_tests;
+#[cfg(test)]
+mod embed_tests;
+#[cfg(test)]
+mod root_tests;
+#[cfg(test)]
+mod cmd_tests;
+#[cfg(test)]
+mod sub_tests;
+#[cfg(test)]
+mod node_ref_tests;
+#[cfg(test)]
+mod hooks_tests;
+#[cfg(test)]
+mod lazy_tests;
+#[cfg(test)]
+mod owned_tests;
+#[cfg(test)]
+mod remote_tests;
+#[cfg(test)]
+mod test_support;
 
 pub mod attribute;
+pub mod mock;
 
 pub use self::node::*;
+pub use self::node_ref::NodeRef;
+pub use self::dom_tree::DomTree;
+pub use self::owned::OwnedPatch;
+pub use self::hooks::{Hook, Hooks};
 pub use self::component::*;
-pub use self::attribute::{Attribute, Attr};
+pub use self::cmd::Cmd;
+pub use self::sub::Sub;
+pub use self::backend::GlobalTarget;
+pub use self::attribute::{Attribute, Attr, AttrKind, TypedAttr, Value};
 pub use self::root::*;
+pub use self::html::{to_html, write_html};
+pub use self::hydrate::Mismatch;
+#[cfg(feature = "web")]
+pub use self::stdweb_backend::StdwebBackend;
+pub use self::events::Event;
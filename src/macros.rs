@@ -27,8 +27,7 @@ macro_rules! vdom {
     };
 
     ($tag:ident) => {{
-        use vdom::Node;
-        Node::new(stringify!($tag))
+        $crate::Node::new(stringify!($tag))
     }};
 
     (@add_children $parent:ident text!( $text:expr ) $($rest:tt)*) => {
@@ -36,6 +35,36 @@ macro_rules! vdom {
         vdom!(@add_children $parent $($rest)*);
     };
 
+    (@add_children $parent:ident fragment!( $fragment:expr ) $($rest:tt)*) => {
+        $parent.append_fragment($fragment);
+        vdom!(@add_children $parent $($rest)*);
+    };
+
+    (@add_children $parent:ident lazy!( $arg:expr, $view:expr ) $($rest:tt)*) => {
+        $parent.append_lazy($arg, $view);
+        vdom!(@add_children $parent $($rest)*);
+    };
+
+    (@add_children $parent:ident keyed!( $key:expr, lazy!( $arg:expr, $view:expr ) ) $($rest:tt)*) => {
+        $parent.append_keyed_lazy($key, $arg, $view);
+        vdom!(@add_children $parent $($rest)*);
+    };
+
+    (@add_children $parent:ident keyed!( $key:expr, $elem:expr ) $($rest:tt)*) => {
+        $parent.append_keyed($key, $elem);
+        vdom!(@add_children $parent $($rest)*);
+    };
+
+    (@add_children $parent:ident component!( $init:expr ) $($rest:tt)*) => {
+        $parent.append_component(move || $init);
+        vdom!(@add_children $parent $($rest)*);
+    };
+
+    (@add_children $parent:ident component!( $init:expr, $emit:expr ) $($rest:tt)*) => {
+        $parent.append_component_with(move || $init, $emit);
+        vdom!(@add_children $parent $($rest)*);
+    };
+
     (@add_children $parent:ident format!( $($args:expr),* ) $($rest:tt)*) => {
         $parent.append_string(format!($( $args ),*));
         vdom!(@add_children $parent $($rest)*);
@@ -79,7 +108,7 @@ macro_rules! vdom {
     (@add_children $parent:ident $tag:ident $($rest:tt)*) => {
         {
             let child = vdom!($tag);
-            $parent.append_child(&child);
+            $parent.append_child(child);
         };
         vdom!(@add_children $parent $($rest)*);
     };
@@ -95,6 +124,76 @@ macro_rules! vdom {
         $node.add_event_listener(move |$evt : $ty| $body);
     };
 
+    (@set_attrs $node:ident on_create $ty:ty | $dnode:ident | $body:expr ; $( $rest:tt )*) => {
+        $node.on_create(move |$dnode : &$ty| $body);
+        vdom!(@set_attrs $node $($rest)*);
+    };
+
+    (@set_attrs $node:ident on_create $ty:ty | $dnode:ident | $body:expr ) => {
+        $node.on_create(move |$dnode : &$ty| $body);
+    };
+
+    (@set_attrs $node:ident on_update $ty:ty | $dnode:ident | $body:expr ; $( $rest:tt )*) => {
+        $node.on_update(move |$dnode : &$ty| $body);
+        vdom!(@set_attrs $node $($rest)*);
+    };
+
+    (@set_attrs $node:ident on_update $ty:ty | $dnode:ident | $body:expr ) => {
+        $node.on_update(move |$dnode : &$ty| $body);
+    };
+
+    (@set_attrs $node:ident on_remove $ty:ty | $dnode:ident | $body:expr ; $( $rest:tt )*) => {
+        $node.on_remove(move |$dnode : &$ty| $body);
+        vdom!(@set_attrs $node $($rest)*);
+    };
+
+    (@set_attrs $node:ident on_remove $ty:ty | $dnode:ident | $body:expr ) => {
+        $node.on_remove(move |$dnode : &$ty| $body);
+    };
+
+    // hyphenated names such as `data-id` or `aria-label` are set as attributes
+    (@set_attrs $node:ident $first:ident $(- $part:ident)+ = $value:expr ; $( $rest:tt )*) => {
+        $node.add_attribute($crate::Attr::attribute(
+            concat!(stringify!($first) $(, "-", stringify!($part))+),
+            $value,
+        ));
+        vdom!(@set_attrs $node $($rest)*);
+    };
+
+    (@set_attrs $node:ident $first:ident $(- $part:ident)+ = $value:expr ) => {
+        $node.add_attribute($crate::Attr::attribute(
+            concat!(stringify!($first) $(, "-", stringify!($part))+),
+            $value,
+        ));
+    };
+
+    (@set_attrs $node:ident ref = $value:expr ; $( $rest:tt )*) => {
+        $node.set_ref($value);
+        vdom!(@set_attrs $node $($rest)*);
+    };
+
+    (@set_attrs $node:ident ref = $value:expr ) => {
+        $node.set_ref($value);
+    };
+
+    (@set_attrs $node:ident xmlns = $value:expr ; $( $rest:tt )*) => {
+        $node.set_namespace($value);
+        vdom!(@set_attrs $node $($rest)*);
+    };
+
+    (@set_attrs $node:ident xmlns = $value:expr ) => {
+        $node.set_namespace($value);
+    };
+
+    (@set_attrs $node:ident style = $value:expr ; $( $rest:tt )*) => {
+        $node.set_styles($value);
+        vdom!(@set_attrs $node $($rest)*);
+    };
+
+    (@set_attrs $node:ident style = $value:expr ) => {
+        $node.set_styles($value);
+    };
+
     (@set_attrs $node:ident $name:ident = $value:expr ; $( $rest:tt )*) => {
         $node.add_attribute($name($value));
         vdom!(@set_attrs $node $($rest)*);
@@ -108,28 +207,45 @@ macro_rules! vdom {
 }
 
 
-/// Generates a public newtype wrapper for an HTML attribute and an
-/// implementation of super::Attribute.
+/// Generates a public factory for an HTML attribute whose value has to
+/// convert into the given type.
 ///
 /// Example:
 ///
-/// ```rust
-/// attr!(class, property, className);
+/// ```rust,ignore
+/// attr!(class, property, className, String);
 ///
 /// vdom!(p { class = "Neat" })
-/// ```rust
+/// ```
 macro_rules! attr {
-    ($rust_name:ident, $attr_fn:ident, $html_name:ident) => {
-        /// Instantiate an `Attr` that will set the DOM elements `$html_name` $attr_fn.
-        pub fn $rust_name<T: Into<::stdweb::Value>>(val: T) -> Attr {
-            super::attribute::Attr::$attr_fn(stringify!($html_name), val)
+    ($rust_name:ident, $attr_fn:ident, $html_name:tt, $ty:ty) => {
+        doc_comment! {
+            concat!("Set the `", attr_name!($html_name), "` ", stringify!($attr_fn), "."),
+            pub fn $rust_name<T: Into<$ty>>(val: T) -> super::attribute::TypedAttr<$ty> {
+                super::attribute::TypedAttr::$attr_fn(attr_name!($html_name), val.into())
+            }
         }
     }
 }
 
+/// Attach a doc comment put together with `concat!` to an item.
+macro_rules! doc_comment {
+    ($doc:expr, $($item:tt)*) => {
+        #[doc = $doc]
+        $($item)*
+    }
+}
+
+/// The DOM name for `attr!`, either an identifier or a string literal for
+/// names that aren't valid identifiers (e.g. `"stroke-width"`).
+macro_rules! attr_name {
+    ($name:ident) => { stringify!($name) };
+    ($name:expr) => { $name };
+}
+
 /// Generates attribute factory functions.
 macro_rules! attrs {
-    [$(($rust_name:ident, $attr_fn:ident, $html_name:ident)),*] => {
-        $(attr!($rust_name, $attr_fn, $html_name);)*
+    [$(($rust_name:ident, $attr_fn:ident, $html_name:tt, $ty:ty)),*] => {
+        $(attr!($rust_name, $attr_fn, $html_name, $ty);)*
     }
 }
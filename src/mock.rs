@@ -0,0 +1,635 @@
+//! An in-memory `Backend`, for testing rendering without a browser.
+//!
+//! Events are dispatched synchronously with `MockNode::dispatch`. Listeners
+//! receive the dispatched value itself as their raw event, so an event type
+//! used with the mock backend only has to downcast to itself.
+
+use std::any::Any;
+use std::boxed::FnBox;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::mem;
+use std::rc::{Rc, Weak};
+use std::sync::Arc;
+
+use futures::{Async, Future};
+use futures::executor::{self, Notify, Spawn};
+
+use super::attribute::Value;
+use super::backend::{Backend, EventHandler, Executor, GlobalTarget, ListenerHandle};
+use super::batch::{self, Batch};
+use super::events::Event;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MockBackend;
+
+impl MockBackend {
+    /// Run the callbacks from every `request_animation_frame` so far. Returns
+    /// how many there were.
+    pub fn run_animation_frames(&self) -> usize {
+        let callbacks: Vec<_> = FRAMES.with(|frames| frames.borrow_mut().drain(..).collect());
+        let count = callbacks.len();
+        for callback in callbacks {
+            callback();
+        }
+        count
+    }
+
+    /// How many batches have been applied on this thread so far.
+    pub fn batches(&self) -> usize {
+        BATCHES.with(|batches| batches.get())
+    }
+
+    /// Move the mock clock forward by `ms` milliseconds, calling every
+    /// `set_timeout` and `set_interval` callback that comes due, earliest
+    /// first. Returns how many calls there were.
+    pub fn advance_time(&self, ms: u32) -> usize {
+        let until = NOW.with(|now| now.get()) + ms as u64;
+        let mut count = 0;
+        loop {
+            let next = TIMERS.with(|timers| {
+                let mut timers = timers.borrow_mut();
+                // ties go to whichever timer was set first
+                let due = timers
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, timer)| timer.at <= until)
+                    .min_by_key(|&(_, timer)| (timer.at, timer.id))
+                    .map(|(pos, _)| pos);
+                due.map(|pos| timers.remove(pos))
+            });
+            let Timer { id, at, kind } = match next {
+                Some(timer) => timer,
+                None => break,
+            };
+            NOW.with(|now| now.set(at));
+            count += 1;
+            match kind {
+                TimerKind::Once(callback) => callback(),
+                TimerKind::Every(ms, callback) => {
+                    TIMERS.with(|timers| {
+                        timers.borrow_mut().push(Timer {
+                            id: id,
+                            at: at + ms as u64,
+                            kind: TimerKind::Every(ms, callback.clone()),
+                        })
+                    });
+                    callback();
+                }
+            }
+        }
+        NOW.with(|now| now.set(until));
+        count
+    }
+
+    /// Synchronously call every listener registered for `E` on the window or
+    /// document.
+    pub fn dispatch_global<E: Event>(&self, target: GlobalTarget, event: E) {
+        let handlers: Vec<EventHandler> = GLOBAL_LISTENERS.with(|listeners| {
+            listeners
+                .borrow()
+                .iter()
+                .filter(|&&(_, t, ref l)| t == target && l.event_type == E::event_type())
+                .map(|&(_, _, ref l)| l.handler.clone())
+                .collect()
+        });
+        for handler in handlers {
+            handler(&event as &Any);
+        }
+    }
+
+    /// The event types listened for on the window or document, sorted.
+    pub fn global_listeners(&self, target: GlobalTarget) -> Vec<&'static str> {
+        let mut types: Vec<_> = GLOBAL_LISTENERS.with(|listeners| {
+            listeners
+                .borrow()
+                .iter()
+                .filter(|&&(_, t, _)| t == target)
+                .map(|&(_, _, ref l)| l.event_type)
+                .collect()
+        });
+        types.sort();
+        types
+    }
+
+    /// Poll every future passed to `spawn` once. Returns how many of them
+    /// finished.
+    pub fn run_tasks(&self) -> usize {
+        let tasks = TASKS.with(|tasks| mem::replace(&mut *tasks.borrow_mut(), Vec::new()));
+        let notify = Arc::new(NoNotify);
+        let mut finished = 0;
+        let mut pending = Vec::new();
+        for mut task in tasks {
+            match task.poll_future_notify(&notify, 0) {
+                Ok(Async::NotReady) => pending.push(task),
+                _ => finished += 1,
+            }
+        }
+        TASKS.with(|tasks| {
+            let mut tasks = tasks.borrow_mut();
+            pending.extend(tasks.drain(..));
+            *tasks = pending;
+        });
+        finished
+    }
+}
+
+struct Timer {
+    id: usize,
+    at: u64,
+    kind: TimerKind,
+}
+
+enum TimerKind {
+    Once(Box<FnBox()>),
+    Every(u32, Rc<Fn()>),
+}
+
+fn next_id() -> usize {
+    NEXT_ID.with(|next| {
+        let id = next.get();
+        next.set(id + 1);
+        id
+    })
+}
+
+/// Tasks are only ever polled by `run_tasks`, so there's nobody to wake up.
+struct NoNotify;
+
+impl Notify for NoNotify {
+    fn notify(&self, _: usize) {}
+}
+
+/// A node in a mock document. Cloning a `MockNode` clones the handle, not the
+/// node, and two handles are equal only if they refer to the same node.
+#[derive(Clone)]
+pub struct MockNode(Rc<RefCell<NodeData>>);
+
+struct NodeData {
+    kind: NodeKind,
+    parent: Option<Weak<RefCell<NodeData>>>,
+    children: Vec<MockNode>,
+}
+
+enum NodeKind {
+    Text(String),
+    Comment(String),
+    Element {
+        tag: String,
+        namespace: Option<String>,
+        attributes: BTreeMap<String, Value>,
+        properties: BTreeMap<String, Value>,
+        styles: BTreeMap<String, String>,
+        listeners: Vec<MockListener>,
+    },
+}
+
+struct MockListener {
+    id: usize,
+    event_type: &'static str,
+    handler: EventHandler,
+}
+
+/// A plain-data copy of a mock node and its descendants, for comparing the
+/// structure of two documents.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Snapshot {
+    Text(String),
+    Comment(String),
+    Element {
+        tag: String,
+        namespace: Option<String>,
+        attributes: BTreeMap<String, Value>,
+        properties: BTreeMap<String, Value>,
+        styles: BTreeMap<String, String>,
+        listeners: Vec<&'static str>,
+        children: Vec<Snapshot>,
+    },
+}
+
+impl MockNode {
+    fn new(kind: NodeKind) -> Self {
+        MockNode(Rc::new(RefCell::new(NodeData {
+            kind: kind,
+            parent: None,
+            children: Vec::new(),
+        })))
+    }
+
+    pub fn element(tag: &str) -> Self {
+        MockNode::new(NodeKind::Element {
+            tag: tag.to_owned(),
+            namespace: None,
+            attributes: BTreeMap::new(),
+            properties: BTreeMap::new(),
+            styles: BTreeMap::new(),
+            listeners: Vec::new(),
+        })
+    }
+
+    pub fn element_ns(namespace: &str, tag: &str) -> Self {
+        MockNode::new(NodeKind::Element {
+            tag: tag.to_owned(),
+            namespace: Some(namespace.to_owned()),
+            attributes: BTreeMap::new(),
+            properties: BTreeMap::new(),
+            styles: BTreeMap::new(),
+            listeners: Vec::new(),
+        })
+    }
+
+    pub fn text(text: &str) -> Self {
+        MockNode::new(NodeKind::Text(text.to_owned()))
+    }
+
+    pub fn comment(data: &str) -> Self {
+        MockNode::new(NodeKind::Comment(data.to_owned()))
+    }
+
+    pub fn is_comment(&self) -> bool {
+        match self.0.borrow().kind {
+            NodeKind::Comment(_) => true,
+            _ => false,
+        }
+    }
+
+    pub fn tag(&self) -> Option<String> {
+        match self.0.borrow().kind {
+            NodeKind::Element { ref tag, .. } => Some(tag.clone()),
+            NodeKind::Text(_) | NodeKind::Comment(_) => None,
+        }
+    }
+
+    /// The namespace an element was created in, `None` for plain HTML
+    /// elements and text nodes.
+    pub fn namespace(&self) -> Option<String> {
+        match self.0.borrow().kind {
+            NodeKind::Element { ref namespace, .. } => namespace.clone(),
+            NodeKind::Text(_) | NodeKind::Comment(_) => None,
+        }
+    }
+
+    pub fn text_content(&self) -> String {
+        match self.0.borrow().kind {
+            NodeKind::Text(ref text) | NodeKind::Comment(ref text) => text.clone(),
+            NodeKind::Element { .. } => {
+                self.children()
+                    .iter()
+                    .filter(|c| !c.is_comment())
+                    .map(|c| c.text_content())
+                    .collect()
+            }
+        }
+    }
+
+    pub fn attribute(&self, key: &str) -> Option<Value> {
+        match self.0.borrow().kind {
+            NodeKind::Element { ref attributes, .. } => attributes.get(key).cloned(),
+            NodeKind::Text(_) | NodeKind::Comment(_) => None,
+        }
+    }
+
+    pub fn property(&self, key: &str) -> Option<Value> {
+        match self.0.borrow().kind {
+            NodeKind::Element { ref properties, .. } => properties.get(key).cloned(),
+            NodeKind::Text(_) | NodeKind::Comment(_) => None,
+        }
+    }
+
+    pub fn style(&self, property: &str) -> Option<String> {
+        match self.0.borrow().kind {
+            NodeKind::Element { ref styles, .. } => styles.get(property).cloned(),
+            NodeKind::Text(_) | NodeKind::Comment(_) => None,
+        }
+    }
+
+    pub fn parent(&self) -> Option<MockNode> {
+        self.0.borrow().parent.as_ref().and_then(Weak::upgrade).map(MockNode)
+    }
+
+    pub fn children(&self) -> Vec<MockNode> {
+        self.0.borrow().children.clone()
+    }
+
+    /// The ids of the listeners registered on this element, in the order
+    /// they were added. Every listener added gets a new id.
+    pub fn listener_ids(&self) -> Vec<usize> {
+        self.with_element(|_, _, listeners| listeners.iter().map(|l| l.id).collect())
+    }
+
+    /// Synchronously call every listener registered for `E` on this node.
+    /// Events don't bubble.
+    pub fn dispatch<E: Event>(&self, event: E) {
+        let handlers: Vec<EventHandler> = match self.0.borrow().kind {
+            NodeKind::Element { ref listeners, .. } => {
+                listeners
+                    .iter()
+                    .filter(|l| l.event_type == E::event_type())
+                    .map(|l| l.handler.clone())
+                    .collect()
+            }
+            NodeKind::Text(_) | NodeKind::Comment(_) => Vec::new(),
+        };
+        for handler in handlers {
+            handler(&event as &Any);
+        }
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        let data = self.0.borrow();
+        match data.kind {
+            NodeKind::Text(ref text) => Snapshot::Text(text.clone()),
+            NodeKind::Comment(ref data) => Snapshot::Comment(data.clone()),
+            NodeKind::Element {
+                ref tag,
+                ref namespace,
+                ref attributes,
+                ref properties,
+                ref styles,
+                ref listeners,
+            } => {
+                let mut listener_types: Vec<_> = listeners.iter().map(|l| l.event_type).collect();
+                listener_types.sort();
+                Snapshot::Element {
+                    tag: tag.clone(),
+                    namespace: namespace.clone(),
+                    attributes: attributes.clone(),
+                    properties: properties.clone(),
+                    styles: styles.clone(),
+                    listeners: listener_types,
+                    children: data.children.iter().map(MockNode::snapshot).collect(),
+                }
+            }
+        }
+    }
+
+    fn position_of(&self, child: &MockNode) -> usize {
+        self.0
+            .borrow()
+            .children
+            .iter()
+            .position(|c| c == child)
+            .expect("node is not a child of this parent")
+    }
+
+    fn detach(&self) {
+        if let Some(parent) = self.parent() {
+            let pos = parent.position_of(self);
+            parent.0.borrow_mut().children.remove(pos);
+        }
+        self.0.borrow_mut().parent = None;
+    }
+
+    fn insert_at(&self, pos: usize, child: &MockNode) {
+        child.detach();
+        child.0.borrow_mut().parent = Some(Rc::downgrade(&self.0));
+        self.0.borrow_mut().children.insert(pos, child.clone());
+    }
+
+    fn with_element<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce(&mut BTreeMap<String, Value>, &mut BTreeMap<String, Value>, &mut Vec<MockListener>) -> T,
+    {
+        match self.0.borrow_mut().kind {
+            NodeKind::Element {
+                ref mut attributes,
+                ref mut properties,
+                ref mut listeners,
+                ..
+            } => f(attributes, properties, listeners),
+            _ => panic!("expected an element, found a text or comment node"),
+        }
+    }
+}
+
+impl PartialEq for MockNode {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Debug for MockNode {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{:?}", self.snapshot())
+    }
+}
+
+thread_local! {
+    static NEXT_ID: Cell<usize> = Cell::new(0);
+    static BATCHES: Cell<usize> = Cell::new(0);
+    static FRAMES: RefCell<Vec<Box<FnBox()>>> = RefCell::new(Vec::new());
+    static NOW: Cell<u64> = Cell::new(0);
+    static TIMERS: RefCell<Vec<Timer>> = RefCell::new(Vec::new());
+    static GLOBAL_LISTENERS: RefCell<Vec<(usize, GlobalTarget, MockListener)>> = RefCell::new(Vec::new());
+    static TASKS: RefCell<Vec<Spawn<Box<Future<Item = (), Error = ()>>>>> = RefCell::new(Vec::new());
+}
+
+impl Backend for MockBackend {
+    type Node = MockNode;
+
+    fn create_element(&self, tag: &str) -> MockNode {
+        MockNode::element(tag)
+    }
+
+    fn create_element_ns(&self, namespace: &str, tag: &str) -> MockNode {
+        MockNode::element_ns(namespace, tag)
+    }
+
+    fn create_text_node(&self, text: &str) -> MockNode {
+        MockNode::text(text)
+    }
+
+    fn set_text_content(&self, node: &MockNode, text: &str) {
+        for child in node.children() {
+            child.detach();
+        }
+        match node.0.borrow_mut().kind {
+            NodeKind::Text(ref mut content) | NodeKind::Comment(ref mut content) => {
+                *content = text.to_owned();
+                return;
+            }
+            NodeKind::Element { .. } => {}
+        }
+        node.insert_at(0, &MockNode::text(text));
+    }
+
+    fn tag_name(&self, node: &MockNode) -> Option<String> {
+        node.tag()
+    }
+
+    fn text_content(&self, node: &MockNode) -> Option<String> {
+        Some(node.text_content())
+    }
+
+    fn is_comment(&self, node: &MockNode) -> bool {
+        node.is_comment()
+    }
+
+    fn parent_node(&self, node: &MockNode) -> Option<MockNode> {
+        node.parent()
+    }
+
+    fn first_child(&self, node: &MockNode) -> Option<MockNode> {
+        node.0.borrow().children.first().cloned()
+    }
+
+    fn last_child(&self, node: &MockNode) -> Option<MockNode> {
+        node.0.borrow().children.last().cloned()
+    }
+
+    fn next_sibling(&self, node: &MockNode) -> Option<MockNode> {
+        node.parent().and_then(|parent| {
+            let pos = parent.position_of(node);
+            parent.0.borrow().children.get(pos + 1).cloned()
+        })
+    }
+
+    fn child_nodes(&self, node: &MockNode) -> Vec<MockNode> {
+        node.children()
+    }
+
+    fn append_child(&self, parent: &MockNode, child: &MockNode) {
+        child.detach();
+        let len = parent.0.borrow().children.len();
+        parent.insert_at(len, child);
+    }
+
+    fn insert_before(&self, parent: &MockNode, child: &MockNode, reference: &MockNode) {
+        child.detach();
+        let pos = parent.position_of(reference);
+        parent.insert_at(pos, child);
+    }
+
+    fn remove_child(&self, parent: &MockNode, child: &MockNode) {
+        parent.position_of(child);
+        child.detach();
+    }
+
+    fn replace_child(&self, parent: &MockNode, new_child: &MockNode, old_child: &MockNode) {
+        new_child.detach();
+        let pos = parent.position_of(old_child);
+        old_child.detach();
+        parent.insert_at(pos, new_child);
+    }
+
+    fn set_attribute(&self, node: &MockNode, key: &str, value: &Value) {
+        node.with_element(|attributes, _, _| {
+            attributes.insert(key.to_owned(), value.clone());
+        })
+    }
+
+    fn remove_attribute(&self, node: &MockNode, key: &str) {
+        node.with_element(|attributes, _, _| { attributes.remove(key); })
+    }
+
+    /// Namespaced attributes are stored under their qualified name, e.g.
+    /// `xlink:href`.
+    fn set_attribute_ns(&self, node: &MockNode, _: &str, key: &str, value: &Value) {
+        self.set_attribute(node, key, value)
+    }
+
+    fn remove_attribute_ns(&self, node: &MockNode, _: &str, key: &str) {
+        self.remove_attribute(node, key)
+    }
+
+    fn set_property(&self, node: &MockNode, key: &str, value: &Value) {
+        node.with_element(|_, properties, _| {
+            properties.insert(key.to_owned(), value.clone());
+        })
+    }
+
+    fn remove_property(&self, node: &MockNode, key: &str) {
+        node.with_element(|_, properties, _| { properties.remove(key); })
+    }
+
+    fn set_style(&self, node: &MockNode, property: &str, value: &str) {
+        if let NodeKind::Element { ref mut styles, .. } = node.0.borrow_mut().kind {
+            styles.insert(property.to_owned(), value.to_owned());
+        }
+    }
+
+    fn remove_style(&self, node: &MockNode, property: &str) {
+        if let NodeKind::Element { ref mut styles, .. } = node.0.borrow_mut().kind {
+            styles.remove(property);
+        }
+    }
+
+    fn add_event_listener(
+        &self,
+        node: &MockNode,
+        event_type: &'static str,
+        handler: EventHandler,
+    ) -> ListenerHandle {
+        let id = next_id();
+        node.with_element(|_, _, listeners| {
+            listeners.push(MockListener {
+                id: id,
+                event_type: event_type,
+                handler: handler,
+            })
+        });
+        let node = Rc::downgrade(&node.0);
+        ListenerHandle::new(move || if let Some(node) = node.upgrade() {
+            MockNode(node).with_element(|_, _, listeners| {
+                listeners.retain(|l| l.id != id)
+            })
+        })
+    }
+
+    fn request_animation_frame(&self, callback: Box<FnBox()>) {
+        FRAMES.with(|frames| frames.borrow_mut().push(callback));
+    }
+
+    fn apply_batch(&self, batch: &Batch<MockNode>) -> Vec<MockNode> {
+        BATCHES.with(|batches| batches.set(batches.get() + 1));
+        batch::run(self, batch)
+    }
+}
+
+impl Executor for MockBackend {
+    fn set_timeout(&self, ms: u32, callback: Box<FnBox()>) {
+        let timer = Timer {
+            id: next_id(),
+            at: NOW.with(|now| now.get()) + ms as u64,
+            kind: TimerKind::Once(callback),
+        };
+        TIMERS.with(|timers| timers.borrow_mut().push(timer));
+    }
+
+    fn set_interval(&self, ms: u32, callback: Rc<Fn()>) -> ListenerHandle {
+        let id = next_id();
+        let timer = Timer {
+            id: id,
+            at: NOW.with(|now| now.get()) + ms as u64,
+            kind: TimerKind::Every(ms, callback),
+        };
+        TIMERS.with(|timers| timers.borrow_mut().push(timer));
+        // the handle may be dropped along with the thread's timers
+        ListenerHandle::new(move || {
+            let _ = TIMERS.try_with(|timers| timers.borrow_mut().retain(|timer| timer.id != id));
+        })
+    }
+
+    fn spawn(&self, future: Box<Future<Item = (), Error = ()>>) {
+        TASKS.with(|tasks| tasks.borrow_mut().push(executor::spawn(future)));
+    }
+
+    fn add_global_listener(
+        &self,
+        target: GlobalTarget,
+        event_type: &'static str,
+        handler: EventHandler,
+    ) -> ListenerHandle {
+        let id = next_id();
+        let listener = MockListener {
+            id: id,
+            event_type: event_type,
+            handler: handler,
+        };
+        GLOBAL_LISTENERS.with(|listeners| listeners.borrow_mut().push((id, target, listener)));
+        ListenerHandle::new(move || {
+            let _ = GLOBAL_LISTENERS.try_with(|listeners| listeners.borrow_mut().retain(|l| l.0 != id));
+        })
+    }
+}
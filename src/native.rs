@@ -0,0 +1,218 @@
+//! An `Executor` for running components outside a browser, e.g. a remote
+//! `Session` on a server.
+//!
+//! Nothing runs on its own: `run_pending` calls the timers that came due and
+//! polls the futures that were woken, and `next_due` says how long it may be
+//! put off. `Session::serve` does both while it waits for the client, `run`
+//! for a program that has nothing else to wait for.
+
+use std::boxed::FnBox;
+use std::cell::RefCell;
+use std::cmp;
+use std::mem;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use futures::{Async, Future};
+use futures::executor::{self, Notify, Spawn};
+
+use super::backend::{EventHandler, Executor, GlobalTarget, ListenerHandle};
+
+/// How long a pending future may go without being polled, in milliseconds.
+/// Futures woken on this thread are polled by the next `run_pending`, but a
+/// wakeup from another thread can't interrupt whatever the caller waits on in
+/// between, so they are polled at least this often.
+pub const TASK_POLL_MS: u64 = 10;
+
+/// Timers and futures for one thread. Clones share the same queue.
+#[derive(Clone, Default)]
+pub struct NativeExecutor(Rc<RefCell<Queue>>);
+
+#[derive(Default)]
+struct Queue {
+    next_id: usize,
+    timers: Vec<Timer>,
+    tasks: Vec<(usize, Spawn<Box<Future<Item = (), Error = ()>>>)>,
+    woken: Arc<Woken>,
+}
+
+impl Queue {
+    fn next_id(&mut self) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+}
+
+struct Timer {
+    id: usize,
+    at: Instant,
+    kind: TimerKind,
+}
+
+enum TimerKind {
+    Once(Box<FnBox()>),
+    Every(Duration, Rc<Fn()>),
+}
+
+/// The ids of the tasks that asked to be polled again.
+#[derive(Default)]
+struct Woken(Mutex<Vec<usize>>);
+
+impl Woken {
+    fn take(&self) -> Vec<usize> {
+        mem::replace(&mut *self.0.lock().unwrap(), Vec::new())
+    }
+}
+
+impl Notify for Woken {
+    fn notify(&self, id: usize) {
+        self.0.lock().unwrap().push(id);
+    }
+}
+
+impl NativeExecutor {
+    pub fn new() -> Self {
+        NativeExecutor::default()
+    }
+
+    /// Call every timer that is due, earliest first, then poll every future
+    /// that was spawned or woken since the last call. Returns how many
+    /// timers and polls there were.
+    pub fn run_pending(&self) -> usize {
+        let now = Instant::now();
+        let mut count = 0;
+        while let Some(timer) = self.take_due(now) {
+            count += 1;
+            match timer.kind {
+                TimerKind::Once(callback) => callback(),
+                TimerKind::Every(interval, callback) => {
+                    // skip the calls missed by falling behind, rather than
+                    // catching up on them all at once
+                    let mut at = timer.at + interval;
+                    if at <= now {
+                        at = now + interval;
+                    }
+                    self.0.borrow_mut().timers.push(Timer {
+                        id: timer.id,
+                        at: at,
+                        kind: TimerKind::Every(interval, callback.clone()),
+                    });
+                    callback();
+                }
+            }
+        }
+
+        let woken = self.0.borrow().woken.clone();
+        for id in woken.take() {
+            // a task woken twice, or that has already finished, is skipped
+            let task = {
+                let mut queue = self.0.borrow_mut();
+                let pos = queue.tasks.iter().position(|&(task, _)| task == id);
+                pos.map(|pos| queue.tasks.swap_remove(pos))
+            };
+            let (id, mut task) = match task {
+                Some(task) => task,
+                None => continue,
+            };
+            count += 1;
+            if let Ok(Async::NotReady) = task.poll_future_notify(&woken, id) {
+                self.0.borrow_mut().tasks.push((id, task));
+            }
+        }
+        count
+    }
+
+    /// How long until `run_pending` has something to do, `None` if there are
+    /// neither timers nor futures left.
+    pub fn next_due(&self) -> Option<Duration> {
+        let queue = self.0.borrow();
+        if !queue.woken.0.lock().unwrap().is_empty() {
+            return Some(Duration::from_millis(0));
+        }
+        let now = Instant::now();
+        let timer = queue.timers.iter().map(|timer| timer.at).min().map(|at| {
+            if at > now { at - now } else { Duration::from_millis(0) }
+        });
+        let task = if queue.tasks.is_empty() {
+            None
+        } else {
+            Some(Duration::from_millis(TASK_POLL_MS))
+        };
+        match (timer, task) {
+            (Some(timer), Some(task)) => Some(cmp::min(timer, task)),
+            (timer, task) => timer.or(task),
+        }
+    }
+
+    /// Run timers and futures as they come due, until there are none left.
+    pub fn run(&self) {
+        loop {
+            self.run_pending();
+            match self.next_due() {
+                Some(wait) => thread::sleep(wait),
+                None => return,
+            }
+        }
+    }
+
+    fn take_due(&self, now: Instant) -> Option<Timer> {
+        let mut queue = self.0.borrow_mut();
+        // ties go to whichever timer was set first
+        let due = queue
+            .timers
+            .iter()
+            .enumerate()
+            .filter(|&(_, timer)| timer.at <= now)
+            .min_by_key(|&(_, timer)| (timer.at, timer.id))
+            .map(|(pos, _)| pos);
+        due.map(|pos| queue.timers.remove(pos))
+    }
+
+    fn add_timer(&self, ms: u32, kind: TimerKind) -> usize {
+        let mut queue = self.0.borrow_mut();
+        let id = queue.next_id();
+        queue.timers.push(Timer {
+            id: id,
+            at: Instant::now() + Duration::from_millis(ms as u64),
+            kind: kind,
+        });
+        id
+    }
+}
+
+impl Executor for NativeExecutor {
+    fn set_timeout(&self, ms: u32, callback: Box<FnBox()>) {
+        self.add_timer(ms, TimerKind::Once(callback));
+    }
+
+    fn set_interval(&self, ms: u32, callback: Rc<Fn()>) -> ListenerHandle {
+        // an interval of 0 would never let `run_pending` return
+        let interval = Duration::from_millis(cmp::max(ms, 1) as u64);
+        let id = self.add_timer(ms, TimerKind::Every(interval, callback));
+        let queue = Rc::downgrade(&self.0);
+        ListenerHandle::new(move || if let Some(queue) = queue.upgrade() {
+            queue.borrow_mut().timers.retain(|timer| timer.id != id)
+        })
+    }
+
+    fn spawn(&self, future: Box<Future<Item = (), Error = ()>>) {
+        let mut queue = self.0.borrow_mut();
+        let id = queue.next_id();
+        queue.tasks.push((id, executor::spawn(future)));
+        queue.woken.notify(id);
+    }
+
+    /// There is no window or document outside a browser, so global listeners
+    /// never fire.
+    fn add_global_listener(
+        &self,
+        _: GlobalTarget,
+        _: &'static str,
+        _: EventHandler,
+    ) -> ListenerHandle {
+        ListenerHandle::new(|| {})
+    }
+}
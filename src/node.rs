@@ -1,32 +1,53 @@
-use std::rc::Rc;
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::boxed::FnBox;
+use std::rc::Rc;
 use std::iter::FromIterator;
 
-use stdweb::web::{Element, EventListenerHandle};
-use stdweb::web::event::ConcreteEvent;
-
-use super::attribute::Attr;
-use super::events::{VListener, ConcreteVListener};
-
-/// shit's complicated (TODO - document how this ended up being the way)
-pub type ListenerInstaller<Msg> = FnBox(&Element, Rc<Fn(Msg)>) -> EventListenerHandle;
+use super::attribute::{Attr, Attribute};
+use super::component::{Callback, Component};
+use super::embed::{Embedded, ListenerMapper};
+use super::events::{Event, VListener, ConcreteVListener, MappedVListener};
+use super::hooks::{Hook, Hooks};
+use super::lazy::Lazy;
+use super::node_ref::NodeRef;
 
+pub const SVG_NAMESPACE: &'static str = "http://www.w3.org/2000/svg";
+pub const MATHML_NAMESPACE: &'static str = "http://www.w3.org/1998/Math/MathML";
 
 pub struct Node<Msg: 'static> {
     pub tag: &'static str,
-    pub attributes: HashMap<&'static str, Attr>,
+    /// The namespace the element is created in, `None` for HTML. Children of
+    /// a namespaced node inherit its namespace when appended, except inside a
+    /// `foreignObject`.
+    pub namespace: Option<&'static str>,
+    pub attributes: HashMap<Cow<'static, str>, Attr>,
+    /// Inline styles, by CSS property name (e.g. `margin-top`).
+    pub styles: HashMap<&'static str, String>,
     pub children: Vec<Child<Msg>>,
     pub listeners: Vec<Box<VListener<Msg>>>,
+    /// Filled with the DOM node rendered for this node, see `NodeRef`.
+    pub node_ref: Option<NodeRef>,
+    pub hooks: Hooks,
 }
 
 impl<Msg> Node<Msg> {
+    /// Create an element. `svg` and `math` elements get their namespace
+    /// automatically.
     pub fn new(tag: &'static str) -> Self {
+        let namespace = match tag {
+            "svg" => Some(SVG_NAMESPACE),
+            "math" => Some(MATHML_NAMESPACE),
+            _ => None,
+        };
         Node {
             tag: tag,
+            namespace: namespace,
             attributes: HashMap::new(),
+            styles: HashMap::new(),
             children: Vec::new(),
             listeners: Vec::new(),
+            node_ref: None,
+            hooks: Hooks::default(),
         }
     }
 
@@ -35,17 +56,49 @@ impl<Msg> Node<Msg> {
         T: Into<Node<Msg>>,
         I: IntoIterator<Item = T>,
     {
-        Node {
-            tag: tag,
-            attributes: HashMap::new(),
-            children: things.into_iter().map(|x| Child::Node(x.into())).collect(),
-            listeners: Vec::new(),
+        let mut node = Node::new(tag);
+        for thing in things {
+            node.append_child(thing.into());
+        }
+        node
+    }
+
+    /// Put this node and every descendant that doesn't have a namespace yet
+    /// into `namespace`.
+    pub fn set_namespace(&mut self, namespace: &'static str) {
+        self.namespace = Some(namespace);
+        if let Some(namespace) = self.child_namespace() {
+            for child in self.children.iter_mut() {
+                child.inherit_namespace(namespace);
+            }
+        }
+    }
+
+    /// Like `set_namespace`, unless this node already has a namespace.
+    pub fn inherit_namespace(&mut self, namespace: &'static str) {
+        if self.namespace.is_none() {
+            self.set_namespace(namespace);
+        }
+    }
+
+    /// The namespace children appended to this node inherit.
+    fn child_namespace(&self) -> Option<&'static str> {
+        match self.tag {
+            "foreignObject" => None,
+            _ => self.namespace,
         }
     }
 
+    fn push_child(&mut self, mut child: Child<Msg>) {
+        if let Some(namespace) = self.child_namespace() {
+            child.inherit_namespace(namespace);
+        }
+        self.children.push(child);
+    }
+
     pub fn add_event_listener<T, F>(&mut self, listener: F)
     where
-        T: ConcreteEvent + 'static,
+        T: Event,
         F: Fn(T) -> Msg + 'static,
     {
         self.listeners.push(
@@ -54,21 +107,296 @@ impl<Msg> Node<Msg> {
     }
 
     pub fn append_child(&mut self, node: Self) {
-        self.children.push(Child::Node(node));
+        self.push_child(Child::Node(node));
+    }
+
+    /// Append a child identified by `key`. When every child of a node is keyed,
+    /// `diff` matches children up by key and moves existing DOM nodes around
+    /// instead of patching them in place.
+    pub fn append_keyed<K: Into<String>>(&mut self, key: K, node: Self) {
+        self.push_child(Child::Keyed(key.into(), node));
     }
 
     pub fn append_string(&mut self, text: String) {
         self.children.push(Child::Text(text))
     }
 
-    pub fn add_attribute(&mut self, attribute: Attr) {
-        self.attributes.insert(attribute.key, attribute);
+    /// Append the children of `fragment`, without an element around them.
+    pub fn append_fragment(&mut self, fragment: Fragment<Msg>) {
+        self.push_child(Child::Fragment(fragment));
+    }
+
+    /// Embed a stateful component. `init` is only called the first time the
+    /// component is rendered; on later renders the existing instance found at
+    /// the same position (or under the same key) is reused.
+    pub fn append_component<C, ChildMsg, F>(&mut self, init: F)
+    where
+        C: Component<ChildMsg>,
+        ChildMsg: 'static,
+        F: FnOnce() -> C + 'static,
+    {
+        self.push_child(Child::Component(Embedded::new(init)));
+    }
+
+    /// Like `append_component`, additionally passing every message the
+    /// component receives to `emit`. Any message `emit` returns is sent on to
+    /// this node's component.
+    pub fn append_component_with<C, ChildMsg, F, E>(&mut self, init: F, emit: E)
+    where
+        C: Component<ChildMsg>,
+        ChildMsg: 'static,
+        F: FnOnce() -> C + 'static,
+        E: Fn(&ChildMsg) -> Option<Msg> + 'static,
+    {
+        self.push_child(Child::Component(Embedded::with_emit(init, emit)));
+    }
+
+    /// Append a subtree rendered by `view(&arg)`. On later renders `view` is
+    /// only called (and the subtree only diffed) if `arg` or `view` changed.
+    pub fn append_lazy<A, F>(&mut self, arg: A, view: F)
+    where
+        A: PartialEq + 'static,
+        F: Fn(&A) -> Node<Msg> + 'static,
+    {
+        self.push_child(Child::Lazy(Lazy::new(arg, view)));
+    }
+
+    /// `append_lazy` for a child in a keyed list, see `append_keyed`.
+    pub fn append_keyed_lazy<K, A, F>(&mut self, key: K, arg: A, view: F)
+    where
+        K: Into<String>,
+        A: PartialEq + 'static,
+        F: Fn(&A) -> Node<Msg> + 'static,
+    {
+        self.push_child(Child::Lazy(Lazy::keyed(key, arg, view)));
+    }
+
+    pub fn add_attribute<A: Attribute>(&mut self, attribute: A) {
+        let attr = attribute.into_attr();
+        self.attributes.insert(attr.key.clone(), attr);
+    }
+
+    pub fn set_ref(&mut self, node_ref: NodeRef) {
+        self.node_ref = Some(node_ref);
+    }
+
+    /// Call `hook` with the DOM node once it has been created. `N` is the
+    /// backend's node type, e.g. `stdweb::web::Node`.
+    pub fn on_create<N: 'static, F: Fn(&N) + 'static>(&mut self, hook: F) {
+        self.hooks.create = Some(Hook::new(hook));
+    }
+
+    /// Call `hook` with the DOM node whenever a new render of this node has
+    /// been patched into it.
+    pub fn on_update<N: 'static, F: Fn(&N) + 'static>(&mut self, hook: F) {
+        self.hooks.update = Some(Hook::new(hook));
+    }
+
+    /// Call `hook` with the DOM node after it has been removed.
+    pub fn on_remove<N: 'static, F: Fn(&N) + 'static>(&mut self, hook: F) {
+        self.hooks.remove = Some(Hook::new(hook));
+    }
+
+    /// Set one CSS property of the inline style. Properties are diffed and
+    /// applied one at a time.
+    pub fn set_style<V: Into<String>>(&mut self, property: &'static str, value: V) {
+        self.styles.insert(property, value.into());
+    }
+
+    pub fn set_styles<I, V>(&mut self, styles: I)
+    where
+        I: IntoIterator<Item = (&'static str, V)>,
+        V: Into<String>,
+    {
+        for (property, value) in styles {
+            self.set_style(property, value);
+        }
+    }
+
+    /// Convert every message this node and its descendants can produce with
+    /// `tagger`, so that a view written for one message type can be nested
+    /// in a view for another.
+    pub fn map<To, F>(self, tagger: F) -> Node<To>
+    where
+        To: 'static,
+        F: Fn(Msg) -> To + 'static,
+    {
+        self.map_listeners(mapper(tagger))
+    }
+
+    /// Rebuild this tree with every listener wrapped in a `MappedVListener`
+    /// with `mapper`.
+    pub fn map_listeners<To: 'static>(self, mapper: ListenerMapper<Msg, To>) -> Node<To> {
+        Node {
+            tag: self.tag,
+            namespace: self.namespace,
+            attributes: self.attributes,
+            styles: self.styles,
+            children: self.children
+                .into_iter()
+                .map(|child| child.map_listeners(mapper.clone()))
+                .collect(),
+            listeners: self.listeners
+                .into_iter()
+                .map(|listener| {
+                    Box::new(MappedVListener::new(listener, mapper.clone())) as Box<VListener<To>>
+                })
+                .collect(),
+            node_ref: self.node_ref,
+            hooks: self.hooks,
+        }
     }
 }
 
+fn mapper<From, To, F>(tagger: F) -> ListenerMapper<From, To>
+where
+    From: 'static,
+    To: 'static,
+    F: Fn(From) -> To + 'static,
+{
+    let tagger: Rc<Fn(From) -> To> = Rc::new(tagger);
+    Rc::new(move |update: Callback<To>| {
+        let tagger = tagger.clone();
+        Rc::new(move |msg| update(tagger(msg))) as Callback<From>
+    })
+}
+
 pub enum Child<Msg: 'static> {
     Text(String),
     Node(Node<Msg>),
+    Keyed(String, Node<Msg>),
+    Component(Embedded<Msg>),
+    Lazy(Lazy<Msg>),
+    Fragment(Fragment<Msg>),
+}
+
+impl<Msg> Child<Msg> {
+    pub fn keyed<K: Into<String>>(key: K, node: Node<Msg>) -> Self {
+        Child::Keyed(key.into(), node)
+    }
+
+    /// The element node of this child, if it is not a text node or a
+    /// fragment. Embedded components and lazy children only have a node once
+    /// they have been resolved.
+    pub fn node(&self) -> Option<&Node<Msg>> {
+        match *self {
+            Child::Text(_) | Child::Fragment(_) => None,
+            Child::Node(ref node) | Child::Keyed(_, ref node) => Some(node),
+            Child::Component(ref embedded) => embedded.rendered(),
+            Child::Lazy(ref lazy) => lazy.rendered(),
+        }
+    }
+
+    pub fn key(&self) -> Option<&str> {
+        match *self {
+            Child::Keyed(ref key, _) => Some(key.as_str()),
+            Child::Lazy(ref lazy) => lazy.key(),
+            _ => None,
+        }
+    }
+
+    fn inherit_namespace(&mut self, namespace: &'static str) {
+        match *self {
+            Child::Text(_) => {}
+            Child::Node(ref mut node) | Child::Keyed(_, ref mut node) => {
+                node.inherit_namespace(namespace)
+            }
+            Child::Component(ref mut embedded) => embedded.inherit_namespace(namespace),
+            Child::Lazy(ref mut lazy) => lazy.inherit_namespace(namespace),
+            Child::Fragment(ref mut fragment) => {
+                for child in fragment.children.iter_mut() {
+                    child.inherit_namespace(namespace);
+                }
+            }
+        }
+    }
+
+    /// See `Node::map`.
+    pub fn map<To, F>(self, tagger: F) -> Child<To>
+    where
+        To: 'static,
+        F: Fn(Msg) -> To + 'static,
+    {
+        self.map_listeners(mapper(tagger))
+    }
+
+    pub fn map_listeners<To: 'static>(self, mapper: ListenerMapper<Msg, To>) -> Child<To> {
+        match self {
+            Child::Text(text) => Child::Text(text),
+            Child::Node(node) => Child::Node(node.map_listeners(mapper)),
+            Child::Keyed(key, node) => Child::Keyed(key, node.map_listeners(mapper)),
+            Child::Component(embedded) => Child::Component(embedded.map_listeners(mapper)),
+            Child::Lazy(lazy) => Child::Lazy(lazy.map_listeners(mapper)),
+            Child::Fragment(fragment) => Child::Fragment(fragment.map_listeners(mapper)),
+        }
+    }
+}
+
+/// Sibling nodes without an element of their own, for helpers that render
+/// several children of their caller's node (e.g. table rows or list items):
+///
+/// ```rust,ignore
+/// fn items(&self) -> Fragment<Msg> {
+///     self.items.iter().map(|item| vdom!(li [ text!(item.as_str()) ])).collect()
+/// }
+///
+/// vdom!(ul [ li [ text!("first") ] fragment!(self.items()) ])
+/// ```
+///
+/// A fragment stays a single child of the node it is appended to, so a
+/// fragment that grows or shrinks between renders doesn't shift the siblings
+/// that follow it: `diff` compares its children with those of the fragment
+/// in the same position, and `apply` keeps an empty text node after them in
+/// the DOM to know where they go. Keyed children of a fragment are matched up
+/// with those of the old fragment, see `Node::append_keyed`.
+pub struct Fragment<Msg: 'static> {
+    pub children: Vec<Child<Msg>>,
+}
+
+impl<Msg> Fragment<Msg> {
+    pub fn new() -> Self {
+        Fragment { children: Vec::new() }
+    }
+
+    pub fn append_child(&mut self, node: Node<Msg>) {
+        self.children.push(Child::Node(node));
+    }
+
+    pub fn append_keyed<K: Into<String>>(&mut self, key: K, node: Node<Msg>) {
+        self.children.push(Child::Keyed(key.into(), node));
+    }
+
+    pub fn append_string(&mut self, text: String) {
+        self.children.push(Child::Text(text));
+    }
+
+    pub fn append_fragment(&mut self, fragment: Fragment<Msg>) {
+        self.children.push(Child::Fragment(fragment));
+    }
+
+    /// See `Node::map`.
+    pub fn map<To, F>(self, tagger: F) -> Fragment<To>
+    where
+        To: 'static,
+        F: Fn(Msg) -> To + 'static,
+    {
+        self.map_listeners(mapper(tagger))
+    }
+
+    pub fn map_listeners<To: 'static>(self, mapper: ListenerMapper<Msg, To>) -> Fragment<To> {
+        Fragment {
+            children: self.children
+                .into_iter()
+                .map(|child| child.map_listeners(mapper.clone()))
+                .collect(),
+        }
+    }
+}
+
+impl<M, C: Into<Child<M>>> FromIterator<C> for Fragment<M> {
+    fn from_iter<T: IntoIterator<Item = C>>(iter: T) -> Self {
+        Fragment { children: iter.into_iter().map(Into::into).collect() }
+    }
 }
 
 impl<M> PartialEq for Child<M> {
@@ -78,6 +406,18 @@ impl<M> PartialEq for Child<M> {
         match (self, other) {
             (&Text(ref s_string), &Text(ref o_string)) => s_string == o_string,
             (&Node(ref s_node), &Node(ref o_node)) => s_node == o_node,
+            (&Keyed(ref s_key, ref s_node), &Keyed(ref o_key, ref o_node)) => {
+                s_key == o_key && s_node == o_node
+            }
+            (&Component(ref s_embedded), &Component(ref o_embedded)) => {
+                s_embedded.rendered() == o_embedded.rendered()
+            }
+            (&Lazy(ref s_lazy), &Lazy(ref o_lazy)) => {
+                s_lazy.key() == o_lazy.key() && s_lazy.rendered() == o_lazy.rendered()
+            }
+            (&Fragment(ref s_fragment), &Fragment(ref o_fragment)) => {
+                s_fragment.children == o_fragment.children
+            }
             _ => false
         }
     }
@@ -88,7 +428,9 @@ use std::fmt::{Debug, Formatter, Result as FmtResult};
 impl<M> PartialEq for Node<M> {
     fn eq(&self, other: &Self) -> bool {
         self.tag == other.tag &&
+        self.namespace == other.namespace &&
         self.attributes == other.attributes &&
+        self.styles == other.styles &&
         self.children.len() == other.children.len() &&
         {
             for (self_child, other_child) in self.children.iter().zip(other.children.iter()) {
@@ -121,17 +463,35 @@ impl<M> Debug for Child<M> {
         match *self {
             Child::Text(ref text) => write!(f, "{:?}", text),
             Child::Node(ref node) => write!(f, "{:?}", node),
+            Child::Keyed(ref key, ref node) => write!(f, "{:?} => {:?}", key, node),
+            Child::Component(ref embedded) => write!(f, "Component({:?})", embedded.rendered()),
+            Child::Lazy(ref lazy) => write!(f, "Lazy({:?})", lazy.rendered()),
+            Child::Fragment(ref fragment) => write!(f, "{:?}", fragment),
         }
     }
 }
 
+impl<M> Debug for Fragment<M> {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "Fragment({:?})", self.children)
+    }
+}
+
+/// Wraps the nodes in a `div`: a `Node` is always a single element, and a
+/// view has to return one, so the collected nodes need an element to live
+/// in. Collect into a `Fragment` instead to append them to a node without a
+/// wrapper.
 impl<M> FromIterator<Node<M>> for Node<M> {
     fn from_iter<T: IntoIterator<Item = Node<M>>>(iter: T) -> Self {
         Node {
             tag: "div",
+            namespace: None,
             attributes: HashMap::new(),
+            styles: HashMap::new(),
             children: iter.into_iter().map(Child::Node).collect(),
             listeners: Vec::new(),
+            node_ref: None,
+            hooks: Hooks::default(),
         }
     }
 }
@@ -142,6 +502,12 @@ impl<M> From<Node<M>> for Child<M> {
     }
 }
 
+impl<M> From<Fragment<M>> for Child<M> {
+    fn from(fragment: Fragment<M>) -> Self {
+        Child::Fragment(fragment)
+    }
+}
+
 impl<M, T> From<T> for Child<M>
 where
     T: Into<String>,
@@ -150,11 +516,3 @@ where
         Child::Text(text.into())
     }
 }
-
-/*
-impl<M> From<(usize, Node<M>)> for Child<M> {
-    fn from(node: Node<M>) -> Self {
-        Child::KeyedNode(usize, node)
-    }
-}
-*/
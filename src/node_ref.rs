@@ -0,0 +1,62 @@
+//! References from a view to the real DOM nodes rendered for it.
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::rc::Rc;
+
+/// A handle to the DOM node rendered for a virtual node, for things the
+/// virtual DOM can't express such as `focus()`, measuring or handing the
+/// element to a third-party widget.
+///
+/// Keep a `NodeRef` in the component and attach a clone of it in `view`:
+///
+/// ```rust,ignore
+/// vdom!(input { ref = self.input.clone() })
+/// ```
+///
+/// It is filled in when the element is created (or hydrated, or when the
+/// ref is moved onto an existing element) and emptied when the element is
+/// removed. Clones share the same node.
+#[derive(Clone, Default)]
+pub struct NodeRef(Rc<RefCell<Option<Box<Any>>>>);
+
+impl NodeRef {
+    pub fn new() -> Self {
+        NodeRef::default()
+    }
+
+    /// The node, if it is currently rendered. `N` is the backend's node
+    /// type, e.g. `stdweb::web::Node` for the `StdwebBackend`.
+    pub fn get<N: Clone + 'static>(&self) -> Option<N> {
+        self.0.borrow().as_ref().and_then(|node| node.downcast_ref::<N>()).cloned()
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.0.borrow().is_some()
+    }
+
+    pub fn set<N: 'static>(&self, node: N) {
+        *self.0.borrow_mut() = Some(Box::new(node));
+    }
+
+    /// Empty the ref if it still refers to `node`. Refs that have moved on to
+    /// another node in the meantime are left alone.
+    pub fn clear_if<N: Clone + PartialEq + 'static>(&self, node: &N) {
+        if self.get::<N>().as_ref() == Some(node) {
+            *self.0.borrow_mut() = None;
+        }
+    }
+}
+
+impl PartialEq for NodeRef {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Debug for NodeRef {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "NodeRef({})", if self.is_set() { "set" } else { "empty" })
+    }
+}
@@ -0,0 +1,123 @@
+use super::apply::apply;
+use super::backend::Backend;
+use super::cmd::Cmd;
+use super::component::Component;
+use super::diff::diff;
+use super::mock::{MockBackend, MockNode};
+use super::test_support::{ignore, mount_sync};
+use super::root::{create_element, create_tree, hydrate_with};
+use super::{Node, NodeRef};
+
+/// Render `old`, patch it to `new` and return the container.
+fn patch(old: &Node<()>, new: &Node<()>) -> MockNode {
+    let container = MockNode::element("body");
+    let tree = create_tree(&MockBackend, old, &ignore());
+    MockBackend.append_child(&container, &tree.node());
+    apply(&MockBackend, &tree, diff(old, new), ignore());
+    container
+}
+
+fn tag(node_ref: &NodeRef) -> Option<String> {
+    node_ref.get::<MockNode>().and_then(|node| node.tag())
+}
+
+/// Shows a search box until toggled.
+struct Search {
+    open: bool,
+    input: NodeRef,
+}
+
+impl Component<()> for Search {
+    fn view(&self) -> Node<()> {
+        if self.open {
+            vdom!(form [ p [ input { ref = self.input.clone() } ] ])
+        } else {
+            vdom!(form)
+        }
+    }
+
+    fn update(&mut self, _: ()) -> Cmd<()> {
+        self.open = !self.open;
+        Cmd::none()
+    }
+}
+
+#[test]
+fn test_ref_follows_component() {
+    let input = NodeRef::new();
+    let (root, container) = mount_sync(Search { open: true, input: input.clone() });
+
+    let first = input.get::<MockNode>().unwrap();
+    assert_eq!(first, container.children()[0].children()[0].children()[0]);
+
+    root.send(());
+    assert_eq!(input.get::<MockNode>(), None);
+
+    root.send(());
+    let second = input.get::<MockNode>().unwrap();
+    assert!(second != first);
+    assert_eq!(second.parent().and_then(|p| p.tag()), Some("p".to_string()));
+}
+
+#[test]
+fn test_ref_moved_between_nodes() {
+    let a = NodeRef::new();
+    let b = NodeRef::new();
+    let old: Node<()> = vdom!(div [ p { ref = a.clone() } span { ref = b.clone() } ]);
+    let new: Node<()> = vdom!(div [ p { ref = b.clone() } span ]);
+    let container = patch(&old, &new);
+
+    assert_eq!(b.get::<MockNode>(), Some(container.children()[0].children()[0].clone()));
+    assert!(!a.is_set());
+}
+
+#[test]
+fn test_ref_cleared_with_replaced_ancestor() {
+    let inner = NodeRef::new();
+    let kept = NodeRef::new();
+    let old: Node<()> = vdom!(div [ p [ b { ref = inner.clone() } ] em { ref = kept.clone() } ]);
+    let new: Node<()> = vdom!(div [ text!("gone") em { ref = kept.clone() } ]);
+    patch(&old, &new);
+
+    assert!(!inner.is_set());
+    assert_eq!(tag(&kept), Some("em".to_string()));
+}
+
+#[test]
+fn test_ref_moves_to_replacement() {
+    let node_ref = NodeRef::new();
+    let old: Node<()> = vdom!(div [ p { ref = node_ref.clone() } ]);
+    let new: Node<()> = vdom!(div [ section { ref = node_ref.clone() } ]);
+    patch(&old, &new);
+
+    assert_eq!(tag(&node_ref), Some("section".to_string()));
+}
+
+#[test]
+fn test_ref_cleared_for_removed_keyed_child() {
+    let first = NodeRef::new();
+    let second = NodeRef::new();
+    let old: Node<()> = vdom!(ul [
+        keyed!("1", vdom!(li { ref = first.clone() }))
+        keyed!("2", vdom!(li [ b { ref = second.clone() } ]))
+    ]);
+    let new: Node<()> = vdom!(ul [ keyed!("1", vdom!(li { ref = first.clone() })) ]);
+    let container = patch(&old, &new);
+
+    assert_eq!(first.get::<MockNode>(), Some(container.children()[0].children()[0].clone()));
+    assert!(!second.is_set());
+}
+
+#[test]
+fn test_ref_set_by_hydrate() {
+    let input = NodeRef::new();
+    let search = Search { open: true, input: NodeRef::new() };
+    let dnode = create_element(&MockBackend, &search.view(), &ignore());
+    let container = MockNode::element("body");
+    MockBackend.append_child(&container, &dnode);
+
+    let search = Search { open: true, input: input.clone() };
+    let (_root, mismatches) = hydrate_with(MockBackend, search, dnode.clone());
+    assert_eq!(mismatches, vec![]);
+    assert_eq!(input.get::<MockNode>(), Some(dnode.children()[0].children()[0].clone()));
+}
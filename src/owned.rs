@@ -0,0 +1,247 @@
+//! Patches that own their data, for keeping or sending them somewhere.
+//!
+//! A `diff::Patch` borrows from the trees it was computed from, so it can't
+//! outlive a redraw. `OwnedPatch` copies what applying it takes: attribute
+//! values, text and snapshots of new nodes. It can be serialized with serde,
+//! e.g. to JSON or bincode.
+//!
+//! Listeners are closures and stay behind; patches refer to them by
+//! `ListenerId`. A listener replacing one for the same event keeps its id, so
+//! replacing listeners has no owned form, and neither have refs and
+//! lifecycle hooks, which only make sense next to the DOM they were rendered
+//! into.
+
+use std::fmt::Debug;
+
+use super::attribute::{prefix_namespace, Attr, AttrKind, Value};
+use super::backend::Backend;
+use super::diff::{KeyedOp, Operation, Patch, Path};
+use super::events::VListener;
+use super::node::{Child, Node};
+
+/// Identifies a listener of the tree a patch was computed for: its event
+/// type, and `VListener::id`. An id stays with the listeners rendered in the
+/// same place for the same event until that event's listener is removed,
+/// and isn't reused after that, so an id that outlived its listener can't be
+/// mistaken for another one.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ListenerId {
+    pub event: String,
+    pub id: u64,
+}
+
+impl ListenerId {
+    pub fn of<M>(listener: &VListener<M>) -> Self {
+        ListenerId {
+            event: listener.key().to_owned(),
+            id: listener.id(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AttrSnapshot {
+    pub kind: AttrKind,
+    pub key: String,
+    pub value: Value,
+}
+
+impl AttrSnapshot {
+    /// Like `Attr::set`.
+    pub fn set<B: Backend>(&self, backend: &B, element: &B::Node) {
+        match (self.kind, prefix_namespace(&self.key)) {
+            (AttrKind::Attribute, Some(ns)) => {
+                backend.set_attribute_ns(element, ns, &self.key, &self.value)
+            }
+            (AttrKind::Attribute, None) => backend.set_attribute(element, &self.key, &self.value),
+            (AttrKind::Property, _) => backend.set_property(element, &self.key, &self.value),
+        }
+    }
+
+    /// Like `Attr::remove`.
+    pub fn remove<B: Backend>(&self, backend: &B, element: &B::Node) {
+        match (self.kind, prefix_namespace(&self.key)) {
+            (AttrKind::Attribute, Some(ns)) => backend.remove_attribute_ns(element, ns, &self.key),
+            (AttrKind::Attribute, None) => backend.remove_attribute(element, &self.key),
+            (AttrKind::Property, _) => backend.remove_property(element, &self.key),
+        }
+    }
+}
+
+impl<'a> From<&'a Attr> for AttrSnapshot {
+    fn from(attr: &'a Attr) -> Self {
+        AttrSnapshot {
+            kind: attr.kind(),
+            key: attr.key.to_string(),
+            value: attr.value.clone(),
+        }
+    }
+}
+
+/// A copy of a rendered node. Attributes and styles are sorted by name.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeSnapshot {
+    pub tag: String,
+    pub namespace: Option<String>,
+    pub attributes: Vec<AttrSnapshot>,
+    pub styles: Vec<(String, String)>,
+    pub listeners: Vec<ListenerId>,
+    pub children: Vec<ChildSnapshot>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ChildSnapshot {
+    Text(String),
+    Node(NodeSnapshot),
+    Fragment(Vec<ChildSnapshot>),
+}
+
+impl<'a, M: 'static> From<&'a Node<M>> for NodeSnapshot {
+    fn from(node: &'a Node<M>) -> Self {
+        let mut attributes: Vec<AttrSnapshot> =
+            node.attributes.values().map(AttrSnapshot::from).collect();
+        attributes.sort_by(|a, b| a.key.cmp(&b.key));
+        let mut styles: Vec<(String, String)> = node.styles
+            .iter()
+            .map(|(property, value)| (property.to_string(), value.clone()))
+            .collect();
+        styles.sort();
+
+        NodeSnapshot {
+            tag: node.tag.to_owned(),
+            namespace: node.namespace.map(str::to_owned),
+            attributes: attributes,
+            styles: styles,
+            listeners: node.listeners.iter().map(|l| ListenerId::of(&**l)).collect(),
+            children: node.children.iter().map(ChildSnapshot::from).collect(),
+        }
+    }
+}
+
+impl<'a, M: 'static> From<&'a Child<M>> for ChildSnapshot {
+    /// Embedded components and lazy children that haven't been resolved are
+    /// rendered on the spot, like `to_html` does.
+    fn from(child: &'a Child<M>) -> Self {
+        match *child {
+            Child::Text(ref text) => ChildSnapshot::Text(text.clone()),
+            Child::Node(ref node) | Child::Keyed(_, ref node) => ChildSnapshot::Node(node.into()),
+            Child::Component(ref embedded) => {
+                ChildSnapshot::Node(match embedded.rendered() {
+                    Some(node) => node.into(),
+                    None => (&embedded.render_detached()).into(),
+                })
+            }
+            Child::Lazy(ref lazy) => {
+                ChildSnapshot::Node(match lazy.rendered() {
+                    Some(node) => node.into(),
+                    None => (&lazy.render()).into(),
+                })
+            }
+            Child::Fragment(ref fragment) => {
+                ChildSnapshot::Fragment(fragment.children.iter().map(Into::into).collect())
+            }
+        }
+    }
+}
+
+/// The owned counterpart of `diff::Operation`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum OwnedOperation {
+    ReplaceNode(NodeSnapshot),
+    ReplaceText(String),
+    ReplaceWithText(String),
+    ReplaceWithFragment(Vec<ChildSnapshot>),
+    RemoveAttribute(AttrSnapshot),
+    SetAttribute(AttrSnapshot),
+    SetStyle(String, String),
+    RemoveStyle(String),
+    RemoveLast(i32),
+    Append(Vec<ChildSnapshot>),
+    Insert(i32, NodeSnapshot),
+    Reorder(Vec<OwnedKeyedOp>),
+    AddListener(ListenerId),
+    RemoveListener(ListenerId),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum OwnedKeyedOp {
+    Remove(i32),
+    Move(i32, i32),
+    Insert(i32, NodeSnapshot),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OwnedPatch {
+    pub node: Path,
+    pub operation: OwnedOperation,
+}
+
+impl OwnedPatch {
+    /// Copy `patch`, or `None` if it replaces a listener, sets a ref or runs a
+    /// hook.
+    pub fn from_patch<'a, M: 'static + Debug>(patch: &Patch<'a, Path, M>) -> Option<Self> {
+        use self::OwnedOperation::*;
+        let operation = match patch.operation {
+            Operation::ReplaceNode(node) => ReplaceNode(node.into()),
+            Operation::ReplaceText(text) => ReplaceText(text.to_owned()),
+            Operation::ReplaceWithText(text) => ReplaceWithText(text.to_owned()),
+            Operation::ReplaceWithFragment(children) => {
+                ReplaceWithFragment(children.iter().map(Into::into).collect())
+            }
+            Operation::RemoveAttribute(attr) => RemoveAttribute(attr.into()),
+            Operation::SetAttribute(attr) => SetAttribute(attr.into()),
+            Operation::SetStyle(property, value) => SetStyle(property.to_owned(), value.to_owned()),
+            Operation::RemoveStyle(property) => RemoveStyle(property.to_owned()),
+            Operation::RemoveLast(count) => RemoveLast(count),
+            Operation::Append(children) => Append(children.iter().map(Into::into).collect()),
+            Operation::Insert(pos, node) => Insert(pos, node.into()),
+            Operation::Reorder(ref ops) => {
+                Reorder(ops.iter()
+                    .map(|op| match *op {
+                        KeyedOp::Remove(from) => OwnedKeyedOp::Remove(from),
+                        KeyedOp::Move(from, to) => OwnedKeyedOp::Move(from, to),
+                        KeyedOp::Insert(to, node) => OwnedKeyedOp::Insert(to, node.into()),
+                    })
+                    .collect())
+            }
+            Operation::AddListener(listener) => AddListener(ListenerId::of(listener)),
+            Operation::RemoveListener(listener) => RemoveListener(ListenerId::of(listener)),
+            Operation::ReplaceListener(..) |
+            Operation::SetRef(_) |
+            Operation::ClearRef(_) |
+            Operation::Updated(_) |
+            Operation::Removed(_) => return None,
+        };
+        Some(OwnedPatch {
+            node: patch.node.clone(),
+            operation: operation,
+        })
+    }
+}
+
+/// Copy every patch that has an owned form, in order.
+pub fn to_owned<'a, M: 'static + Debug>(patches: &[Patch<'a, Path, M>]) -> Vec<OwnedPatch> {
+    patches.iter().filter_map(OwnedPatch::from_patch).collect()
+}
+
+/// The path of the node in `node` that has the listener `id`.
+pub fn find_listener<M>(node: &Node<M>, id: &ListenerId) -> Option<Path> {
+    if node.listeners.iter().any(|listener| ListenerId::of(&**listener) == *id) {
+        return Some(Vec::new());
+    }
+    find_child_listener(&node.children, id)
+}
+
+fn find_child_listener<M>(children: &[Child<M>], id: &ListenerId) -> Option<Path> {
+    for (pos, child) in children.iter().enumerate() {
+        let found = match *child {
+            Child::Fragment(ref fragment) => find_child_listener(&fragment.children, id),
+            _ => child.node().and_then(|child| find_listener(child, id)),
+        };
+        if let Some(mut path) = found {
+            path.insert(0, pos);
+            return Some(path);
+        }
+    }
+    None
+}
@@ -0,0 +1,168 @@
+extern crate bincode;
+
+use serde_json;
+
+use super::apply::apply;
+use super::attribute::{class, AttrKind, Value};
+use super::diff::diff;
+use super::mock::MockBackend;
+use super::test_support::{ignore, Click};
+use super::owned::{to_owned, AttrSnapshot, ChildSnapshot, ListenerId, NodeSnapshot,
+                   OwnedKeyedOp, OwnedPatch};
+use super::owned::OwnedOperation::*;
+use super::root::create_tree;
+use super::{Node, NodeRef};
+
+fn text_node(tag: &str, text: &str) -> NodeSnapshot {
+    NodeSnapshot {
+        tag: tag.to_owned(),
+        namespace: None,
+        attributes: vec![],
+        styles: vec![],
+        listeners: vec![],
+        children: vec![ChildSnapshot::Text(text.to_owned())],
+    }
+}
+
+fn sample() -> (Node<&'static str>, Node<&'static str>) {
+    let old = vdom!(div { class = "a"; style = vec![("color", "red")] } [
+        ul [
+            keyed!("a", vdom!(li [ text!("a") ]))
+            keyed!("b", vdom!(li [ text!("b") ]))
+        ]
+        p [ text!("one") ]
+    ]);
+    let new = vdom!(div { style = vec![("margin", "0")] } [
+        ul [
+            keyed!("c", vdom!(li [ text!("c") ]))
+            keyed!("a", vdom!(li [ text!("a") ]))
+        ]
+        p [ text!("two") ]
+        button { on Click |_evt| "clicked" } [ text!("go") ]
+    ]);
+    (old, new)
+}
+
+#[test]
+fn test_owned_patches() {
+    let (old, new) = sample();
+    let owned = to_owned(&diff(&old, &new));
+
+    let mut root: Vec<_> = owned.iter().filter(|p| p.node.is_empty()).cloned().collect();
+    root.sort_by_key(|p| format!("{:?}", p.operation));
+    let button = NodeSnapshot {
+        listeners: vec![ListenerId::of(&*new.children[2].node().unwrap().listeners[0])],
+        ..text_node("button", "go")
+    };
+    assert_eq!(
+        root,
+        vec![
+            OwnedPatch {
+                node: vec![],
+                operation: Append(vec![ChildSnapshot::Node(button)]),
+            },
+            OwnedPatch {
+                node: vec![],
+                operation: RemoveAttribute(AttrSnapshot {
+                    kind: AttrKind::Property,
+                    key: "className".into(),
+                    value: Value::String("a".into()),
+                }),
+            },
+            OwnedPatch {
+                node: vec![],
+                operation: RemoveStyle("color".into()),
+            },
+            OwnedPatch {
+                node: vec![],
+                operation: SetStyle("margin".into(), "0".into()),
+            },
+        ]
+    );
+
+    assert!(owned.contains(&OwnedPatch {
+        node: vec![0],
+        operation: Reorder(vec![
+            OwnedKeyedOp::Insert(0, text_node("li", "c")),
+            OwnedKeyedOp::Move(0, 1),
+            OwnedKeyedOp::Remove(1),
+        ]),
+    }));
+    assert!(owned.contains(&OwnedPatch {
+        node: vec![1, 0],
+        operation: ReplaceText("two".into()),
+    }));
+}
+
+#[test]
+fn test_owned_patches_skip_refs_and_hooks() {
+    let old: Node<()> = vdom!(div [ p [ text!("a") ] ]);
+    let mut p = vdom!(p [ text!("b") ]);
+    p.set_ref(NodeRef::new());
+    p.on_update(|_: &()| ());
+    let new = vdom!(div [ (p) ]);
+
+    let patches = diff(&old, &new);
+    assert_eq!(patches.len(), 3);
+    assert_eq!(
+        to_owned(&patches),
+        vec![OwnedPatch {
+            node: vec![0, 0],
+            operation: ReplaceText("b".into()),
+        }]
+    );
+}
+
+#[test]
+fn test_listener_ids() {
+    let old: Node<&'static str> = vdom!(button { on Click |_evt| "old" });
+    let new: Node<&'static str> = vdom!(button { on Click |_evt| "new" });
+    let old_id = ListenerId::of(&*old.listeners[0]);
+    let new_id = ListenerId::of(&*new.listeners[0]);
+    assert_eq!(old_id.event, "click");
+    assert!(old_id != new_id);
+
+    // the id a snapshot gives a listener is the one later patches use
+    assert_eq!(NodeSnapshot::from(&old).listeners, vec![old_id.clone()]);
+
+    // a listener replacing another one keeps its id, there is nothing to send
+    let tree = create_tree(&MockBackend, &old, &ignore());
+    let patches = diff(&old, &new);
+    assert!(to_owned(&patches).is_empty());
+    apply(&MockBackend, &tree, patches, ignore());
+    assert_eq!(ListenerId::of(&*new.listeners[0]), old_id);
+
+    let gone: Node<&'static str> = vdom!(button);
+    assert_eq!(
+        to_owned(&diff(&new, &gone)),
+        vec![OwnedPatch {
+            node: vec![],
+            operation: RemoveListener(old_id),
+        }]
+    );
+}
+
+#[test]
+fn test_serialize_owned_patches() {
+    let (old, new) = sample();
+    let owned = to_owned(&diff(&old, &new));
+
+    let json = serde_json::to_string(&owned).unwrap();
+    assert_eq!(serde_json::from_str::<Vec<OwnedPatch>>(&json).unwrap(), owned);
+
+    let binary = bincode::serialize(&owned).unwrap();
+    assert_eq!(bincode::deserialize::<Vec<OwnedPatch>>(&binary).unwrap(), owned);
+    assert!(binary.len() < json.len());
+}
+
+#[test]
+fn test_owned_patches_outlive_trees() {
+    let owned = {
+        let (old, new) = sample();
+        to_owned(&diff(&old, &new))
+    };
+    assert!(owned.contains(&OwnedPatch {
+        node: vec![1, 0],
+        operation: ReplaceText("two".into()),
+    }));
+}
@@ -0,0 +1,605 @@
+//! Running a component on a server and rendering it somewhere else.
+//!
+//! A `Session` runs a component under a `Root` like any other, but on a
+//! `RemoteBackend`, which only keeps the skeleton of a document: enough to
+//! apply patches and dispatch events. What gets rendered goes out as
+//! `ServerMessage`s, a snapshot of the first render followed by the owned
+//! patches of every redraw. A client (`remote_client::Client`, or
+//! `client/remote.js` in a browser) builds the real document from them and
+//! answers with a `ClientMessage` whenever one of the listeners fires.
+//!
+//! Messages are plain serde types, sent as JSON by a `Transport`: a
+//! `WebSocket` for browsers, one message per text frame, or a
+//! `Connection` over any other byte stream. `serve` runs a session on a
+//! `NativeExecutor`, whose timers and futures it runs while it waits for the
+//! client.
+//!
+//! On the server, listeners receive the `RemoteEvent` itself as their raw
+//! event, so event types used with a session recover themselves from that:
+//!
+//! ```rust,ignore
+//! impl Event for Input {
+//!     fn event_type() -> &'static str { "input" }
+//!     fn from_raw(raw: &Any) -> Option<Self> {
+//!         let event = raw.downcast_ref::<RemoteEvent>()?;
+//!         match event.field("value") {
+//!             Some(&Value::String(ref value)) => Some(Input(value.clone())),
+//!             _ => None,
+//!         }
+//!     }
+//! }
+//! ```
+
+use std::any::Any;
+use std::boxed::FnBox;
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Debug;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::rc::{Rc, Weak};
+use std::time::Duration;
+
+use futures::Future;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json;
+
+use super::attribute::Value;
+use super::backend::{Backend, EventHandler, Executor, GlobalTarget, ListenerHandle};
+use super::component::Component;
+use super::native::NativeExecutor;
+use super::owned::{ListenerId, NodeSnapshot, OwnedPatch};
+use super::root::{mount, Redraw, Root};
+use super::websocket::{check_frame_size, invalid_data};
+
+pub use super::websocket::WebSocket;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ServerMessage {
+    /// Everything rendered so far, sent once when the session starts.
+    Render(NodeSnapshot),
+    /// The patches of one redraw, see `Root::on_patches`.
+    Patch(Vec<OwnedPatch>),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ClientMessage {
+    /// `event` fired on the node the client installed `listener` on.
+    Event {
+        listener: ListenerId,
+        event: RemoteEvent,
+    },
+    /// A patch didn't fit what the client rendered, see
+    /// `remote_client::Client::handle`. The session answers with a `Render`
+    /// of everything.
+    Resync,
+}
+
+/// An event that fired on the client, with whichever of its fields the
+/// client copied (e.g. `value` for an `input` event).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RemoteEvent {
+    pub event_type: String,
+    pub fields: BTreeMap<String, Value>,
+}
+
+impl RemoteEvent {
+    pub fn new<T: Into<String>>(event_type: T) -> Self {
+        RemoteEvent {
+            event_type: event_type.into(),
+            fields: BTreeMap::new(),
+        }
+    }
+
+    pub fn with<K: Into<String>, V: Into<Value>>(mut self, key: K, value: V) -> Self {
+        self.fields.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn field(&self, key: &str) -> Option<&Value> {
+        self.fields.get(key)
+    }
+}
+
+/// Sends and receives messages as JSON.
+pub trait Transport {
+    fn send<T: Serialize>(&mut self, message: &T) -> io::Result<()>;
+    /// The next message, or `None` once the other end has hung up.
+    fn receive<T: DeserializeOwned>(&mut self) -> io::Result<Option<T>>;
+}
+
+/// A stream that can be waited on for a limited time, so that `serve` can
+/// run timers and futures while the client is quiet.
+pub trait Wait {
+    /// Wait until there is something to read or `timeout` has passed.
+    /// Returns `false` on timeout.
+    fn wait(&mut self, timeout: Duration) -> io::Result<bool>;
+}
+
+impl Wait for TcpStream {
+    fn wait(&mut self, timeout: Duration) -> io::Result<bool> {
+        // a timeout of 0 would be refused
+        self.set_read_timeout(Some(cmp::max(timeout, Duration::from_millis(1))))?;
+        let ready = self.peek(&mut [0]);
+        self.set_read_timeout(None)?;
+        match ready {
+            Ok(_) => Ok(true),
+            Err(ref err) if is_timeout(err) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+fn is_timeout(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::TimedOut
+}
+
+/// How large a frame `Connection` and `WebSocket` accept unless told
+/// otherwise, in bytes.
+pub const MAX_FRAME_SIZE: usize = 16 << 20;
+
+/// Runs `C` on behalf of one client. Messages for the client pile up until
+/// they are taken with `take_messages`, unless they are sent right away, see
+/// `send_to` and `serve`.
+pub struct Session<Msg: 'static + Debug, C: Component<Msg>, E: Executor + 'static> {
+    root: Root<Msg, C, RemoteBackend<E>>,
+    outbox: Rc<RefCell<Outbox>>,
+    /// Holds on to the root element, which may be replaced.
+    _document: ShadowNode,
+}
+
+type Sink = Box<FnMut(&ServerMessage) -> io::Result<()>>;
+
+struct Outbox {
+    queue: VecDeque<ServerMessage>,
+    sink: Option<Sink>,
+    /// The first error `sink` returned, messages are dropped after it.
+    error: Option<io::Error>,
+}
+
+impl Outbox {
+    fn push(&mut self, message: ServerMessage) {
+        match self.sink {
+            Some(ref mut sink) => {
+                if self.error.is_none() {
+                    self.error = sink(&message).err();
+                }
+            }
+            None => self.queue.push_back(message),
+        }
+    }
+}
+
+impl<Msg, C, E> Session<Msg, C, E>
+where
+    Msg: 'static + Debug,
+    C: 'static + Component<Msg>,
+    E: Executor + 'static,
+{
+    /// Render `comp`, running its commands and subscriptions on `executor`.
+    /// The session redraws right after every message.
+    pub fn new(comp: C, executor: E) -> Self {
+        let document = ShadowNode::element("body");
+        let target = ShadowNode::element("div");
+        document.insert_at(0, &target);
+
+        let root = mount(RemoteBackend::new(executor), comp, target);
+        root.set_redraw(Redraw::Sync);
+        let outbox = Rc::new(RefCell::new(Outbox {
+            queue: VecDeque::new(),
+            sink: None,
+            error: None,
+        }));
+        outbox.borrow_mut().push(ServerMessage::Render(root.snapshot()));
+        {
+            let outbox = outbox.clone();
+            root.on_patches(move |patches| if !patches.is_empty() {
+                outbox.borrow_mut().push(ServerMessage::Patch(patches))
+            });
+        }
+
+        Session {
+            root: root,
+            outbox: outbox,
+            _document: document,
+        }
+    }
+
+    /// The root the component runs under, e.g. to send it messages.
+    pub fn root(&self) -> &Root<Msg, C, RemoteBackend<E>> {
+        &self.root
+    }
+
+    /// Handle a message from the client. Events for listeners that are no
+    /// longer rendered are dropped, the client can't have heard of the
+    /// removal yet.
+    pub fn handle(&self, message: ClientMessage) {
+        match message {
+            ClientMessage::Event { listener, event } => {
+                if let Some(node) = self.root.listener_node(&listener) {
+                    node.dispatch(&event);
+                }
+            }
+            ClientMessage::Resync => {
+                let render = ServerMessage::Render(self.root.snapshot());
+                self.outbox.borrow_mut().push(render);
+            }
+        }
+    }
+
+    pub fn take_messages(&self) -> Vec<ServerMessage> {
+        self.outbox.borrow_mut().queue.drain(..).collect()
+    }
+
+    /// Send the messages waiting to be taken to `transport`, and every
+    /// message after them as soon as it is rendered. Patches rendered for
+    /// timers, futures and subscriptions go out without waiting for the
+    /// client to say something. If sending fails, later messages are dropped
+    /// and `take_error` returns the error.
+    pub fn send_to<T: Transport + 'static>(&self, mut transport: T) -> io::Result<()> {
+        self.attach(Box::new(move |message| transport.send(message)))
+    }
+
+    /// The error sending a message to the transport given to `send_to`
+    /// failed with, if any.
+    pub fn take_error(&self) -> Option<io::Error> {
+        self.outbox.borrow_mut().error.take()
+    }
+
+    fn attach(&self, mut sink: Sink) -> io::Result<()> {
+        let mut outbox = self.outbox.borrow_mut();
+        for message in outbox.queue.drain(..) {
+            sink(&message)?;
+        }
+        outbox.sink = Some(sink);
+        Ok(())
+    }
+}
+
+impl<Msg, C> Session<Msg, C, NativeExecutor>
+where
+    Msg: 'static + Debug,
+    C: 'static + Component<Msg>,
+{
+    /// Send everything rendered to the client at the other end of
+    /// `transport` as soon as it is, like `send_to`, and handle what it sends
+    /// back until it hangs up. Timers and futures run while waiting for the
+    /// client, so their patches go out without it saying anything.
+    pub fn serve<T: Transport + Wait + 'static>(&self, transport: T) -> io::Result<()> {
+        let executor = self.root.backend().executor().clone();
+        // the sink only writes while a message is handled or the executor
+        // runs, never during `wait` or `receive`
+        let transport = Rc::new(RefCell::new(transport));
+        let sink = transport.clone();
+        let result = self.attach(Box::new(move |message| sink.borrow_mut().send(message)))
+            .and_then(|()| loop {
+                executor.run_pending();
+                if let Some(err) = self.take_error() {
+                    return Err(err);
+                }
+                if let Some(timeout) = executor.next_due() {
+                    if !transport.borrow_mut().wait(timeout)? {
+                        continue;
+                    }
+                }
+                let message = transport.borrow_mut().receive()?;
+                match message {
+                    Some(message) => self.handle(message),
+                    None => return Ok(()),
+                }
+                if let Some(err) = self.take_error() {
+                    return Err(err);
+                }
+            });
+        self.outbox.borrow_mut().sink = None;
+        result
+    }
+}
+
+/// Sends and receives messages over a byte stream, each as its length (four
+/// bytes, big-endian) followed by that many bytes of JSON.
+pub struct Connection<S> {
+    stream: S,
+    max_frame_size: usize,
+}
+
+impl<S: Read + Write> Connection<S> {
+    pub fn new(stream: S) -> Self {
+        Connection {
+            stream: stream,
+            max_frame_size: MAX_FRAME_SIZE,
+        }
+    }
+
+    /// Refuse frames longer than `bytes` with `ErrorKind::InvalidData`,
+    /// instead of `MAX_FRAME_SIZE`.
+    pub fn max_frame_size(mut self, bytes: usize) -> Self {
+        self.max_frame_size = bytes;
+        self
+    }
+}
+
+impl<S: Wait> Wait for Connection<S> {
+    fn wait(&mut self, timeout: Duration) -> io::Result<bool> {
+        self.stream.wait(timeout)
+    }
+}
+
+impl<S: Read + Write> Transport for Connection<S> {
+    fn send<T: Serialize>(&mut self, message: &T) -> io::Result<()> {
+        let json = serde_json::to_vec(message).map_err(invalid_data)?;
+        let len = json.len() as u32;
+        let header = [(len >> 24) as u8, (len >> 16) as u8, (len >> 8) as u8, len as u8];
+        self.stream.write_all(&header)?;
+        self.stream.write_all(&json)?;
+        self.stream.flush()
+    }
+
+    fn receive<T: DeserializeOwned>(&mut self) -> io::Result<Option<T>> {
+        let mut header = [0; 4];
+        match self.stream.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        }
+        let len = header.iter().fold(0, |len, &byte| len << 8 | byte as u64);
+        check_frame_size(len, self.max_frame_size)?;
+        let mut json = vec![0; len as usize];
+        self.stream.read_exact(&mut json)?;
+        serde_json::from_slice(&json).map(Some).map_err(invalid_data)
+    }
+}
+
+/// The backend a `Session` renders into. The document it keeps only has the
+/// structure, text and listeners of the real one on the client; attributes
+/// and styles are left out. Commands, subscriptions and animation frames run
+/// on the wrapped executor.
+pub struct RemoteBackend<E> {
+    executor: E,
+}
+
+impl<E: Executor> RemoteBackend<E> {
+    pub fn new(executor: E) -> Self {
+        RemoteBackend { executor: executor }
+    }
+
+    pub fn executor(&self) -> &E {
+        &self.executor
+    }
+}
+
+impl<E: Executor> Executor for RemoteBackend<E> {
+    fn set_timeout(&self, ms: u32, callback: Box<FnBox()>) {
+        self.executor.set_timeout(ms, callback)
+    }
+
+    fn set_interval(&self, ms: u32, callback: Rc<Fn()>) -> ListenerHandle {
+        self.executor.set_interval(ms, callback)
+    }
+
+    fn spawn(&self, future: Box<Future<Item = (), Error = ()>>) {
+        self.executor.spawn(future)
+    }
+
+    fn add_global_listener(
+        &self,
+        target: GlobalTarget,
+        event_type: &'static str,
+        handler: EventHandler,
+    ) -> ListenerHandle {
+        self.executor.add_global_listener(target, event_type, handler)
+    }
+}
+
+impl<E: Executor + 'static> Backend for RemoteBackend<E> {
+    type Node = ShadowNode;
+
+    fn create_element(&self, tag: &str) -> ShadowNode {
+        ShadowNode::element(tag)
+    }
+
+    fn create_element_ns(&self, _: &str, tag: &str) -> ShadowNode {
+        ShadowNode::element(tag)
+    }
+
+    fn create_text_node(&self, text: &str) -> ShadowNode {
+        ShadowNode::text(text)
+    }
+
+    fn set_text_content(&self, node: &ShadowNode, text: &str) {
+        for child in node.children() {
+            child.detach();
+        }
+        if node.tag().is_none() {
+            node.0.borrow_mut().text = text.to_owned();
+        } else {
+            node.insert_at(0, &ShadowNode::text(text));
+        }
+    }
+
+    fn tag_name(&self, node: &ShadowNode) -> Option<String> {
+        node.tag()
+    }
+
+    fn text_content(&self, node: &ShadowNode) -> Option<String> {
+        Some(node.text_content())
+    }
+
+    fn parent_node(&self, node: &ShadowNode) -> Option<ShadowNode> {
+        node.parent()
+    }
+
+    fn first_child(&self, node: &ShadowNode) -> Option<ShadowNode> {
+        node.children().first().cloned()
+    }
+
+    fn last_child(&self, node: &ShadowNode) -> Option<ShadowNode> {
+        node.children().last().cloned()
+    }
+
+    fn next_sibling(&self, node: &ShadowNode) -> Option<ShadowNode> {
+        node.parent().and_then(|parent| {
+            let pos = parent.position_of(node);
+            parent.children().get(pos + 1).cloned()
+        })
+    }
+
+    fn child_nodes(&self, node: &ShadowNode) -> Vec<ShadowNode> {
+        node.children()
+    }
+
+    fn append_child(&self, parent: &ShadowNode, child: &ShadowNode) {
+        child.detach();
+        let len = parent.0.borrow().children.len();
+        parent.insert_at(len, child);
+    }
+
+    fn insert_before(&self, parent: &ShadowNode, child: &ShadowNode, reference: &ShadowNode) {
+        child.detach();
+        let pos = parent.position_of(reference);
+        parent.insert_at(pos, child);
+    }
+
+    fn remove_child(&self, _: &ShadowNode, child: &ShadowNode) {
+        child.detach();
+    }
+
+    fn replace_child(&self, parent: &ShadowNode, new_child: &ShadowNode, old_child: &ShadowNode) {
+        new_child.detach();
+        let pos = parent.position_of(old_child);
+        old_child.detach();
+        parent.insert_at(pos, new_child);
+    }
+
+    fn set_attribute(&self, _: &ShadowNode, _: &str, _: &Value) {}
+    fn remove_attribute(&self, _: &ShadowNode, _: &str) {}
+    fn set_attribute_ns(&self, _: &ShadowNode, _: &str, _: &str, _: &Value) {}
+    fn remove_attribute_ns(&self, _: &ShadowNode, _: &str, _: &str) {}
+    fn set_property(&self, _: &ShadowNode, _: &str, _: &Value) {}
+    fn remove_property(&self, _: &ShadowNode, _: &str) {}
+    fn set_style(&self, _: &ShadowNode, _: &str, _: &str) {}
+    fn remove_style(&self, _: &ShadowNode, _: &str) {}
+
+    fn add_event_listener(
+        &self,
+        node: &ShadowNode,
+        event_type: &'static str,
+        handler: EventHandler,
+    ) -> ListenerHandle {
+        let id = {
+            let mut data = node.0.borrow_mut();
+            let id = data.next_listener;
+            data.next_listener += 1;
+            data.listeners.push((id, event_type, handler));
+            id
+        };
+        let node = Rc::downgrade(&node.0);
+        ListenerHandle::new(move || if let Some(node) = node.upgrade() {
+            node.borrow_mut().listeners.retain(|&(l, _, _)| l != id)
+        })
+    }
+
+    fn request_animation_frame(&self, callback: Box<FnBox()>) {
+        self.executor.set_timeout(0, callback)
+    }
+}
+
+/// A node of the document kept by a `RemoteBackend`.
+#[derive(Clone)]
+pub struct ShadowNode(Rc<RefCell<ShadowData>>);
+
+struct ShadowData {
+    /// `None` for text nodes.
+    tag: Option<String>,
+    text: String,
+    parent: Option<Weak<RefCell<ShadowData>>>,
+    children: Vec<ShadowNode>,
+    listeners: Vec<(usize, &'static str, EventHandler)>,
+    next_listener: usize,
+}
+
+impl ShadowNode {
+    fn new(tag: Option<String>, text: &str) -> Self {
+        ShadowNode(Rc::new(RefCell::new(ShadowData {
+            tag: tag,
+            text: text.to_owned(),
+            parent: None,
+            children: Vec::new(),
+            listeners: Vec::new(),
+            next_listener: 0,
+        })))
+    }
+
+    pub fn element(tag: &str) -> Self {
+        ShadowNode::new(Some(tag.to_owned()), "")
+    }
+
+    pub fn text(text: &str) -> Self {
+        ShadowNode::new(None, text)
+    }
+
+    pub fn tag(&self) -> Option<String> {
+        self.0.borrow().tag.clone()
+    }
+
+    pub fn text_content(&self) -> String {
+        let data = self.0.borrow();
+        match data.tag {
+            Some(_) => data.children.iter().map(ShadowNode::text_content).collect(),
+            None => data.text.clone(),
+        }
+    }
+
+    pub fn parent(&self) -> Option<ShadowNode> {
+        self.0.borrow().parent.as_ref().and_then(Weak::upgrade).map(ShadowNode)
+    }
+
+    pub fn children(&self) -> Vec<ShadowNode> {
+        self.0.borrow().children.clone()
+    }
+
+    /// Call every listener registered on this node for the type of `event`.
+    pub fn dispatch(&self, event: &RemoteEvent) {
+        let handlers: Vec<EventHandler> = self.0
+            .borrow()
+            .listeners
+            .iter()
+            .filter(|&&(_, event_type, _)| event_type == event.event_type)
+            .map(|&(_, _, ref handler)| handler.clone())
+            .collect();
+        for handler in handlers {
+            handler(event as &Any);
+        }
+    }
+
+    fn position_of(&self, child: &ShadowNode) -> usize {
+        self.0
+            .borrow()
+            .children
+            .iter()
+            .position(|c| c == child)
+            .expect("node is not a child of this parent")
+    }
+
+    fn detach(&self) {
+        if let Some(parent) = self.parent() {
+            let pos = parent.position_of(self);
+            parent.0.borrow_mut().children.remove(pos);
+        }
+        self.0.borrow_mut().parent = None;
+    }
+
+    fn insert_at(&self, pos: usize, child: &ShadowNode) {
+        child.detach();
+        child.0.borrow_mut().parent = Some(Rc::downgrade(&self.0));
+        self.0.borrow_mut().children.insert(pos, child.clone());
+    }
+}
+
+impl PartialEq for ShadowNode {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
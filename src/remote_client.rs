@@ -0,0 +1,381 @@
+//! The other end of a `remote::Session`: builds the document it renders
+//! through any `Backend` and reports events back.
+//!
+//! A patch that doesn't fit what the client rendered means the two are out
+//! of step. The client then asks the session for a fresh `Render` and
+//! ignores patches until it arrives.
+
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashSet};
+use std::rc::Rc;
+
+use super::apply::{arrange, replace, set_at};
+use super::attribute::Value;
+use super::backend::{Backend, ListenerHandle};
+use super::dom_tree::DomTree;
+use super::owned::{ChildSnapshot, ListenerId, NodeSnapshot, OwnedKeyedOp, OwnedOperation,
+                   OwnedPatch};
+use super::remote::{ClientMessage, RemoteEvent, ServerMessage};
+
+/// Copies the fields the server needs out of an event fired by the backend.
+pub type EventFields = Rc<Fn(&Any) -> BTreeMap<String, Value>>;
+
+pub struct Client<B: Backend> {
+    backend: B,
+    tree: DomTree<Rendered<B::Node>>,
+    send: Rc<Fn(ClientMessage)>,
+    fields: EventFields,
+    /// Set while waiting for the `Render` asked for after a patch failed.
+    resyncing: Cell<bool>,
+}
+
+/// Why a patch couldn't be applied. Each variant holds the path of the
+/// patch.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatchError {
+    /// There is no node at the path.
+    NoNode(Vec<usize>),
+    /// The node is, or is replaced with, a fragment, which needs a parent
+    /// element to put its children in, and it has none.
+    NoParent(Vec<usize>),
+    /// `RemoveLast` asked for more children than the node has.
+    NoChild(Vec<usize>),
+    /// A `Reorder` referred to a child that doesn't exist or left a position
+    /// in the new child list empty.
+    BadReorder(Vec<usize>),
+}
+
+/// A node created by the client, with the listeners installed on it.
+struct Rendered<N> {
+    node: N,
+    listeners: Rc<RefCell<Vec<(ListenerId, ListenerHandle)>>>,
+}
+
+impl<N: Clone> Rendered<N> {
+    fn new(node: N) -> Self {
+        Rendered {
+            node: node,
+            listeners: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+}
+
+impl<N: Clone> Clone for Rendered<N> {
+    fn clone(&self) -> Self {
+        Rendered {
+            node: self.node.clone(),
+            listeners: self.listeners.clone(),
+        }
+    }
+}
+
+impl<B: Backend> Client<B> {
+    /// Render into `target`, which the first `Render` message replaces.
+    /// `send` delivers messages to the session, `fields` picks what the
+    /// session sees of an event.
+    pub fn new<S, F>(backend: B, target: B::Node, send: S, fields: F) -> Self
+    where
+        S: Fn(ClientMessage) + 'static,
+        F: Fn(&Any) -> BTreeMap<String, Value> + 'static,
+    {
+        Client {
+            backend: backend,
+            tree: DomTree::leaf(Rendered::new(target)),
+            send: Rc::new(send),
+            fields: Rc::new(fields),
+            resyncing: Cell::new(false),
+        }
+    }
+
+    /// The node rendered for the root of the session's view.
+    pub fn root(&self) -> B::Node {
+        self.tree.node().node
+    }
+
+    /// Apply a message from the session. If a patch doesn't fit what was
+    /// rendered, the session is asked to render everything again and the
+    /// error is returned; patches are ignored until the new `Render`.
+    pub fn handle(&self, message: ServerMessage) -> Result<(), PatchError> {
+        match message {
+            ServerMessage::Render(snapshot) => {
+                let new_tree = self.create_tree(&snapshot);
+                replace(&self.backend, &self.root(), &new_tree.node().node);
+                self.tree.replace(new_tree);
+                self.resyncing.set(false);
+                Ok(())
+            }
+            ServerMessage::Patch(_) if self.resyncing.get() => Ok(()),
+            ServerMessage::Patch(patches) => self.apply(patches).map_err(|err| {
+                self.resyncing.set(true);
+                (self.send)(ClientMessage::Resync);
+                err
+            }),
+        }
+    }
+
+    /// Like `apply::apply`, every path is looked up before anything changes.
+    fn apply(&self, patches: Vec<OwnedPatch>) -> Result<(), PatchError> {
+        let mut targets = Vec::with_capacity(patches.len());
+        for patch in patches {
+            match self.tree.find_with_parent(&patch.node) {
+                Some((target, parent)) => targets.push((target, parent, patch)),
+                None => return Err(PatchError::NoNode(patch.node)),
+            }
+        }
+        for (tree, parent, patch) in targets {
+            let parent = parent.map(|parent| parent.node);
+            self.apply_patch(&tree, parent, &patch.node, patch.operation)?;
+        }
+        Ok(())
+    }
+
+    fn apply_patch(
+        &self,
+        tree: &DomTree<Rendered<B::Node>>,
+        parent: Option<B::Node>,
+        path: &[usize],
+        operation: OwnedOperation,
+    ) -> Result<(), PatchError> {
+        use super::owned::OwnedOperation::*;
+
+        let backend = &self.backend;
+        let rendered = tree.node();
+        let node = &rendered.node;
+        // where the children of the tree go, and what they go before if it is
+        // a fragment
+        let (container, anchor) = if tree.is_fragment() {
+            match parent {
+                Some(ref parent) => (parent.clone(), Some(node.clone())),
+                None => return Err(PatchError::NoParent(path.to_vec())),
+            }
+        } else {
+            (node.clone(), None)
+        };
+        match operation {
+            ReplaceNode(ref snapshot) => {
+                self.replace_tree(tree, parent, path, self.create_tree(snapshot))?
+            }
+            RemoveAttribute(ref attr) => attr.remove(backend, node),
+            SetAttribute(ref attr) => attr.set(backend, node),
+            SetStyle(ref property, ref value) => backend.set_style(node, property, value),
+            RemoveStyle(ref property) => backend.remove_style(node, property),
+            ReplaceText(ref text) => backend.set_text_content(node, text),
+            ReplaceWithText(ref text) => {
+                let new_tree = DomTree::leaf(Rendered::new(backend.create_text_node(text)));
+                self.replace_tree(tree, parent, path, new_tree)?;
+            }
+            ReplaceWithFragment(ref children) => {
+                self.replace_tree(tree, parent, path, self.create_fragment(children))?
+            }
+            RemoveLast(count) => {
+                for _ in 0..count {
+                    let last = match tree.pop() {
+                        Some(last) => last,
+                        None => return Err(PatchError::NoChild(path.to_vec())),
+                    };
+                    for rendered in last.nodes() {
+                        backend.remove_child(&container, &rendered.node);
+                    }
+                }
+            }
+            Append(ref children) => {
+                for child in children.iter() {
+                    let child_tree = self.create_child(child);
+                    self.place(&container, &child_tree, anchor.as_ref());
+                    tree.push(child_tree);
+                }
+            }
+            Insert(pos, ref snapshot) => {
+                let new_tree = self.create_tree(snapshot);
+                let mut children = tree.children();
+                let sibling = children.get(pos as usize).map(|sibling| sibling.first_node().node);
+                self.place(&container, &new_tree, sibling.as_ref().or(anchor.as_ref()));
+                let pos = ::std::cmp::min(pos as usize, children.len());
+                children.insert(pos, new_tree);
+                tree.set_children(children);
+            }
+            Reorder(ref ops) => self.reorder(tree, &container, anchor.as_ref(), path, ops)?,
+            AddListener(id) => self.listen(&rendered, id),
+            RemoveListener(ref id) => unlisten(&rendered, id),
+        }
+        Ok(())
+    }
+
+    /// Put the nodes of `tree` into `parent`, before `sibling` or at the end.
+    fn place(
+        &self,
+        parent: &B::Node,
+        tree: &DomTree<Rendered<B::Node>>,
+        sibling: Option<&B::Node>,
+    ) {
+        for rendered in tree.nodes() {
+            match sibling {
+                Some(sibling) => self.backend.insert_before(parent, &rendered.node, sibling),
+                None => self.backend.append_child(parent, &rendered.node),
+            }
+        }
+    }
+
+    /// Put `new_tree` where `tree` is, like `apply::encode_replace`.
+    fn replace_tree(
+        &self,
+        tree: &DomTree<Rendered<B::Node>>,
+        parent: Option<B::Node>,
+        path: &[usize],
+        new_tree: DomTree<Rendered<B::Node>>,
+    ) -> Result<(), PatchError> {
+        if !tree.is_fragment() && !new_tree.is_fragment() {
+            replace(&self.backend, &tree.node().node, &new_tree.node().node);
+        } else {
+            let parent = match parent {
+                Some(parent) => parent,
+                None => return Err(PatchError::NoParent(path.to_vec())),
+            };
+            self.place(&parent, &new_tree, Some(&tree.first_node().node));
+            for rendered in tree.nodes() {
+                self.backend.remove_child(&parent, &rendered.node);
+            }
+        }
+        tree.replace(new_tree);
+        Ok(())
+    }
+
+    fn reorder(
+        &self,
+        tree: &DomTree<Rendered<B::Node>>,
+        parent: &B::Node,
+        anchor: Option<&B::Node>,
+        path: &[usize],
+        ops: &[OwnedKeyedOp],
+    ) -> Result<(), PatchError> {
+        let old_children = tree.children();
+        let mut placed = Vec::with_capacity(old_children.len());
+        let bad_reorder = || PatchError::BadReorder(path.to_vec());
+        let old_child = |from: i32| old_children.get(from as usize).ok_or_else(&bad_reorder);
+        // every op puts at most one child in place, so positions past the
+        // number of ops can only leave holes
+        let check_to = |to: i32| {
+            if to >= 0 && (to as usize) < ops.len() { Ok(to) } else { Err(bad_reorder()) }
+        };
+
+        for op in ops.iter() {
+            match *op {
+                OwnedKeyedOp::Remove(from) => {
+                    self.backend.remove_child(parent, &old_child(from)?.node().node);
+                }
+                OwnedKeyedOp::Move(from, to) => {
+                    set_at(&mut placed, check_to(to)?, old_child(from)?.clone());
+                }
+                OwnedKeyedOp::Insert(to, ref snapshot) => {
+                    set_at(&mut placed, check_to(to)?, self.create_tree(snapshot));
+                }
+            }
+        }
+        let mut new_children = Vec::with_capacity(placed.len());
+        for child in placed {
+            new_children.push(child.ok_or_else(&bad_reorder)?);
+        }
+
+        match anchor {
+            Some(anchor) => {
+                for child in new_children.iter() {
+                    self.backend.insert_before(parent, &child.node().node, anchor);
+                }
+            }
+            None => {
+                arrange(&self.backend, parent, new_children.iter().map(|child| child.node().node))
+            }
+        }
+        tree.set_children(new_children);
+        Ok(())
+    }
+
+    fn create_tree(&self, snapshot: &NodeSnapshot) -> DomTree<Rendered<B::Node>> {
+        let backend = &self.backend;
+        let node = match snapshot.namespace {
+            Some(ref namespace) => backend.create_element_ns(namespace, &snapshot.tag),
+            None => backend.create_element(&snapshot.tag),
+        };
+        for attr in snapshot.attributes.iter() {
+            attr.set(backend, &node);
+        }
+        for &(ref property, ref value) in snapshot.styles.iter() {
+            backend.set_style(&node, property, value);
+        }
+
+        let mut children = Vec::with_capacity(snapshot.children.len());
+        for child in snapshot.children.iter() {
+            let tree = self.create_child(child);
+            self.place(&node, &tree, None);
+            children.push(tree);
+        }
+
+        let rendered = Rendered::new(node);
+        for id in snapshot.listeners.iter() {
+            self.listen(&rendered, id.clone());
+        }
+        DomTree::new(rendered, children)
+    }
+
+    fn create_child(&self, child: &ChildSnapshot) -> DomTree<Rendered<B::Node>> {
+        match *child {
+            ChildSnapshot::Text(ref text) => {
+                DomTree::leaf(Rendered::new(self.backend.create_text_node(text)))
+            }
+            ChildSnapshot::Node(ref snapshot) => self.create_tree(snapshot),
+            ChildSnapshot::Fragment(ref children) => self.create_fragment(children),
+        }
+    }
+
+    fn create_fragment(&self, children: &[ChildSnapshot]) -> DomTree<Rendered<B::Node>> {
+        let children = children.iter().map(|child| self.create_child(child)).collect();
+        DomTree::fragment(Rendered::new(self.backend.create_text_node("")), children)
+    }
+
+    fn listen(&self, rendered: &Rendered<B::Node>, id: ListenerId) {
+        let event_type = intern(&id.event);
+        let handler = {
+            let id = id.clone();
+            let send = self.send.clone();
+            let fields = self.fields.clone();
+            Rc::new(move |raw: &Any| {
+                send(ClientMessage::Event {
+                    listener: id.clone(),
+                    event: RemoteEvent {
+                        event_type: id.event.clone(),
+                        fields: fields(raw),
+                    },
+                })
+            })
+        };
+        let handle = self.backend.add_event_listener(&rendered.node, event_type, handler);
+        rendered.listeners.borrow_mut().push((id, handle));
+    }
+}
+
+fn unlisten<N>(rendered: &Rendered<N>, id: &ListenerId) {
+    let mut listeners = rendered.listeners.borrow_mut();
+    if let Some(pos) = listeners.iter().position(|&(ref l, _)| l == id) {
+        listeners.remove(pos).1.remove();
+    }
+}
+
+thread_local! {
+    static EVENT_TYPES: RefCell<HashSet<&'static str>> = RefCell::new(HashSet::new());
+}
+
+/// Backends take event types as `&'static str`. The session only uses the
+/// types its views listen for, so each one is leaked the first time it
+/// comes up.
+fn intern(event_type: &str) -> &'static str {
+    EVENT_TYPES.with(|types| {
+        let mut types = types.borrow_mut();
+        if let Some(&interned) = types.get(event_type) {
+            return interned;
+        }
+        let interned: &'static str = Box::leak(event_type.to_owned().into_boxed_str());
+        types.insert(interned);
+        interned
+    })
+}
@@ -0,0 +1,623 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io::{self, Cursor, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+
+use super::attribute::{value, Value};
+use super::backend::Backend;
+use super::cmd::Cmd;
+use super::component::Component;
+use super::events::Event;
+use super::diff::diff;
+use super::mock::{MockBackend, MockNode};
+use super::native::NativeExecutor;
+use super::test_support::{ignore, Click};
+use super::node::Fragment;
+use super::owned::{to_owned, OwnedKeyedOp, OwnedOperation, OwnedPatch};
+use super::remote::{ClientMessage, Connection, RemoteEvent, ServerMessage, Session, Transport,
+                   WebSocket};
+use super::remote_client::{Client, PatchError};
+use super::root::create_element;
+use super::Node;
+use serde_json;
+
+// An input as the session sees it, recovered from the `RemoteEvent`. The
+// shared `Click` works on both ends.
+
+struct Input(String);
+
+impl Event for Input {
+    fn event_type() -> &'static str {
+        "input"
+    }
+
+    fn from_raw(raw: &Any) -> Option<Self> {
+        match raw.downcast_ref::<RemoteEvent>().and_then(|event| event.field("value")) {
+            Some(&Value::String(ref value)) => Some(Input(value.clone())),
+            _ => None,
+        }
+    }
+}
+
+// An input as the mock document on the client dispatches it.
+
+#[derive(Clone)]
+struct ClientInput(&'static str);
+
+impl Event for ClientInput {
+    fn event_type() -> &'static str {
+        "input"
+    }
+
+    fn from_raw(raw: &Any) -> Option<Self> {
+        raw.downcast_ref::<Self>().cloned()
+    }
+}
+
+fn fields(raw: &Any) -> BTreeMap<String, Value> {
+    let mut fields = BTreeMap::new();
+    if let Some(&ClientInput(text)) = raw.downcast_ref::<ClientInput>() {
+        fields.insert("value".to_owned(), Value::from(text));
+    }
+    fields
+}
+
+#[derive(Debug)]
+enum Msg {
+    Edit(String),
+    Add,
+    Reverse,
+}
+
+#[derive(Default)]
+struct Todo {
+    draft: String,
+    items: Vec<String>,
+}
+
+impl Component<Msg> for Todo {
+    fn view(&self) -> Node<Msg> {
+        let mut list = vdom!(ul);
+        for item in self.items.iter() {
+            list.append_keyed(item.clone(), vdom!(li [ text!(item) ]));
+        }
+        vdom!(div [
+            input { value = self.draft.clone(); on Input |evt| Msg::Edit(evt.0) }
+            button { on Click |_evt| Msg::Add } [ text!("add") ]
+            button { on Click |_evt| Msg::Reverse } [ text!("reverse") ]
+            (list)
+        ])
+    }
+
+    fn update(&mut self, msg: Msg) -> Cmd<Msg> {
+        match msg {
+            Msg::Edit(draft) => self.draft = draft,
+            Msg::Add => {
+                let item = ::std::mem::replace(&mut self.draft, String::new());
+                self.items.push(item);
+            }
+            Msg::Reverse => self.items.reverse(),
+        }
+        Cmd::none()
+    }
+}
+
+type Outbox = Rc<RefCell<Vec<ClientMessage>>>;
+
+/// A client rendering into a mock document, queueing what it sends.
+fn client() -> (Client<MockBackend>, Outbox) {
+    let container = MockNode::element("body");
+    let target = MockNode::element("div");
+    MockBackend.append_child(&container, &target);
+    let outbox = Outbox::default();
+    let sent = outbox.clone();
+    let client = Client::new(
+        MockBackend,
+        target,
+        move |msg| sent.borrow_mut().push(msg),
+        fields,
+    );
+    (client, outbox)
+}
+
+/// Pass messages back and forth until both sides are done.
+fn exchange(session: &Session<Msg, Todo, MockBackend>, client: &Client<MockBackend>, outbox: &Outbox) {
+    loop {
+        let to_client = session.take_messages();
+        let to_session: Vec<_> = outbox.borrow_mut().drain(..).collect();
+        if to_client.is_empty() && to_session.is_empty() {
+            return;
+        }
+        for msg in to_client {
+            client.handle(msg).unwrap();
+        }
+        for msg in to_session {
+            session.handle(msg);
+        }
+    }
+}
+
+/// Have a client render `old`, then patch it to `new`.
+fn patch_client(old: &Node<Msg>, new: &Node<Msg>) -> MockNode {
+    let (client, _) = client();
+    client.handle(ServerMessage::Render(old.into())).unwrap();
+    client.handle(ServerMessage::Patch(to_owned(&diff(old, new)))).unwrap();
+    client.root()
+}
+
+fn items(root: &MockNode) -> Vec<String> {
+    root.children()[3].children().iter().map(MockNode::text_content).collect()
+}
+
+#[test]
+fn test_session_and_client() {
+    let session = Session::new(Todo::default(), MockBackend);
+    let (client, outbox) = client();
+    exchange(&session, &client, &outbox);
+
+    let root = client.root();
+    assert_eq!(root.tag(), Some("div".to_owned()));
+    assert_eq!(root.children()[3].tag(), Some("ul".to_owned()));
+
+    for item in &["milk", "eggs"] {
+        root.children()[0].dispatch(ClientInput(item));
+        exchange(&session, &client, &outbox);
+        assert_eq!(root.children()[0].property("value"), Some(Value::from(*item)));
+        root.children()[1].dispatch(Click);
+        exchange(&session, &client, &outbox);
+    }
+    assert_eq!(items(&root), vec!["milk", "eggs"]);
+    assert_eq!(root.children()[0].property("value"), Some(Value::from("")));
+
+    let milk = root.children()[3].children()[0].clone();
+    root.children()[2].dispatch(Click);
+    exchange(&session, &client, &outbox);
+    assert_eq!(items(&root), vec!["eggs", "milk"]);
+    assert_eq!(root.children()[3].children()[1], milk);
+
+    // the client only listens once per listener, even after they were replaced
+    assert_eq!(root.snapshot(), {
+        let (client, outbox) = self::client();
+        let session = Session::new(
+            Todo {
+                draft: String::new(),
+                items: vec!["eggs".into(), "milk".into()],
+            },
+            MockBackend,
+        );
+        exchange(&session, &client, &outbox);
+        client.root().snapshot()
+    });
+}
+
+#[test]
+fn test_client_applies_fragments() {
+    fn items(names: &[&'static str]) -> Fragment<Msg> {
+        names.iter().map(|name| vdom!(li [ text!(*name) ])).collect()
+    }
+    fn keyed(names: &[&'static str]) -> Fragment<Msg> {
+        let mut fragment = Fragment::new();
+        for name in names {
+            fragment.append_keyed(*name, vdom!(li [ text!(*name) ]));
+        }
+        fragment
+    }
+
+    let cases: Vec<(Node<Msg>, Node<Msg>)> = vec![
+        (
+            vdom!(ul [ fragment!(items(&["a", "b"])) li [ text!("c") ] ]),
+            vdom!(ul [ fragment!(items(&["a", "x", "y"])) li [ text!("c") ] ]),
+        ),
+        (
+            vdom!(ul [ fragment!(items(&["a", "b", "c"])) li [ text!("d") ] ]),
+            vdom!(ul [ fragment!(items(&["a"])) li [ text!("d") ] ]),
+        ),
+        (
+            vdom!(ul [ li [ text!("a") ] fragment!(keyed(&["b", "c", "d"])) li [ text!("e") ] ]),
+            vdom!(ul [ li [ text!("a") ] fragment!(keyed(&["d", "x", "b"])) li [ text!("e") ] ]),
+        ),
+        (
+            vdom!(ul [ text!("a") fragment!(items(&["b", "c"])) li [ text!("d") ] ]),
+            vdom!(ul [ fragment!(items(&["a"])) li [ text!("b") ] li [ text!("d") ] ]),
+        ),
+    ];
+    for (old, new) in cases {
+        assert_eq!(
+            patch_client(&old, &new).snapshot(),
+            create_element(&MockBackend, &new, &ignore()).snapshot()
+        );
+    }
+}
+
+#[test]
+fn test_events_for_removed_listeners_are_dropped() {
+    let session = Session::new(Todo::default(), MockBackend);
+    let (client, outbox) = client();
+    exchange(&session, &client, &outbox);
+
+    let root = client.root();
+    root.children()[1].dispatch(Click);
+    let clicks = outbox.borrow().clone();
+    exchange(&session, &client, &outbox);
+    assert_eq!(items(&root), vec![""]);
+
+    // the click listener was replaced by the redraw, and kept its id
+    for msg in clicks.iter().cloned() {
+        session.handle(msg);
+    }
+    exchange(&session, &client, &outbox);
+    assert_eq!(items(&root), vec!["", ""]);
+
+    // a listener that isn't rendered anymore
+    for msg in clicks {
+        let (mut listener, event) = match msg {
+            ClientMessage::Event { listener, event } => (listener, event),
+            msg => panic!("expected an event, got {:?}", msg),
+        };
+        listener.id = u64::max_value();
+        session.handle(ClientMessage::Event {
+            listener: listener,
+            event: event,
+        });
+    }
+    assert!(session.take_messages().is_empty());
+}
+
+#[test]
+fn test_client_resyncs_after_a_patch_that_does_not_fit() {
+    let session = Session::new(Todo::default(), MockBackend);
+    let (client, outbox) = client();
+    exchange(&session, &client, &outbox);
+
+    let patch = |node: Vec<usize>, operation| OwnedPatch {
+        node: node,
+        operation: operation,
+    };
+    let cases = vec![
+        (
+            patch(vec![3, 7], OwnedOperation::ReplaceText("x".into())),
+            PatchError::NoNode(vec![3, 7]),
+        ),
+        (patch(vec![3], OwnedOperation::RemoveLast(10)), PatchError::NoChild(vec![3])),
+        (
+            patch(vec![3], OwnedOperation::Reorder(vec![OwnedKeyedOp::Move(0, 1)])),
+            PatchError::BadReorder(vec![3]),
+        ),
+    ];
+    for (count, (patch, error)) in cases.into_iter().enumerate() {
+        assert_eq!(client.handle(ServerMessage::Patch(vec![patch])), Err(error));
+        assert_eq!(*outbox.borrow(), vec![ClientMessage::Resync]);
+
+        // the patches for this item reach the client before the session
+        // hears about the resync, and are ignored until the new render
+        session.root().send(Msg::Add);
+        exchange(&session, &client, &outbox);
+        assert_eq!(items(&client.root()).len(), count + 1);
+    }
+    assert_eq!(client.root().snapshot(), {
+        let (client, outbox) = self::client();
+        let session = Session::new(
+            Todo {
+                draft: String::new(),
+                items: vec!["".into(), "".into(), "".into()],
+            },
+            MockBackend,
+        );
+        exchange(&session, &client, &outbox);
+        client.root().snapshot()
+    });
+}
+
+#[test]
+fn test_serve_over_socket() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let session = Session::new(Todo::default(), NativeExecutor::new());
+        session.serve(Connection::new(stream))
+    });
+
+    let mut connection = Connection::new(TcpStream::connect(addr).unwrap());
+    let (client, outbox) = client();
+    let receive = |connection: &mut Connection<TcpStream>| {
+        let msg: ServerMessage = connection.receive().unwrap().unwrap();
+        client.handle(msg).unwrap();
+    };
+    receive(&mut connection);
+    let root = client.root();
+
+    let send = |connection: &mut Connection<TcpStream>| {
+        for msg in outbox.borrow_mut().drain(..) {
+            connection.send(&msg).unwrap();
+        }
+    };
+    root.children()[0].dispatch(ClientInput("milk"));
+    send(&mut connection);
+    receive(&mut connection);
+    root.children()[1].dispatch(Click);
+    send(&mut connection);
+    receive(&mut connection);
+    assert_eq!(items(&root), vec!["milk"]);
+
+    drop(connection);
+    server.join().unwrap().unwrap();
+}
+
+/// A frame as `remote.js` sends it, which browsers always mask.
+fn client_frame(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mask = [0x12, 0x34, 0x56, 0x78];
+    let mut frame = vec![if fin { 0x80 | opcode } else { opcode }];
+    if payload.len() < 126 {
+        frame.push(0x80 | payload.len() as u8);
+    } else {
+        frame.extend_from_slice(&[0x80 | 126, (payload.len() >> 8) as u8, payload.len() as u8]);
+    }
+    frame.extend_from_slice(&mask);
+    frame.extend(payload.iter().enumerate().map(|(pos, byte)| byte ^ mask[pos % 4]));
+    frame
+}
+
+/// The opcode and payload of the next frame the server sent.
+fn server_frame(stream: &mut TcpStream) -> (u8, Vec<u8>) {
+    let mut head = [0; 2];
+    stream.read_exact(&mut head).unwrap();
+    assert_eq!(head[0] & 0x80, 0x80, "frames are sent whole");
+    assert_eq!(head[1] & 0x80, 0, "frames from the server are not masked");
+    let len = match head[1] {
+        126 => {
+            let mut len = [0; 2];
+            stream.read_exact(&mut len).unwrap();
+            (len[0] as usize) << 8 | len[1] as usize
+        }
+        127 => {
+            let mut len = [0; 8];
+            stream.read_exact(&mut len).unwrap();
+            len.iter().fold(0, |len, &byte| len << 8 | byte as usize)
+        }
+        len => len as usize,
+    };
+    let mut payload = vec![0; len];
+    stream.read_exact(&mut payload).unwrap();
+    (head[0] & 0x0f, payload)
+}
+
+#[test]
+fn test_serve_over_websocket() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let session = Session::new(Todo::default(), NativeExecutor::new());
+        session.serve(WebSocket::accept(stream)?)
+    });
+
+    // the handshake, with the sample key from RFC 6455
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .write_all(
+            b"GET /session HTTP/1.1\r\n\
+              Host: localhost\r\n\
+              Upgrade: websocket\r\n\
+              Connection: Upgrade\r\n\
+              Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\
+              Sec-WebSocket-Version: 13\r\n\r\n",
+        )
+        .unwrap();
+    let mut response = Vec::new();
+    let mut byte = [0; 1];
+    while !response.ends_with(b"\r\n\r\n") {
+        stream.read_exact(&mut byte).unwrap();
+        response.push(byte[0]);
+    }
+    let response = String::from_utf8(response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 101 "));
+    assert!(response.contains("\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+
+    let (opcode, render) = server_frame(&mut stream);
+    assert_eq!(opcode, 0x1);
+    let render: serde_json::Value = serde_json::from_slice(&render).unwrap();
+    let input = &render["Render"]["children"][0]["Node"];
+    assert_eq!(input["tag"], "input");
+
+    // an input event the way remote.js sends it, split over two frames
+    let event = format!(
+        concat!(
+            r#"{{"Event":{{"listener":{},"#,
+            r#""event":{{"event_type":"input","fields":{{"value":{{"String":"milk"}}}}}}}}}}"#
+        ),
+        input["listeners"][0]
+    );
+    let (first, rest) = event.as_bytes().split_at(10);
+    stream.write_all(&client_frame(false, 0x1, first)).unwrap();
+    stream.write_all(&client_frame(true, 0x9, b"ping")).unwrap();
+    stream.write_all(&client_frame(true, 0x0, rest)).unwrap();
+    assert_eq!(server_frame(&mut stream), (0xa, b"ping".to_vec()));
+    let (opcode, patch) = server_frame(&mut stream);
+    assert_eq!(opcode, 0x1);
+    let patch: ServerMessage = serde_json::from_slice(&patch).unwrap();
+    match patch {
+        ServerMessage::Patch(ref patches) => assert!(!patches.is_empty()),
+        ref msg => panic!("expected a patch, got {:?}", msg),
+    }
+
+    // closing is answered with the same status
+    stream.write_all(&client_frame(true, 0x8, &[0x03, 0xe8])).unwrap();
+    assert_eq!(server_frame(&mut stream), (0x8, vec![0x03, 0xe8]));
+    server.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_websocket_handshake_is_required() {
+    let mut stream = Pipe::new(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n".to_vec());
+    let err = WebSocket::accept(&mut stream).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(stream.output.starts_with(b"HTTP/1.1 400 "));
+}
+
+/// An in-memory stream reading `input`, keeping what is written to it.
+struct Pipe {
+    input: Cursor<Vec<u8>>,
+    output: Vec<u8>,
+}
+
+impl Pipe {
+    fn new(input: Vec<u8>) -> Self {
+        Pipe {
+            input: Cursor::new(input),
+            output: Vec::new(),
+        }
+    }
+
+    /// A pipe that a WebSocket has been accepted on, which then reads `frames`.
+    fn websocket(frames: &[u8]) -> Self {
+        let mut input = b"GET / HTTP/1.1\r\n\
+                          Upgrade: websocket\r\n\
+                          Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n"
+            .to_vec();
+        input.extend_from_slice(frames);
+        Pipe::new(input)
+    }
+}
+
+impl Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_frames_over_the_limit_are_refused() {
+    // a length prefix of a gigabyte, with nothing after it
+    let mut connection = Connection::new(Pipe::new(vec![0x40, 0, 0, 0])).max_frame_size(1024);
+    let err = connection.receive::<ClientMessage>().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    let mut frame = vec![0x81, 0x80 | 127, 0, 0, 0x01, 0, 0, 0, 0, 0];
+    frame.extend_from_slice(&[0; 4]);
+    let mut websocket = WebSocket::accept(Pipe::websocket(&frame)).unwrap();
+    let err = websocket.receive::<ClientMessage>().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    // every frame is small enough, the message they add up to isn't
+    let mut frames = client_frame(false, 0x1, &[b' '; 10]);
+    frames.extend(client_frame(true, 0x0, &[b' '; 10]));
+    let mut websocket = WebSocket::accept(Pipe::websocket(&frames))
+        .unwrap()
+        .max_frame_size(16);
+    let err = websocket.receive::<ClientMessage>().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    // unmasked frames aren't from a browser
+    let mut websocket = WebSocket::accept(Pipe::websocket(&[0x81, 0x00])).unwrap();
+    let err = websocket.receive::<ClientMessage>().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[derive(Debug)]
+enum Timer {
+    Start,
+    Done,
+}
+
+#[derive(Default)]
+struct Countdown {
+    done: bool,
+}
+
+impl Component<Timer> for Countdown {
+    fn view(&self) -> Node<Timer> {
+        vdom!(p [ text!(if self.done { "done" } else { "waiting" }) ])
+    }
+
+    fn update(&mut self, msg: Timer) -> Cmd<Timer> {
+        match msg {
+            Timer::Start => return Cmd::delay(100, Timer::Done),
+            Timer::Done => self.done = true,
+        }
+        Cmd::none()
+    }
+}
+
+#[test]
+fn test_patches_are_sent_without_a_client_message() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client = thread::spawn(move || {
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut connection = Connection::new(stream);
+        let render: ServerMessage = connection.receive().unwrap().unwrap();
+        let patch: ServerMessage = connection.receive().unwrap().unwrap();
+        (render, patch)
+    });
+
+    let (stream, _) = listener.accept().unwrap();
+    let session = Session::new(Countdown::default(), MockBackend);
+    session.send_to(Connection::new(stream)).unwrap();
+    session.root().send(Timer::Start);
+    assert_eq!(MockBackend.advance_time(100), 1);
+
+    let (render, patch) = client.join().unwrap();
+    match render {
+        ServerMessage::Render(_) => {}
+        msg => panic!("expected the render, got {:?}", msg),
+    }
+    assert_eq!(
+        patch,
+        ServerMessage::Patch(vec![
+            OwnedPatch {
+                node: vec![0],
+                operation: OwnedOperation::ReplaceText("done".into()),
+            },
+        ])
+    );
+    assert!(session.take_error().is_none());
+}
+
+#[test]
+fn test_serve_runs_timers_while_waiting_for_the_client() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let session = Session::new(Countdown::default(), NativeExecutor::new());
+        session.root().send(Timer::Start);
+        session.serve(Connection::new(stream))
+    });
+
+    let stream = TcpStream::connect(addr).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut connection = Connection::new(stream);
+    match connection.receive().unwrap().unwrap() {
+        ServerMessage::Render(_) => {}
+        msg => panic!("expected the render, got {:?}", msg),
+    }
+    let patch: ServerMessage = connection.receive().unwrap().unwrap();
+    assert_eq!(
+        patch,
+        ServerMessage::Patch(vec![
+            OwnedPatch {
+                node: vec![0],
+                operation: OwnedOperation::ReplaceText("done".into()),
+            },
+        ])
+    );
+
+    drop(connection);
+    server.join().unwrap().unwrap();
+}
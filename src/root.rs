@@ -1,121 +1,533 @@
 use std::fmt::Debug;
 use std::rc::Rc;
-use std::cell::RefCell;
-use stdweb::web::{document, Element, Node as DNode, INode};
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::boxed::FnBox;
+use futures::Future;
+#[cfg(feature = "web")]
+use stdweb::web::{Element, INode};
 
-use super::node::Node as VNode;
+use super::backend::{Backend, EventHandler, Executor, GlobalTarget, ListenerHandle, NodeTarget};
+#[cfg(feature = "web")]
+use super::stdweb_backend::StdwebBackend;
+use super::node::{Child, Node as VNode};
+use super::diff::{Patch, Path};
+use super::dom_tree::DomTree;
+use super::owned::{self, ListenerId, NodeSnapshot, OwnedPatch};
+use super::hydrate::Mismatch;
+use super::embed::{Host, Raised};
+use super::sub::Subscriptions;
 use super::component::*;
 
-pub struct Root<Msg: 'static + Debug, C: Component<Msg>>(Rc<RootState<Msg, C>>);
+pub struct Root<Msg: 'static + Debug, C: Component<Msg>, B: Backend>(
+    Rc<RootState<Msg, C, B>>
+);
 
-impl<Msg, C> Root<Msg, C>
+impl<Msg, C, B> Root<Msg, C, B>
 where
     Msg: 'static + Debug,
     C: Component<Msg>,
+    B: Backend,
 {
+    /// Send a message to the root component and run the command its `update`
+    /// returns. Messages sent while an update is running (e.g. by a
+    /// `Cmd::Send`) are handled in order after it returns, followed by a
+    /// single redraw. Messages sent while redrawing (e.g. by a hook) are
+    /// handled once the redraw is done, and redrawn for after that.
     pub fn send(&self, msg: Msg) {
         send(self.0.clone(), msg)
     }
+
+    /// Choose when the DOM is updated after a message, `AnimationFrame` by
+    /// default.
+    pub fn set_redraw(&self, mode: Redraw) {
+        self.0.mode.set(mode);
+    }
+
+    /// Redraw now if there are changes waiting for the next animation frame.
+    pub fn flush(&self) {
+        flush(self.0.clone())
+    }
+
+    /// Call `observer` with the owned form of the patches applied by every
+    /// redraw from now on, e.g. to replay them on another document.
+    pub fn on_patches<F: Fn(Vec<OwnedPatch>) + 'static>(&self, observer: F) {
+        *self.0.patch_observer.borrow_mut() = Some(Box::new(observer));
+    }
+
+    /// A copy of what is currently rendered.
+    pub fn snapshot(&self) -> NodeSnapshot {
+        NodeSnapshot::from(&*self.0.vnode.borrow())
+    }
+
+    /// The backend the root renders with.
+    pub fn backend(&self) -> &B {
+        &self.0.backend
+    }
+
+    /// The DOM node the currently rendered listener `id` is installed on.
+    pub fn listener_node(&self, id: &ListenerId) -> Option<B::Node> {
+        owned::find_listener(&self.0.vnode.borrow(), id)
+            .and_then(|path| self.0.dom.find(&path))
+            .map(|tree| tree.node())
+    }
 }
 
-struct RootState<Msg: 'static, C: Component<Msg>> {
+/// When the DOM is updated after a message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Redraw {
+    /// Redraw once on the next animation frame, however many messages arrive
+    /// before then.
+    AnimationFrame,
+    /// Redraw right after every message.
+    Sync,
+}
+
+struct RootState<Msg: 'static, C: Component<Msg>, B: Backend> {
+    backend: B,
     comp: RefCell<C>,
-    dnode: RefCell<DNode>,
+    dom: DomTree<B::Node>,
     vnode: RefCell<VNode<Msg>>,
+    mode: Cell<Redraw>,
+    /// Messages sent while `update` or a redraw was running, handled once it
+    /// returns.
+    queue: RefCell<VecDeque<Msg>>,
+    updating: Cell<bool>,
+    redrawing: Cell<bool>,
+    subs: Subscriptions<Msg>,
+    /// The root component was updated since the last redraw.
+    dirty: Cell<bool>,
+    /// Embedded components that were updated since the last redraw.
+    dirty_components: RefCell<Vec<usize>>,
+    frame_requested: Cell<bool>,
+    patch_observer: RefCell<Option<Box<Fn(Vec<OwnedPatch>)>>>,
+}
+
+impl<Msg, C, B> RootState<Msg, C, B>
+where
+    Msg: 'static + Debug,
+    C: Component<Msg>,
+    B: Backend,
+{
+    fn new(backend: B, comp: C, target: B::Node) -> Self {
+        RootState {
+            backend: backend,
+            vnode: RefCell::new(VNode::new("div")),
+            dom: DomTree::leaf(target),
+            comp: RefCell::new(comp),
+            mode: Cell::new(Redraw::AnimationFrame),
+            queue: RefCell::new(VecDeque::new()),
+            updating: Cell::new(false),
+            redrawing: Cell::new(false),
+            subs: Subscriptions::new(),
+            dirty: Cell::new(false),
+            dirty_components: RefCell::new(Vec::new()),
+            frame_requested: Cell::new(false),
+            patch_observer: RefCell::new(None),
+        }
+    }
+}
+
+/// Render `comp` into `target` in the browser document.
+#[cfg(feature = "web")]
+pub fn render<M, C>(comp: C, target: Element) -> Root<M, C, StdwebBackend>
+where
+    M: 'static + Debug,
+    C: 'static + Component<M>,
+{
+    mount(StdwebBackend, comp, target.as_node().clone())
 }
 
-pub fn render<M, C>(comp: C, target: Element) -> Root<M, C>
+/// Render `comp` into `target` using an arbitrary DOM backend.
+pub fn mount<M, C, B>(backend: B, comp: C, target: B::Node) -> Root<M, C, B>
 where
     M: 'static + Debug,
     C: 'static + Component<M>,
+    B: Backend,
 {
-    let root = Rc::new(RootState {
-        vnode: RefCell::new(VNode::new("div")),
-        dnode: RefCell::new(target.as_node().clone()),
-        comp: RefCell::new(comp),
-    });
-    redraw(root.clone());
+    let root = Rc::new(RootState::new(backend, comp, target));
+    {
+        let _redrawing = Raised::new(&root.redrawing);
+        redraw(root.clone());
+    }
+    subscribe(&root);
+    update_queued(&root);
     Root(root)
 }
 
+/// Make the server-rendered markup in the browser document interactive.
+/// `target` is the element rendered for the root of `comp.view()`.
+#[cfg(feature = "web")]
+pub fn hydrate<M, C>(comp: C, target: Element) -> (Root<M, C, StdwebBackend>, Vec<Mismatch>)
+where
+    M: 'static + Debug,
+    C: 'static + Component<M>,
+{
+    hydrate_with(StdwebBackend, comp, target.as_node().clone())
+}
 
-fn create_receiver<Msg, C>(root: Rc<RootState<Msg, C>>) -> Callback<Msg>
+/// Adopt the existing DOM under `target` as the first render of `comp`
+/// instead of recreating it. Any differences between the two are patched and
+/// returned.
+pub fn hydrate_with<M, C, B>(backend: B, comp: C, target: B::Node) -> (Root<M, C, B>, Vec<Mismatch>)
+where
+    M: 'static + Debug,
+    C: 'static + Component<M>,
+    B: Backend,
+{
+    use super::hydrate::hydrate;
+    use super::embed::resolve;
+
+    let root = Rc::new(RootState::new(backend, comp, target));
+    let mut mismatches = Vec::new();
+    {
+        let _redrawing = Raised::new(&root.redrawing);
+        let mut vnode = root.comp.borrow().view();
+        let send = create_receiver(root.clone());
+        resolve(&mut vnode, None, &host(root.clone()), &send);
+        let dom = hydrate(&root.backend, &root.dom.node(), &vnode, &send, &mut mismatches);
+        root.dom.replace(dom);
+        *root.vnode.borrow_mut() = vnode;
+    }
+    subscribe(&root);
+    update_queued(&root);
+    (Root(root), mismatches)
+}
+
+/// Handle the messages sent during the first render (e.g. by a create hook),
+/// which only queued them.
+fn update_queued<Msg, C, B>(root: &Rc<RootState<Msg, C, B>>)
+where
+    Msg: 'static + Debug,
+    C: 'static + Component<Msg>,
+    B: Backend,
+{
+    if !root.queue.borrow().is_empty() {
+        update(root);
+        schedule(root.clone());
+    }
+}
+
+fn create_receiver<Msg, C, B>(root: Rc<RootState<Msg, C, B>>) -> Callback<Msg>
 where
     Msg: 'static + Debug,
     C: 'static + Component<Msg>,
+    B: Backend,
 {
     Rc::new(move |msg| send(root.clone(), msg))
 }
 
-fn send<Msg, C>(root: Rc<RootState<Msg, C>>, msg: Msg)
+fn send<Msg, C, B>(root: Rc<RootState<Msg, C, B>>, msg: Msg)
+where
+    Msg: 'static + Debug,
+    C: 'static + Component<Msg>,
+    B: Backend,
+{
+    root.queue.borrow_mut().push_back(msg);
+    if root.updating.get() || root.redrawing.get() {
+        // the update or redraw that's already running will get to it
+        return;
+    }
+
+    update(&root);
+    schedule(root);
+}
+
+/// Handle every queued message and mark the root for a redraw.
+fn update<Msg, C, B>(root: &Rc<RootState<Msg, C, B>>)
 where
     Msg: 'static + Debug,
     C: 'static + Component<Msg>,
+    B: Backend,
 {
-    let recur = create_receiver(root.clone());
     {
-        match root.comp.try_borrow_mut() {
-            Ok(mut comp) => {
-                println!("updating with message: {:?}", msg);
-                comp.update(msg, recur)
+        let _updating = Raised::new(&root.updating);
+        loop {
+            let next = root.queue.borrow_mut().pop_front();
+            match next {
+                Some(msg) => {
+                    let cmd = root.comp.borrow_mut().update(msg);
+                    cmd.run(&root.backend, create_receiver(root.clone()));
+                }
+                None => break,
             }
-            Err(_) => {
-                panic!(
-                    "attempted to update while already updating, this is probably due to synchronously calling recur"
-                )
+        }
+    }
+
+    subscribe(root);
+    root.dirty.set(true);
+}
+
+fn subscribe<Msg, C, B>(root: &Rc<RootState<Msg, C, B>>)
+where
+    Msg: 'static + Debug,
+    C: 'static + Component<Msg>,
+    B: Backend,
+{
+    let sub = root.comp.borrow().subscriptions();
+    root.subs.update(sub, &root.backend, create_receiver(root.clone()));
+}
+
+fn schedule<Msg, C, B>(root: Rc<RootState<Msg, C, B>>)
+where
+    Msg: 'static + Debug,
+    C: 'static + Component<Msg>,
+    B: Backend,
+{
+    match root.mode.get() {
+        Redraw::Sync => flush(root),
+        Redraw::AnimationFrame => {
+            if !root.frame_requested.get() {
+                root.frame_requested.set(true);
+                let frame_root = root.clone();
+                root.backend.request_animation_frame(
+                    Box::new(move || flush(frame_root)),
+                );
             }
         }
     }
-    redraw(root);
 }
 
-fn redraw<Msg, C>(root: Rc<RootState<Msg, C>>)
+fn flush<Msg, C, B>(root: Rc<RootState<Msg, C, B>>)
+where
+    Msg: 'static + Debug,
+    C: 'static + Component<Msg>,
+    B: Backend,
+{
+    if root.redrawing.get() {
+        // e.g. a hook sent a message, which is redrawn for once this redraw
+        // is done
+        return;
+    }
+    root.frame_requested.set(false);
+    {
+        let _redrawing = Raised::new(&root.redrawing);
+        let mut components: Vec<usize> = root.dirty_components.borrow_mut().drain(..).collect();
+        if root.dirty.get() {
+            // a full redraw re-renders updated components along the way,
+            // except inside the lazy subtrees it reuses
+            root.dirty.set(false);
+            redraw(root.clone());
+        }
+        components.sort();
+        components.dedup();
+        for id in components {
+            redraw_component(root.clone(), id);
+        }
+    }
+
+    if !root.queue.borrow().is_empty() {
+        update(&root);
+    }
+    if root.dirty.get() || !root.dirty_components.borrow().is_empty() {
+        schedule(root);
+    }
+}
+
+fn redraw<Msg, C, B>(root: Rc<RootState<Msg, C, B>>)
 where
     Msg: Debug + 'static,
     C: 'static + Component<Msg>,
+    B: Backend,
 {
     use super::diff::diff;
     use super::apply::apply;
+    use super::embed::resolve;
 
-    let next_vnode = root.comp.borrow().view();
+    let mut next_vnode = root.comp.borrow().view();
     let mut vnode = root.vnode.borrow_mut();
+    let send = create_receiver(root.clone());
+    resolve(&mut next_vnode, Some(&vnode), &host(root.clone()), &send);
     {
         let patches = diff(&vnode, &next_vnode);
-        println!("Patches: {:?}", patches);
-        let mut dnode = root.dnode.borrow_mut();
-        let send = create_receiver(root.clone());
-        apply(&mut dnode, patches, send);
+        observe(&root, &patches);
+        apply(&root.backend, &root.dom, patches, send);
     }
     *vnode = next_vnode;
 }
 
+fn observe<Msg, C, B>(root: &Rc<RootState<Msg, C, B>>, patches: &[Patch<Path, Msg>])
+where
+    Msg: Debug + 'static,
+    C: 'static + Component<Msg>,
+    B: Backend,
+{
+    if let Some(ref observer) = *root.patch_observer.borrow() {
+        observer(owned::to_owned(patches));
+    }
+}
+
+fn host<Msg, C, B>(root: Rc<RootState<Msg, C, B>>) -> Rc<Host>
+where
+    Msg: Debug + 'static,
+    C: 'static + Component<Msg>,
+    B: Backend,
+{
+    Rc::new(RootHost(root))
+}
+
+/// Lets embedded components ask for a redraw and run commands on the root's
+/// backend.
+struct RootHost<Msg: 'static, C: Component<Msg>, B: Backend>(Rc<RootState<Msg, C, B>>);
+
+impl<Msg, C, B> Executor for RootHost<Msg, C, B>
+where
+    Msg: Debug + 'static,
+    C: 'static + Component<Msg>,
+    B: Backend,
+{
+    fn set_timeout(&self, ms: u32, callback: Box<FnBox()>) {
+        self.0.backend.set_timeout(ms, callback)
+    }
+
+    fn set_interval(&self, ms: u32, callback: Rc<Fn()>) -> ListenerHandle {
+        self.0.backend.set_interval(ms, callback)
+    }
+
+    fn spawn(&self, future: Box<Future<Item = (), Error = ()>>) {
+        self.0.backend.spawn(future)
+    }
+
+    fn add_global_listener(
+        &self,
+        target: GlobalTarget,
+        event_type: &'static str,
+        handler: EventHandler,
+    ) -> ListenerHandle {
+        self.0.backend.add_global_listener(target, event_type, handler)
+    }
+}
+
+impl<Msg, C, B> Host for RootHost<Msg, C, B>
+where
+    Msg: Debug + 'static,
+    C: 'static + Component<Msg>,
+    B: Backend,
+{
+    fn redraw(&self, id: usize) {
+        self.0.dirty_components.borrow_mut().push(id);
+        schedule(self.0.clone());
+    }
+}
+
+/// Re-render only the embedded component instance `id`.
+fn redraw_component<Msg, C, B>(root: Rc<RootState<Msg, C, B>>, id: usize)
+where
+    Msg: Debug + 'static,
+    C: 'static + Component<Msg>,
+    B: Backend,
+{
+    use super::diff::diff_at;
+    use super::apply::apply;
+    use super::embed::{find, rendered_slot, resolve};
+
+    let mut vnode = root.vnode.borrow_mut();
+    match find(&vnode, id) {
+        // the component is gone, or hasn't been rendered yet
+        None => return,
+        Some((_, ref embedded)) if embedded.rendered().is_none() => return,
+        // already re-rendered by a full redraw
+        Some((_, ref embedded)) if !embedded.is_dirty() => return,
+        Some(_) => {}
+    }
+    if rendered_slot(&mut vnode, id).is_none() {
+        // what it rendered can't be replaced while it is shared, a full
+        // redraw stops sharing it
+        drop(vnode);
+        root.dirty.set(true);
+        root.dirty_components.borrow_mut().push(id);
+        schedule(root);
+        return;
+    }
+
+    let send = create_receiver(root.clone());
+    let (path, old, mut new) = {
+        let (path, embedded) = find(&vnode, id).unwrap();
+        (path, embedded.rendered_rc().unwrap(), embedded.render(&host(root.clone()), &send))
+    };
+    resolve(&mut new, Some(&old), &host(root.clone()), &send);
+    {
+        let patches = diff_at(&old, &new, path);
+        observe(&root, &patches);
+        apply(&root.backend, &root.dom, patches, send);
+    }
+    *rendered_slot(&mut vnode, id).unwrap() = Some(Rc::new(new));
+}
+
 /// Create a new DOM element for the given `super::VNode`
-pub fn create_element<Msg>(vnode: &VNode<Msg>, update: &Callback<Msg>) -> DNode
+pub fn create_element<B, Msg>(backend: &B, vnode: &VNode<Msg>, update: &Callback<Msg>) -> B::Node
 where
+    B: Backend,
     Msg: Sized + Debug + 'static,
 {
-    let dnode = document().create_element(vnode.tag);
+    create_tree(backend, vnode, update).node()
+}
+
+/// Like `create_element`, returning the DOM nodes created for every node in
+/// `vnode` as well.
+pub fn create_tree<B, Msg>(backend: &B, vnode: &VNode<Msg>, update: &Callback<Msg>) -> DomTree<B::Node>
+where
+    B: Backend,
+    Msg: Sized + Debug + 'static,
+{
+    let dnode = match vnode.namespace {
+        Some(namespace) => backend.create_element_ns(namespace, vnode.tag),
+        None => backend.create_element(vnode.tag),
+    };
 
     for (_, attr) in vnode.attributes.iter() {
-        attr.set(&dnode);
+        attr.set(backend, &dnode);
+    }
+    for (property, value) in vnode.styles.iter() {
+        backend.set_style(&dnode, property, value);
     }
 
+    let mut children = Vec::with_capacity(vnode.children.len());
     for child in vnode.children.iter() {
-        use super::Child::*;
-
-        match *child {
-            Text(ref string) => {
-                dnode.append_child(&document().create_text_node(string));
-            }
-            Node(ref child) => {
-                dnode.append_child(&create_element(child, update));
-            }
+        let tree = create_child(backend, child, update);
+        for node in tree.nodes() {
+            backend.append_child(&dnode, &node);
         }
+        children.push(tree);
     }
 
     for listener in vnode.listeners.iter() {
-        listener.install(&dnode, update.clone());
+        listener.install(&NodeTarget::new(backend, &dnode), update.clone());
+    }
+    if let Some(ref node_ref) = vnode.node_ref {
+        node_ref.set(dnode.clone());
+    }
+    if let Some(ref hook) = vnode.hooks.create {
+        hook.call(&dnode);
     }
 
-    dnode.as_node().clone()
+    DomTree::new(dnode, children)
+}
+
+/// Create the DOM for a child, without adding it to its parent.
+pub fn create_child<B, Msg>(backend: &B, child: &Child<Msg>, update: &Callback<Msg>) -> DomTree<B::Node>
+where
+    B: Backend,
+    Msg: Sized + Debug + 'static,
+{
+    match *child {
+        Child::Text(ref text) => DomTree::leaf(backend.create_text_node(text)),
+        Child::Node(ref node) | Child::Keyed(_, ref node) => create_tree(backend, node, update),
+        Child::Component(ref embedded) => {
+            let node = embedded.rendered().expect("embedded component was not resolved");
+            create_tree(backend, node, update)
+        }
+        Child::Lazy(ref lazy) => {
+            let node = lazy.rendered().expect("lazy child was not resolved");
+            create_tree(backend, node, update)
+        }
+        Child::Fragment(ref fragment) => {
+            let children = fragment
+                .children
+                .iter()
+                .map(|child| create_child(backend, child, update))
+                .collect();
+            DomTree::fragment(backend.create_text_node(""), children)
+        }
+    }
 }
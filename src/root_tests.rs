@@ -0,0 +1,222 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use super::cmd::Cmd;
+use super::component::Component;
+use super::mock::{MockBackend, MockNode};
+use super::test_support::{mount_sync, Click};
+use super::root::{mount, Redraw, Root};
+use super::backend::Backend;
+use super::Node;
+
+struct Counter {
+    count: i32,
+    views: Rc<Cell<usize>>,
+}
+
+impl Component<i32> for Counter {
+    fn view(&self) -> Node<i32> {
+        self.views.set(self.views.get() + 1);
+        vdom!(button { on Click |_evt| 1 } [ format!("{}", self.count) ])
+    }
+
+    fn update(&mut self, msg: i32) -> Cmd<i32> {
+        self.count += msg;
+        Cmd::none()
+    }
+}
+
+fn render() -> (Root<i32, Counter, MockBackend>, MockNode, Rc<Cell<usize>>) {
+    let views = Rc::new(Cell::new(0));
+    let (root, container) = mount_sync(Counter {
+        count: 0,
+        views: views.clone(),
+    });
+    root.set_redraw(Redraw::AnimationFrame);
+    (root, container, views)
+}
+
+#[test]
+fn test_redraw_batches_messages_until_animation_frame() {
+    let (root, container, views) = render();
+    let button = container.children()[0].clone();
+    assert_eq!(views.get(), 1);
+
+    button.dispatch(Click);
+    root.send(2);
+    button.dispatch(Click);
+    assert_eq!(button.text_content(), "0");
+    assert_eq!(views.get(), 1);
+
+    assert_eq!(MockBackend.run_animation_frames(), 1);
+    assert_eq!(button.text_content(), "4");
+    assert_eq!(views.get(), 2);
+
+    // nothing changed, so no frame is requested
+    assert_eq!(MockBackend.run_animation_frames(), 0);
+}
+
+#[test]
+fn test_redraw_flush() {
+    let (root, container, views) = render();
+    let button = container.children()[0].clone();
+
+    button.dispatch(Click);
+    root.flush();
+    assert_eq!(button.text_content(), "1");
+    assert_eq!(views.get(), 2);
+
+    // the frame requested before flushing has nothing left to do
+    MockBackend.run_animation_frames();
+    assert_eq!(views.get(), 2);
+}
+
+#[test]
+fn test_redraw_sync() {
+    let (root, container, views) = render();
+    let button = container.children()[0].clone();
+    root.set_redraw(Redraw::Sync);
+
+    button.dispatch(Click);
+    button.dispatch(Click);
+    assert_eq!(button.text_content(), "2");
+    assert_eq!(views.get(), 3);
+    assert_eq!(MockBackend.run_animation_frames(), 0);
+}
+
+/// Counts down to zero by sending itself messages with `Cmd::Send`.
+struct Countdown {
+    seen: Vec<i32>,
+    views: Rc<Cell<usize>>,
+}
+
+impl Component<i32> for Countdown {
+    fn view(&self) -> Node<i32> {
+        self.views.set(self.views.get() + 1);
+        vdom!(div [ format!("{:?}", self.seen) ])
+    }
+
+    fn update(&mut self, msg: i32) -> Cmd<i32> {
+        self.seen.push(msg);
+        if msg > 0 {
+            Cmd::batch(vec![Cmd::send(msg - 1), Cmd::send(-msg)])
+        } else {
+            Cmd::none()
+        }
+    }
+}
+
+#[test]
+fn test_send_from_update_is_queued() {
+    let views = Rc::new(Cell::new(0));
+    let (root, container) = mount_sync(Countdown {
+        seen: vec![],
+        views: views.clone(),
+    });
+
+    root.send(2);
+    assert_eq!(container.text_content(), "[2, 1, -2, 0, -1]");
+    assert_eq!(views.get(), 2);
+}
+
+/// Clicks each new item from its create hook, the way a hook might report
+/// something it measured.
+struct Items {
+    items: usize,
+    clicked: usize,
+    views: Rc<Cell<usize>>,
+}
+
+#[derive(Debug)]
+enum ItemsMsg {
+    Add,
+    Clicked,
+}
+
+impl Component<ItemsMsg> for Items {
+    fn view(&self) -> Node<ItemsMsg> {
+        self.views.set(self.views.get() + 1);
+        let mut list = vdom!(ul);
+        for _ in 0..self.items {
+            let mut item = vdom!(li { on Click |_evt| ItemsMsg::Clicked });
+            item.on_create(|dnode: &MockNode| dnode.dispatch(Click));
+            list.append_child(item);
+        }
+        vdom!(div [ p [ format!("{}", self.clicked) ] (list) ])
+    }
+
+    fn update(&mut self, msg: ItemsMsg) -> Cmd<ItemsMsg> {
+        match msg {
+            ItemsMsg::Add => self.items += 1,
+            ItemsMsg::Clicked => self.clicked += 1,
+        }
+        Cmd::none()
+    }
+}
+
+#[test]
+fn test_send_while_redrawing_sync() {
+    let views = Rc::new(Cell::new(0));
+    let (root, container) = mount_sync(Items {
+        items: 0,
+        clicked: 0,
+        views: views.clone(),
+    });
+    let views_before = views.get();
+
+    root.send(ItemsMsg::Add);
+    assert_eq!(container.text_content(), "1");
+    assert_eq!(views.get(), views_before + 2);
+
+    root.send(ItemsMsg::Add);
+    assert_eq!(container.text_content(), "2");
+    assert_eq!(views.get(), views_before + 4);
+}
+
+/// Sends a message from the create hook of what it renders, and notes how
+/// many updates had run by the time the message was sent.
+struct Eager {
+    updates: Rc<Cell<usize>>,
+    seen_by_hook: Rc<Cell<Option<usize>>>,
+}
+
+impl Component<()> for Eager {
+    fn view(&self) -> Node<()> {
+        let mut node = vdom!(p { on Click |_evt| () } [ format!("{}", self.updates.get()) ]);
+        let updates = self.updates.clone();
+        let seen_by_hook = self.seen_by_hook.clone();
+        node.on_create(move |dnode: &MockNode| if seen_by_hook.get().is_none() {
+            dnode.dispatch(Click);
+            seen_by_hook.set(Some(updates.get()));
+        });
+        node
+    }
+
+    fn update(&mut self, _: ()) -> Cmd<()> {
+        self.updates.set(self.updates.get() + 1);
+        Cmd::none()
+    }
+}
+
+#[test]
+fn test_send_during_mount_is_queued() {
+    let updates = Rc::new(Cell::new(0));
+    let seen_by_hook = Rc::new(Cell::new(None));
+    let container = MockNode::element("body");
+    let target = MockNode::element("div");
+    MockBackend.append_child(&container, &target);
+    let root = mount(
+        MockBackend,
+        Eager {
+            updates: updates.clone(),
+            seen_by_hook: seen_by_hook.clone(),
+        },
+        target,
+    );
+
+    // not handled in the middle of the first render, but right after it
+    assert_eq!(seen_by_hook.get(), Some(0));
+    assert_eq!(updates.get(), 1);
+    root.flush();
+    assert_eq!(container.text_content(), "1");
+}
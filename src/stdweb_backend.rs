@@ -0,0 +1,231 @@
+//! `Backend` implementation for the browser DOM, via stdweb.
+
+use std::any::Any;
+
+use std::boxed::FnBox;
+use std::rc::Rc;
+
+use futures::Future;
+use stdweb::web::{document, set_timeout, window, Node, INode};
+use stdweb::{self, PromiseFuture, Reference, UnsafeTypedArray};
+use stdweb::unstable::TryInto;
+
+use super::attribute::Value;
+use super::backend::{Backend, EventHandler, Executor, GlobalTarget, ListenerHandle};
+use super::batch::Batch;
+
+fn js_value(value: &Value) -> stdweb::Value {
+    match *value {
+        Value::Null => stdweb::Value::Null,
+        Value::Bool(b) => b.into(),
+        Value::Number(n) => n.into(),
+        Value::String(ref s) => s.as_str().into(),
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StdwebBackend;
+
+impl Backend for StdwebBackend {
+    type Node = Node;
+
+    fn create_element(&self, tag: &str) -> Node {
+        document().create_element(tag).as_node().clone()
+    }
+
+    fn create_element_ns(&self, namespace: &str, tag: &str) -> Node {
+        js!( return document.createElementNS(@{namespace}, @{tag}); ).try_into().unwrap()
+    }
+
+    fn create_text_node(&self, text: &str) -> Node {
+        document().create_text_node(text).as_node().clone()
+    }
+
+    fn set_text_content(&self, node: &Node, text: &str) {
+        node.set_text_content(text);
+    }
+
+    fn tag_name(&self, node: &Node) -> Option<String> {
+        js!(
+            var node = @{node};
+            return node.nodeType === 1 ? node.tagName.toLowerCase() : null;
+        ).into_string()
+    }
+
+    fn text_content(&self, node: &Node) -> Option<String> {
+        node.text_content()
+    }
+
+    fn is_comment(&self, node: &Node) -> bool {
+        js!( return @{node}.nodeType === 8; ).try_into().unwrap()
+    }
+
+    fn parent_node(&self, node: &Node) -> Option<Node> {
+        node.parent_node()
+    }
+
+    fn first_child(&self, node: &Node) -> Option<Node> {
+        node.first_child()
+    }
+
+    fn last_child(&self, node: &Node) -> Option<Node> {
+        node.last_child()
+    }
+
+    fn next_sibling(&self, node: &Node) -> Option<Node> {
+        node.next_sibling()
+    }
+
+    fn child_nodes(&self, node: &Node) -> Vec<Node> {
+        node.child_nodes().iter().collect()
+    }
+
+    fn append_child(&self, parent: &Node, child: &Node) {
+        parent.append_child(child);
+    }
+
+    fn insert_before(&self, parent: &Node, child: &Node, reference: &Node) {
+        parent.insert_before(child, reference);
+    }
+
+    fn remove_child(&self, parent: &Node, child: &Node) {
+        parent.remove_child(child).unwrap();
+    }
+
+    fn replace_child(&self, parent: &Node, new_child: &Node, old_child: &Node) {
+        parent.replace_child(new_child, old_child);
+    }
+
+    fn set_attribute(&self, node: &Node, key: &str, value: &Value) {
+        js!( @{node}.setAttribute(@{key}, @{js_value(value)}); );
+    }
+
+    fn remove_attribute(&self, node: &Node, key: &str) {
+        js!( @{node}.removeAttribute(@{key}); );
+    }
+
+    fn set_attribute_ns(&self, node: &Node, namespace: &str, key: &str, value: &Value) {
+        js!( @{node}.setAttributeNS(@{namespace}, @{key}, @{js_value(value)}); );
+    }
+
+    /// `removeAttributeNS` wants the local name, without the prefix.
+    fn remove_attribute_ns(&self, node: &Node, namespace: &str, key: &str) {
+        let local_name = key.splitn(2, ':').last().unwrap();
+        js!( @{node}.removeAttributeNS(@{namespace}, @{local_name}); );
+    }
+
+    fn set_property(&self, node: &Node, key: &str, value: &Value) {
+        js!( @{node}[@{key}] = @{js_value(value)}; );
+    }
+
+    fn remove_property(&self, node: &Node, key: &str) {
+        js!( delete @{node}[@{key}]; );
+    }
+
+    fn set_style(&self, node: &Node, property: &str, value: &str) {
+        js!( @{node}.style.setProperty(@{property}, @{value}); );
+    }
+
+    fn remove_style(&self, node: &Node, property: &str) {
+        js!( @{node}.style.removeProperty(@{property}); );
+    }
+
+    /// Handlers receive the raw event as a `stdweb::Reference`.
+    fn add_event_listener(
+        &self,
+        node: &Node,
+        event_type: &'static str,
+        handler: EventHandler,
+    ) -> ListenerHandle {
+        let callback = move |event: Reference| handler(&event as &Any);
+        let listener = js!(
+            var listener = {
+                node: @{node},
+                type: @{event_type},
+                callback: @{callback}
+            };
+            listener.node.addEventListener(listener.type, listener.callback);
+            return listener;
+        );
+        ListenerHandle::new(move || {
+            js!(
+                var listener = @{listener};
+                listener.node.removeEventListener(listener.type, listener.callback);
+                listener.callback.drop();
+            );
+        })
+    }
+
+    fn request_animation_frame(&self, callback: Box<FnBox()>) {
+        window().request_animation_frame(move |_| callback());
+    }
+
+    /// Hands the whole batch to `batch.js` in one call.
+    fn apply_batch(&self, batch: &Batch<Node>) -> Vec<Node> {
+        // A view of the buffer in place. It stays valid as long as nothing is
+        // allocated while JavaScript reads it, which `batch.js` never calls
+        // back into Rust for.
+        let bytes = unsafe { UnsafeTypedArray::new(batch.bytes()) };
+        APPLY_BATCH.with(|apply_batch| {
+            js!( return @{apply_batch}(@{bytes}, @{batch.inputs()}); ).try_into().unwrap()
+        })
+    }
+}
+
+thread_local! {
+    static APPLY_BATCH: Reference = js!( return (0, eval)(@{include_str!("batch.js")}); )
+        .try_into()
+        .unwrap();
+}
+
+impl Executor for StdwebBackend {
+    fn set_timeout(&self, ms: u32, callback: Box<FnBox()>) {
+        set_timeout(move || callback(), ms);
+    }
+
+    fn set_interval(&self, ms: u32, callback: Rc<Fn()>) -> ListenerHandle {
+        let callback = move || callback();
+        let interval = js!(
+            var interval = { callback: @{callback} };
+            interval.id = setInterval(interval.callback, @{ms});
+            return interval;
+        );
+        ListenerHandle::new(move || {
+            js!(
+                var interval = @{interval};
+                clearInterval(interval.id);
+                interval.callback.drop();
+            );
+        })
+    }
+
+    fn spawn(&self, future: Box<Future<Item = (), Error = ()>>) {
+        PromiseFuture::spawn(future);
+    }
+
+    /// Handlers receive the raw event as a `stdweb::Reference`.
+    fn add_global_listener(
+        &self,
+        target: GlobalTarget,
+        event_type: &'static str,
+        handler: EventHandler,
+    ) -> ListenerHandle {
+        let callback = move |event: Reference| handler(&event as &Any);
+        let listener = js!(
+            var listener = {
+                target: @{target == GlobalTarget::Window} ? window : document,
+                type: @{event_type},
+                callback: @{callback}
+            };
+            listener.target.addEventListener(listener.type, listener.callback);
+            return listener;
+        );
+        ListenerHandle::new(move || {
+            js!(
+                var listener = @{listener};
+                listener.target.removeEventListener(listener.type, listener.callback);
+                listener.callback.drop();
+            );
+        })
+    }
+}
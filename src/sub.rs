@@ -0,0 +1,204 @@
+//! Subscriptions to events from outside a component's own view.
+//!
+//! After every update, the component's `subscriptions` are compared with the
+//! ones it had before, by source (an event type on the window or document,
+//! or an interval). Listeners and timers are only installed for new sources
+//! and removed for sources that went away. A source that stays keeps its
+//! listener, which from then on uses the tagger from the latest `Sub`.
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::rc::Rc;
+
+use super::backend::{EventHandler, Executor, GlobalTarget, ListenerHandle};
+use super::component::Callback;
+use super::events::Event;
+
+pub enum Sub<Msg> {
+    /// Subscribe to nothing.
+    None,
+    /// Subscribe to everything in the list.
+    Batch(Vec<Sub<Msg>>),
+    /// Listen for an event type on the window or document, see `Sub::window`.
+    Listen(GlobalTarget, &'static str, Rc<Fn(&Any) -> Option<Msg>>),
+    /// Send a message every so many milliseconds.
+    Every(u32, Rc<Fn() -> Msg>),
+}
+
+impl<Msg: 'static> Sub<Msg> {
+    pub fn none() -> Self {
+        Sub::None
+    }
+
+    pub fn batch(subs: Vec<Sub<Msg>>) -> Self {
+        Sub::Batch(subs)
+    }
+
+    /// Send a message for every `E` dispatched on the window, e.g. `resize`
+    /// or `hashchange`.
+    pub fn window<E: Event, F: Fn(E) -> Msg + 'static>(tagger: F) -> Self {
+        Sub::listen(GlobalTarget::Window, tagger)
+    }
+
+    /// Send a message for every `E` that reaches the document, e.g. keyboard
+    /// shortcuts.
+    pub fn document<E: Event, F: Fn(E) -> Msg + 'static>(tagger: F) -> Self {
+        Sub::listen(GlobalTarget::Document, tagger)
+    }
+
+    fn listen<E: Event, F: Fn(E) -> Msg + 'static>(target: GlobalTarget, tagger: F) -> Self {
+        Sub::Listen(target, E::event_type(), Rc::new(move |raw| E::from_raw(raw).map(&tagger)))
+    }
+
+    pub fn every<F: Fn() -> Msg + 'static>(ms: u32, tagger: F) -> Self {
+        Sub::Every(ms, Rc::new(tagger))
+    }
+
+    /// Convert the messages this subscription produces, for passing
+    /// subscriptions from a nested component up to its parent.
+    pub fn map<To, F>(self, tagger: F) -> Sub<To>
+    where
+        To: 'static,
+        F: Fn(Msg) -> To + 'static,
+    {
+        self.map_rc(Rc::new(tagger))
+    }
+
+    fn map_rc<To: 'static>(self, tagger: Rc<Fn(Msg) -> To>) -> Sub<To> {
+        match self {
+            Sub::None => Sub::None,
+            Sub::Batch(subs) => {
+                Sub::Batch(subs.into_iter().map(|sub| sub.map_rc(tagger.clone())).collect())
+            }
+            Sub::Listen(target, event_type, inner) => {
+                Sub::Listen(target, event_type, Rc::new(move |raw| inner(raw).map(&*tagger)))
+            }
+            Sub::Every(ms, inner) => Sub::Every(ms, Rc::new(move || tagger(inner()))),
+        }
+    }
+
+    /// Flatten into one tagger per source. Repeats of the same source are
+    /// told apart by how many came before them.
+    fn flatten(self, out: &mut Vec<(Source, Rc<Fn(&Any) -> Option<Msg>>)>) {
+        let (source, tagger): (_, Rc<Fn(&Any) -> Option<Msg>>) = match self {
+            Sub::None => return,
+            Sub::Batch(subs) => {
+                for sub in subs {
+                    sub.flatten(out);
+                }
+                return;
+            }
+            Sub::Listen(target, event_type, tagger) => (Source::Listen(target, event_type, 0), tagger),
+            Sub::Every(ms, tagger) => (Source::Every(ms, 0), Rc::new(move |_| Some(tagger()))),
+        };
+        let repeats = out.iter().filter(|&&(ref other, _)| other.repeat(0) == source).count();
+        out.push((source.repeat(repeats), tagger));
+    }
+}
+
+impl<Msg> Default for Sub<Msg> {
+    fn default() -> Self {
+        Sub::None
+    }
+}
+
+impl<Msg> Debug for Sub<Msg> {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match *self {
+            Sub::None => write!(f, "None"),
+            Sub::Batch(ref subs) => write!(f, "Batch({:?})", subs),
+            Sub::Listen(target, event_type, _) => write!(f, "Listen({:?}, {:?})", target, event_type),
+            Sub::Every(ms, _) => write!(f, "Every({})", ms),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Source {
+    Listen(GlobalTarget, &'static str, usize),
+    Every(u32, usize),
+}
+
+impl Source {
+    fn repeat(self, n: usize) -> Source {
+        match self {
+            Source::Listen(target, event_type, _) => Source::Listen(target, event_type, n),
+            Source::Every(ms, _) => Source::Every(ms, n),
+        }
+    }
+}
+
+struct Active<Msg> {
+    tagger: Rc<RefCell<Rc<Fn(&Any) -> Option<Msg>>>>,
+    handle: ListenerHandle,
+}
+
+/// The subscriptions currently installed for one component.
+pub struct Subscriptions<Msg> {
+    active: RefCell<HashMap<Source, Active<Msg>>>,
+}
+
+impl<Msg: 'static> Subscriptions<Msg> {
+    pub fn new() -> Self {
+        Subscriptions { active: RefCell::new(HashMap::new()) }
+    }
+
+    /// Make the installed subscriptions match `sub`, sending their messages
+    /// to `send`.
+    pub fn update<E: Executor + ?Sized>(&self, sub: Sub<Msg>, executor: &E, send: Callback<Msg>) {
+        let mut wanted = Vec::new();
+        sub.flatten(&mut wanted);
+
+        let mut old = self.active.borrow_mut().drain().collect::<HashMap<_, _>>();
+        let mut active = HashMap::new();
+        for (source, tagger) in wanted {
+            if let Some(existing) = old.remove(&source) {
+                *existing.tagger.borrow_mut() = tagger;
+                active.insert(source, existing);
+                continue;
+            }
+
+            let tagger = Rc::new(RefCell::new(tagger));
+            let handler: EventHandler = {
+                let tagger = tagger.clone();
+                let send = send.clone();
+                Rc::new(move |raw| {
+                    // `send` may update the subscriptions, so don't hold on
+                    // to the borrow
+                    let tagger = tagger.borrow().clone();
+                    if let Some(msg) = tagger(raw) {
+                        send(msg);
+                    }
+                })
+            };
+            let handle = match source {
+                Source::Listen(target, event_type, _) => {
+                    executor.add_global_listener(target, event_type, handler)
+                }
+                Source::Every(ms, _) => executor.set_interval(ms, Rc::new(move || handler(&()))),
+            };
+            active.insert(
+                source,
+                Active {
+                    tagger: tagger,
+                    handle: handle,
+                },
+            );
+        }
+
+        for (_, gone) in old {
+            gone.handle.remove();
+        }
+        *self.active.borrow_mut() = active;
+    }
+}
+
+impl<Msg> Drop for Subscriptions<Msg> {
+    fn drop(&mut self) {
+        for (_, active) in self.active.borrow_mut().drain() {
+            active.handle.remove();
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use std::any::Any;
+
+use super::backend::GlobalTarget;
+use super::cmd::Cmd;
+use super::component::Component;
+use super::events::Event;
+use super::mock::MockBackend;
+use super::test_support::mount_sync;
+use super::sub::Sub;
+use super::Node;
+
+#[derive(Clone)]
+struct Resize;
+
+impl Event for Resize {
+    fn event_type() -> &'static str {
+        "resize"
+    }
+
+    fn from_raw(raw: &Any) -> Option<Self> {
+        raw.downcast_ref::<Self>().cloned()
+    }
+}
+
+#[derive(Clone)]
+struct KeyDown(char);
+
+impl Event for KeyDown {
+    fn event_type() -> &'static str {
+        "keydown"
+    }
+
+    fn from_raw(raw: &Any) -> Option<Self> {
+        raw.downcast_ref::<Self>().cloned()
+    }
+}
+
+#[derive(Debug)]
+enum Msg {
+    Tick,
+    Key(char),
+    /// Carries the number of ticks seen when the subscription was made.
+    Resized(i32),
+}
+
+#[derive(Default)]
+struct Clock {
+    ticks: i32,
+    log: Vec<String>,
+}
+
+impl Component<Msg> for Clock {
+    fn view(&self) -> Node<Msg> {
+        vdom!(div [ format!("{} {:?}", self.ticks, self.log) ])
+    }
+
+    fn update(&mut self, msg: Msg) -> Cmd<Msg> {
+        match msg {
+            Msg::Tick => self.ticks += 1,
+            Msg::Key(key) => self.log.push(format!("key {}", key)),
+            Msg::Resized(ticks) => self.log.push(format!("resized at {}", ticks)),
+        }
+        Cmd::none()
+    }
+
+    /// Ticks until a key is pressed, and stops listening for keys after two.
+    fn subscriptions(&self) -> Sub<Msg> {
+        let ticks = self.ticks;
+        let mut subs = vec![Sub::window(move |_: Resize| Msg::Resized(ticks))];
+        if self.log.is_empty() {
+            subs.push(Sub::every(1000, || Msg::Tick));
+        }
+        if self.log.len() < 2 {
+            subs.push(Sub::document(|evt: KeyDown| Msg::Key(evt.0)));
+        }
+        Sub::batch(subs)
+    }
+}
+
+#[test]
+fn test_subscriptions_follow_state() {
+    let (_root, container) = mount_sync(Clock::default());
+    assert_eq!(MockBackend.global_listeners(GlobalTarget::Window), vec!["resize"]);
+    assert_eq!(MockBackend.global_listeners(GlobalTarget::Document), vec!["keydown"]);
+
+    assert_eq!(MockBackend.advance_time(3500), 3);
+    assert_eq!(container.text_content(), "3 []");
+
+    MockBackend.dispatch_global(GlobalTarget::Document, KeyDown('a'));
+    assert_eq!(MockBackend.advance_time(3000), 0);
+    assert_eq!(container.text_content(), "3 [\"key a\"]");
+
+    MockBackend.dispatch_global(GlobalTarget::Document, KeyDown('b'));
+    assert_eq!(MockBackend.global_listeners(GlobalTarget::Document), Vec::<&str>::new());
+    MockBackend.dispatch_global(GlobalTarget::Document, KeyDown('c'));
+    assert_eq!(container.text_content(), "3 [\"key a\", \"key b\"]");
+}
+
+#[test]
+fn test_kept_subscription_uses_latest_tagger() {
+    let (root, container) = mount_sync(Clock::default());
+    MockBackend.dispatch_global(GlobalTarget::Window, Resize);
+    root.send(Msg::Tick);
+    MockBackend.dispatch_global(GlobalTarget::Window, Resize);
+
+    assert_eq!(MockBackend.global_listeners(GlobalTarget::Window), vec!["resize"]);
+    assert_eq!(container.text_content(), "1 [\"resized at 0\", \"resized at 1\"]");
+}
+
+/// Shows a `Clock` until told not to.
+struct Parent(bool);
+
+impl Component<()> for Parent {
+    fn view(&self) -> Node<()> {
+        if self.0 {
+            vdom!(div [ component!(Clock::default()) ])
+        } else {
+            vdom!(div)
+        }
+    }
+
+    fn update(&mut self, _: ()) -> Cmd<()> {
+        self.0 = false;
+        Cmd::none()
+    }
+}
+
+#[test]
+fn test_embedded_subscriptions() {
+    let (root, container) = mount_sync(Parent(true));
+    assert_eq!(MockBackend.advance_time(2000), 2);
+    assert_eq!(container.text_content(), "2 []");
+
+    root.send(());
+    assert_eq!(MockBackend.global_listeners(GlobalTarget::Window), Vec::<&str>::new());
+    assert_eq!(MockBackend.advance_time(2000), 0);
+}
@@ -0,0 +1,51 @@
+//! Fixtures shared by the tests.
+
+use std::any::Any;
+use std::fmt::Debug;
+use std::rc::Rc;
+
+use super::backend::Backend;
+use super::component::{Callback, Component};
+use super::events::Event;
+use super::mock::{MockBackend, MockNode};
+use super::remote::RemoteEvent;
+use super::root::{mount, Redraw, Root};
+
+/// A click, dispatched with `MockNode::dispatch(Click)`. Listeners in a
+/// `remote::Session` recover it from the `RemoteEvent` of a click.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Click;
+
+impl Event for Click {
+    fn event_type() -> &'static str {
+        "click"
+    }
+
+    fn from_raw(raw: &Any) -> Option<Self> {
+        match raw.downcast_ref::<RemoteEvent>() {
+            Some(event) if event.event_type == "click" => Some(Click),
+            Some(_) => None,
+            None => raw.downcast_ref::<Self>().cloned(),
+        }
+    }
+}
+
+/// A callback that drops every message.
+pub fn ignore<Msg: 'static>() -> Callback<Msg> {
+    Rc::new(|_| {})
+}
+
+/// Mount `comp` on a `MockBackend`, into a `div` inside a `body` element,
+/// redrawing right after every message. Returns the root and the `body`.
+pub fn mount_sync<Msg, C>(comp: C) -> (Root<Msg, C, MockBackend>, MockNode)
+where
+    Msg: 'static + Debug,
+    C: 'static + Component<Msg>,
+{
+    let container = MockNode::element("body");
+    let target = MockNode::element("div");
+    MockBackend.append_child(&container, &target);
+    let root = mount(MockBackend, comp, target);
+    root.set_redraw(Redraw::Sync);
+    (root, container)
+}
@@ -0,0 +1,312 @@
+//! The server end of a WebSocket (RFC 6455), which is what
+//! `client/remote.js` talks to a `remote::Session` over.
+//!
+//! Only as much of the protocol as a session needs is here: the opening
+//! handshake, one JSON message per text frame (possibly split into
+//! continuation frames), pings and closing. Extensions and subprotocols are
+//! never negotiated.
+
+use std::io::{self, Read, Write};
+use std::time::Duration;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json;
+
+use super::remote::{Transport, Wait, MAX_FRAME_SIZE};
+
+const CONTINUATION: u8 = 0x0;
+const TEXT: u8 = 0x1;
+const BINARY: u8 = 0x2;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xa;
+
+/// Appended to the client's key before hashing it for the handshake.
+const ACCEPT_GUID: &'static str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// How long the client's handshake request may be, in bytes.
+const MAX_REQUEST_SIZE: usize = 8192;
+
+/// A WebSocket connection over `stream`, as the server. Messages go out as
+/// text frames; frames from the client have to be masked, as browsers do.
+pub struct WebSocket<S> {
+    stream: S,
+    max_frame_size: usize,
+}
+
+impl<S: Read + Write> WebSocket<S> {
+    /// Answer the opening handshake a client sent over `stream`, e.g. a
+    /// `TcpStream` a browser connected to. Requests that don't ask for a
+    /// WebSocket are answered with `400 Bad Request` and an `InvalidData`
+    /// error.
+    pub fn accept(mut stream: S) -> io::Result<Self> {
+        let request = read_request(&mut stream)?;
+        let mut upgrade = false;
+        let mut key = None;
+        for line in request.lines().skip(1) {
+            let mut parts = line.splitn(2, ':');
+            let name = parts.next().unwrap_or("").trim();
+            let value = parts.next().unwrap_or("").trim();
+            if name.eq_ignore_ascii_case("upgrade") {
+                upgrade = value.eq_ignore_ascii_case("websocket");
+            } else if name.eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value.to_owned());
+            }
+        }
+
+        let key = match key {
+            Some(ref key) if upgrade => key,
+            _ => {
+                stream.write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n")?;
+                stream.flush()?;
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "not a WebSocket handshake",
+                ));
+            }
+        };
+        let accept = base64(&sha1(format!("{}{}", key, ACCEPT_GUID).as_bytes()));
+        write!(
+            stream,
+            "HTTP/1.1 101 Switching Protocols\r\n\
+             Upgrade: websocket\r\n\
+             Connection: Upgrade\r\n\
+             Sec-WebSocket-Accept: {}\r\n\r\n",
+            accept
+        )?;
+        stream.flush()?;
+
+        Ok(WebSocket {
+            stream: stream,
+            max_frame_size: MAX_FRAME_SIZE,
+        })
+    }
+
+    /// Refuse frames (and messages) longer than `bytes` with
+    /// `ErrorKind::InvalidData`, instead of `MAX_FRAME_SIZE`.
+    pub fn max_frame_size(mut self, bytes: usize) -> Self {
+        self.max_frame_size = bytes;
+        self
+    }
+
+    fn write_frame(&mut self, opcode: u8, payload: &[u8]) -> io::Result<()> {
+        let mut header = vec![0x80 | opcode];
+        let len = payload.len();
+        if len < 126 {
+            header.push(len as u8);
+        } else if len <= 0xffff {
+            header.push(126);
+            header.extend_from_slice(&[(len >> 8) as u8, len as u8]);
+        } else {
+            header.push(127);
+            for shift in (0..8).rev() {
+                header.push((len as u64 >> (shift * 8)) as u8);
+            }
+        }
+        self.stream.write_all(&header)?;
+        self.stream.write_all(payload)?;
+        self.stream.flush()
+    }
+
+    /// The next frame: whether it is the last of its message, its opcode and
+    /// its unmasked payload. `None` if the stream ends before it starts.
+    fn read_frame(&mut self) -> io::Result<Option<(bool, u8, Vec<u8>)>> {
+        let mut head = [0; 2];
+        match self.stream.read_exact(&mut head) {
+            Ok(()) => {}
+            Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        }
+        let fin = head[0] & 0x80 != 0;
+        let opcode = head[0] & 0x0f;
+        if head[1] & 0x80 == 0 {
+            return Err(protocol_error("frames from the client have to be masked"));
+        }
+        let len = match head[1] & 0x7f {
+            126 => {
+                let mut len = [0; 2];
+                self.stream.read_exact(&mut len)?;
+                (len[0] as u64) << 8 | len[1] as u64
+            }
+            127 => {
+                let mut len = [0; 8];
+                self.stream.read_exact(&mut len)?;
+                len.iter().fold(0, |len, &byte| len << 8 | byte as u64)
+            }
+            len => len as u64,
+        };
+        if opcode >= CLOSE && (!fin || len > 125) {
+            return Err(protocol_error("control frames can't be split or longer than 125 bytes"));
+        }
+        check_frame_size(len, self.max_frame_size)?;
+
+        let mut mask = [0; 4];
+        self.stream.read_exact(&mut mask)?;
+        let mut payload = vec![0; len as usize];
+        self.stream.read_exact(&mut payload)?;
+        for (pos, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[pos % 4];
+        }
+        Ok(Some((fin, opcode, payload)))
+    }
+}
+
+impl<S: Wait> Wait for WebSocket<S> {
+    fn wait(&mut self, timeout: Duration) -> io::Result<bool> {
+        self.stream.wait(timeout)
+    }
+}
+
+impl<S: Read + Write> Transport for WebSocket<S> {
+    fn send<T: Serialize>(&mut self, message: &T) -> io::Result<()> {
+        let json = serde_json::to_vec(message).map_err(invalid_data)?;
+        self.write_frame(TEXT, &json)
+    }
+
+    /// The next message, or `None` once the client has closed the connection
+    /// (which is answered) or the stream ends.
+    fn receive<T: DeserializeOwned>(&mut self) -> io::Result<Option<T>> {
+        // the frames of the message read so far
+        let mut message: Option<Vec<u8>> = None;
+        loop {
+            let (fin, opcode, payload) = match self.read_frame()? {
+                Some(frame) => frame,
+                None => return Ok(None),
+            };
+            match opcode {
+                TEXT | BINARY if message.is_none() => message = Some(payload),
+                CONTINUATION if message.is_some() => {
+                    let message = message.as_mut().unwrap();
+                    check_frame_size((message.len() + payload.len()) as u64, self.max_frame_size)?;
+                    message.extend_from_slice(&payload);
+                }
+                CLOSE => {
+                    // echo the status code, if there is one
+                    let status = if payload.len() >= 2 { &payload[..2] } else { &[] };
+                    self.write_frame(CLOSE, status)?;
+                    return Ok(None);
+                }
+                PING => {
+                    self.write_frame(PONG, &payload)?;
+                    continue;
+                }
+                PONG => continue,
+                _ => return Err(protocol_error("unexpected frame")),
+            }
+            if fin {
+                let json = message.take().unwrap();
+                return serde_json::from_slice(&json).map(Some).map_err(invalid_data);
+            }
+        }
+    }
+}
+
+/// The error for a frame of `len` bytes, if that is more than `max`. Lengths
+/// come from the other end, nothing is allocated for them before this.
+pub fn check_frame_size(len: u64, max: usize) -> io::Result<()> {
+    if len > max as u64 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame of {} bytes is larger than the limit of {}", len, max),
+        ));
+    }
+    Ok(())
+}
+
+pub fn invalid_data(err: serde_json::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+fn protocol_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// The request the handshake starts with, up to the blank line after its
+/// headers. It's read a byte at a time so that nothing after it is consumed.
+fn read_request<S: Read>(stream: &mut S) -> io::Result<String> {
+    let mut request = Vec::new();
+    let mut byte = [0; 1];
+    while !request.ends_with(b"\r\n\r\n") {
+        if request.len() >= MAX_REQUEST_SIZE {
+            return Err(protocol_error("handshake request too long"));
+        }
+        stream.read_exact(&mut byte)?;
+        request.push(byte[0]);
+    }
+    String::from_utf8(request).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    let bits = data.len() as u64 * 8;
+    for shift in (0..8).rev() {
+        message.push((bits >> (shift * 8)) as u8);
+    }
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = word.iter().fold(0, |w, &byte| w << 8 | byte as u32);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let (mut a, mut b, mut c, mut d, mut e) = (h[0], h[1], h[2], h[3], h[4]);
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i / 20 {
+                0 => ((b & c) | (!b & d), 0x5a827999),
+                1 => (b ^ c ^ d, 0x6ed9eba1),
+                2 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let temp = a.rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (state, word) in h.iter_mut().zip([a, b, c, d, e].iter()) {
+            *state = state.wrapping_add(*word);
+        }
+    }
+
+    let mut digest = [0; 20];
+    for (i, word) in h.iter().enumerate() {
+        for j in 0..4 {
+            digest[i * 4 + j] = (word >> (24 - j * 8)) as u8;
+        }
+    }
+    digest
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &'static [u8] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &byte)| {
+            n | (byte as u32) << (16 - i * 8)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - i * 6) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}